@@ -102,7 +102,7 @@ impl Alias {
         }
     }
 
-    /// Fetch all possible aliases
+    /// Fetch all possible aliases, in the order they were set
     pub fn get(&self, alias_ctx: &AliasRuleCtx) -> Vec<&String> {
         let alias_list = match self.aliases.get_vec(alias_ctx.alias_name) {
             Some(alias_list) => alias_list,
@@ -116,12 +116,39 @@ impl Alias {
             .collect::<Vec<_>>()
     }
 
-    /// For a given alias, check what it will evaluate to
-    pub fn get_subst(&self, alias_name: &String) -> Option<&String> {
-        match self.aliases.get(alias_name) {
-            Some(alias_info) => Some(&alias_info.subst),
-            None => None,
-        }
+    /// Find the alias to substitute for a name
+    ///
+    /// When several rules of the name match, the one set most recently is used, so that setting
+    /// an alias again overrides the earlier definitions.
+    pub fn resolve(&self, alias_ctx: &AliasRuleCtx) -> Option<&String> {
+        self.resolve_with(alias_ctx.alias_name, |alias_info| {
+            (alias_info.rule.0)(alias_ctx)
+        })
+    }
+
+    fn resolve_with(
+        &self,
+        alias_name: &str,
+        matches: impl Fn(&AliasInfo) -> bool,
+    ) -> Option<&String> {
+        self.aliases
+            .get_vec(alias_name)?
+            .iter()
+            .rev()
+            .find(|alias_info| matches(alias_info))
+            .map(|alias_info| &alias_info.subst)
+    }
+
+    /// For a given alias, check what it will evaluate to if its most recently set rule matches
+    pub fn get_subst(&self, alias_name: &str) -> Option<&String> {
+        self.resolve_with(alias_name, |_| true)
+    }
+
+    /// Iterate over the alias names, along with the substitution set most recently for each
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.aliases
+            .iter_all()
+            .filter_map(|(name, infos)| Some((name, &infos.last()?.subst)))
     }
 
     /// Update an alias of given name
@@ -156,3 +183,35 @@ impl<S: ToString> FromIterator<(S, S)> for Alias {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Alias, AliasInfo};
+
+    #[test]
+    fn rule_precedence() {
+        let mut alias = Alias::new();
+        alias.set("ll", AliasInfo::always("ls -l"));
+        alias.set("ll", AliasInfo::always("ls -la"));
+        alias.set("g", AliasInfo::always("git"));
+
+        // the rule set last wins when both match
+        assert_eq!(alias.resolve_with("ll", |_| true).unwrap(), "ls -la");
+        assert_eq!(alias.get_subst("ll").unwrap(), "ls -la");
+        // and earlier rules are used when it doesn't
+        let matches = |info: &AliasInfo| info.subst != "ls -la";
+        assert_eq!(alias.resolve_with("ll", matches).unwrap(), "ls -l");
+        assert_eq!(alias.resolve_with("ll", |_| false), None);
+        assert_eq!(alias.resolve_with("l", |_| true), None);
+
+        let mut names = alias.iter().collect::<Vec<_>>();
+        names.sort();
+        assert_eq!(
+            names,
+            [
+                (&"g".into(), &"git".into()),
+                (&"ll".into(), &"ls -la".into())
+            ]
+        );
+    }
+}
@@ -1,44 +1,45 @@
-use clap::{Parser, Subcommand};
+use shrs_lang::quote_word;
 
 use crate::{
     alias::AliasInfo,
-    prelude::{Alias, CmdOutput},
-    state::StateMut,
+    prelude::{Alias, CmdOutput, OutputWriter, StateMut},
 };
 
-#[derive(Parser)]
-struct Cli {
-    alias: String,
-}
-
-#[derive(Subcommand)]
-enum Commands {}
-
-pub fn alias_builtin(mut alias: StateMut<Alias>, args: &Vec<String>) -> anyhow::Result<CmdOutput> {
-    let cli = Cli::try_parse_from(args)?;
+pub fn alias_builtin(
+    mut alias: StateMut<Alias>,
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    // without arguments all the aliases are listed
+    if args.len() == 1 {
+        let mut aliases = alias
+            .iter()
+            .map(|(name, subst)| format!("alias {name}={}", quote_word(subst)))
+            .collect::<Vec<_>>();
+        aliases.sort();
+        for line in aliases {
+            out.println(line)?;
+        }
+        return Ok(CmdOutput::success());
+    }
 
-    let mut it = cli.alias.splitn(2, '=');
-    let alias_name = it.next().unwrap();
-    match it.next() {
-        Some(alias_def) => {
+    let mut status = 0;
+    for arg in &args[1..] {
+        match arg.split_once('=') {
             // if alias body is passed, set the alias
-            alias.set(alias_name, AliasInfo::always(alias_def));
-        },
-        None => {
+            Some((alias_name, alias_def)) => {
+                alias.set(alias_name, AliasInfo::always(alias_def));
+            },
             // if alias body is not passed, print the alias definition
-            /*
-            match ctx.alias.get(alias_name) {
-                Some(alias_def) => {
-                    println!("alias {}={}", alias_name, alias_def);
-                },
+            None => match alias.iter().find(|(name, _)| *name == arg) {
+                Some((_, subst)) => out.println(format!("alias {arg}={}", quote_word(subst)))?,
                 None => {
-                    eprintln!("{} not defined", alias_name);
+                    out.eprintln(format!("alias: {arg}: not found"))?;
+                    status = 1;
                 },
-            };
-            */
-            todo!()
-        },
+            },
+        }
     }
 
-    Ok(CmdOutput::success())
+    Ok(CmdOutput::from_status(status))
 }
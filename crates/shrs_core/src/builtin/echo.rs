@@ -0,0 +1,161 @@
+use crate::prelude::{CmdOutput, OutputWriter, StateMut};
+
+pub fn echo_builtin(
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let mut newline = true;
+    let mut escapes = false;
+    let mut words = &args[1..];
+    // options are only recognized as long as every character of the argument is a valid flag
+    while let Some((arg, rest)) = words.split_first() {
+        let flags = match arg.strip_prefix('-') {
+            Some(flags) if !flags.is_empty() && flags.chars().all(|c| "neE".contains(c)) => flags,
+            _ => break,
+        };
+        for flag in flags.chars() {
+            match flag {
+                'n' => newline = false,
+                'e' => escapes = true,
+                _ => escapes = false,
+            }
+        }
+        words = rest;
+    }
+
+    let words = words.join(" ");
+    let mut line = match escapes {
+        true => {
+            let (expanded, stop) = expand_escapes(&words, true);
+            // `\c` suppresses all further output, including the newline
+            newline &= !stop;
+            expanded
+        },
+        false => words.into_bytes(),
+    };
+    if newline {
+        line.push(b'\n');
+    }
+    out.print_bytes(&line)?;
+
+    Ok(CmdOutput::success())
+}
+
+/// Expand the backslash escapes in a string, returning the result and whether a `\c` ended it
+///
+/// The result is made of bytes since escapes like `\xff` can produce ones that aren't valid
+/// UTF-8.
+///
+/// `echo -e` and `printf %b` write octal escapes as `\0nnn` and stop at `\c`, while printf
+/// format strings write them as `\nnn` and don't treat `\c` specially.
+pub(super) fn expand_escapes(s: &str, echo_style: bool) -> (Vec<u8>, bool) {
+    let mut bytes = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            push_char(&mut bytes, c);
+            continue;
+        }
+        let Some(escape) = chars.next() else {
+            bytes.push(b'\\');
+            break;
+        };
+        match escape {
+            'a' => bytes.push(0x07),
+            'b' => bytes.push(0x08),
+            'e' | 'E' => bytes.push(0x1b),
+            'f' => bytes.push(0x0c),
+            'n' => bytes.push(b'\n'),
+            'r' => bytes.push(b'\r'),
+            't' => bytes.push(b'\t'),
+            'v' => bytes.push(0x0b),
+            '\\' => bytes.push(b'\\'),
+            'c' if echo_style => return (bytes, true),
+            '0'..='7' if !echo_style || escape == '0' => {
+                let mut value = match echo_style {
+                    true => 0,
+                    false => escape.to_digit(8).unwrap(),
+                };
+                for _ in 0..3 - usize::from(!echo_style) {
+                    match chars.peek().and_then(|c| c.to_digit(8)) {
+                        Some(digit) => value = value * 8 + digit,
+                        None => break,
+                    }
+                    chars.next();
+                }
+                bytes.push(value as u8);
+            },
+            'x' | 'u' | 'U' => {
+                let max_digits = match escape {
+                    'x' => 2,
+                    'u' => 4,
+                    _ => 8,
+                };
+                let mut value = 0;
+                let mut digits = 0;
+                while digits < max_digits {
+                    match chars.peek().and_then(|c| c.to_digit(16)) {
+                        Some(digit) => value = value * 16 + digit,
+                        None => break,
+                    }
+                    chars.next();
+                    digits += 1;
+                }
+                match (digits, escape) {
+                    (0, _) => {
+                        bytes.push(b'\\');
+                        push_char(&mut bytes, escape);
+                    },
+                    (_, 'x') => bytes.push(value as u8),
+                    _ => push_char(&mut bytes, char::from_u32(value).unwrap_or('\u{fffd}')),
+                }
+            },
+            // unknown escapes are left as they are
+            _ => {
+                bytes.push(b'\\');
+                push_char(&mut bytes, escape);
+            },
+        }
+    }
+    (bytes, false)
+}
+
+fn push_char(bytes: &mut Vec<u8>, c: char) {
+    bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand_escapes;
+
+    #[test]
+    fn escapes() {
+        assert_eq!(
+            expand_escapes(r"a\tb\n", false),
+            (b"a\tb\n".to_vec(), false)
+        );
+        assert_eq!(expand_escapes(r"\\\e\a", false).0, b"\\\x1b\x07");
+        assert_eq!(expand_escapes(r"\x41\x4a4\xff\xg", false).0, b"AJ4\xff\\xg");
+        assert_eq!(
+            expand_escapes(r"é\U0001F600\u", false).0,
+            "é😀\\u".as_bytes()
+        );
+        assert_eq!(expand_escapes(r"\q\", true).0, br"\q\");
+    }
+
+    #[test]
+    fn octal_escapes() {
+        // echo style octal escapes need a leading zero and can have three more digits
+        assert_eq!(expand_escapes(r"\0101\101", true).0, br"A\101");
+        assert_eq!(expand_escapes(r"\0377\0", true).0, [0xff, 0]);
+        // format strings have at most three digits in total
+        assert_eq!(expand_escapes(r"\101\0101", false).0, b"A\x081");
+        assert_eq!(expand_escapes(r"\377\08", false).0, [0xff, 0, b'8']);
+    }
+
+    #[test]
+    fn stop_output() {
+        assert_eq!(expand_escapes(r"a\cb\n", true), (b"a".to_vec(), true));
+        assert_eq!(expand_escapes(r"a\cb", false), (br"a\cb".to_vec(), false));
+    }
+}
@@ -1,5 +1,17 @@
-use crate::prelude::CmdOutput;
+use anyhow::anyhow;
 
-pub fn exit_builtin(_args: &Vec<String>) -> anyhow::Result<CmdOutput> {
-    std::process::exit(0)
+use crate::prelude::{CmdOutput, Runtime, State};
+
+pub fn exit_builtin(rt: State<Runtime>, args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+    // without an argument the shell exits with the status of the last command
+    let status = match args.get(1) {
+        Some(status) => {
+            status
+                .parse::<i32>()
+                .map_err(|_| anyhow!("exit: {status}: numeric argument required"))?
+                & 0xff
+        },
+        None => rt.exit_status,
+    };
+    std::process::exit(status)
 }
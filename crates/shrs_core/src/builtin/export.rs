@@ -42,11 +42,22 @@ pub fn export_builtin(
     }
 
     for var in cli.vars {
-        let mut it = var.splitn(2, '=');
-        let var = it.next().unwrap();
-        let val = it.next().unwrap_or_default();
+        // exporting a shell variable without a value keeps its current value
+        let (var, val) = match var.split_once('=') {
+            Some((var, val)) => (var.to_string(), val.to_string()),
+            None => {
+                let val = rt
+                    .vars
+                    .get(&var)
+                    .or_else(|| rt.env.get(&var).ok())
+                    .cloned()
+                    .unwrap_or_default();
+                (var, val)
+            },
+        };
 
-        rt.env.set(var, val)?;
+        rt.vars.remove(&var);
+        rt.env.set(&var, &val)?;
     }
 
     Ok(CmdOutput::success())
@@ -8,14 +8,21 @@
 mod alias;
 mod cd;
 mod debug;
+mod echo;
 mod exit;
 mod export;
 mod help;
 mod history;
 mod jobs;
+mod printf;
+mod read;
+mod set;
 mod source;
+mod test;
+mod trap;
 mod r#type;
 mod unalias;
+mod unset;
 
 use std::{
     collections::{hash_map::Iter, HashMap},
@@ -24,16 +31,18 @@ use std::{
 
 use anyhow::Result;
 use unalias::unalias_builtin;
+use unset::unset_builtin;
 
 use self::{
-    alias::alias_builtin, cd::cd_builtin, debug::debug_builtin, exit::exit_builtin,
-    export::export_builtin, help::help_builtin, history::HistoryBuiltin, jobs::jobs_builtin,
-    r#type::type_builtin, source::source_builtin,
+    alias::alias_builtin, cd::cd_builtin, debug::debug_builtin, echo::echo_builtin,
+    exit::exit_builtin, export::export_builtin, help::help_builtin, history::HistoryBuiltin,
+    jobs::jobs_builtin, printf::printf_builtin, r#type::type_builtin, read::read_builtin,
+    set::set_builtin, source::SourceBuiltin, test::test_builtin, trap::trap_builtin,
 };
 use crate::{
     all_the_tuples,
     prelude::{CmdOutput, States},
-    shell::Shell,
+    shell::{Runtime, Shell},
     state::Param,
 };
 // TODO could prob just be a map, to support arbitrary (user defined even) number of builtin commands
@@ -78,6 +87,40 @@ impl Builtins {
     pub fn get(&self, name: &'static str) -> Option<&Box<dyn Builtin>> {
         self.builtins.get(name)
     }
+
+    /// Check if a builtin of the given name is registered
+    pub fn contains(&self, name: &str) -> bool {
+        self.builtins.contains_key(name)
+    }
+
+    /// Run the builtin named by the first argument
+    ///
+    /// Returns `None` if there is no such builtin. Errors from the builtin are printed along with
+    /// the shell name and give a failing status. Every [`Lang`] runs builtins through this.
+    ///
+    /// [`Lang`]: crate::lang::Lang
+    pub fn run(&self, sh: &Shell, states: &States, args: &[String]) -> Option<CmdOutput> {
+        let builtin_cmd = self.builtins.get(args.first()?)?;
+        match builtin_cmd.run(sh, states, &args.to_vec()) {
+            Ok(output) => Some(output),
+            Err(e) => {
+                eprintln!("{}: {e:#}", states.get::<Runtime>().name);
+                Some(CmdOutput::error())
+            },
+        }
+    }
+
+    /// Run a line as a builtin if its first word names one
+    ///
+    /// For languages that don't have a syntax for commands of their own, the line is split into
+    /// words like the shell would and ran with [`Builtins::run`].
+    pub fn run_line(&self, sh: &Shell, states: &States, line: &str) -> Option<CmdOutput> {
+        let words = shrs_lang::split_words(line)
+            .into_iter()
+            .filter(|s| !s.is_empty())
+            .collect::<Vec<_>>();
+        self.run(sh, states, &words)
+    }
 }
 
 impl Default for Builtins {
@@ -91,9 +134,17 @@ impl Default for Builtins {
         builtins.insert("export", export_builtin);
         builtins.insert("history", HistoryBuiltin {});
         builtins.insert("jobs", jobs_builtin);
-        builtins.insert("source", source_builtin);
+        builtins.insert("source", SourceBuiltin {});
         builtins.insert("debug", debug_builtin);
         builtins.insert("unalias", unalias_builtin);
+        builtins.insert("unset", unset_builtin);
+        builtins.insert("set", set_builtin);
+        builtins.insert("trap", trap_builtin);
+        builtins.insert("test", test_builtin);
+        builtins.insert("[", test_builtin);
+        builtins.insert("echo", echo_builtin);
+        builtins.insert("printf", printf_builtin);
+        builtins.insert("read", read_builtin);
 
         builtins
     }
@@ -0,0 +1,628 @@
+use std::{fmt::Write, time::SystemTime};
+
+use chrono::{Local, TimeZone};
+use shrs_lang::quote_word;
+
+use super::echo::expand_escapes;
+use crate::{
+    prelude::{CmdOutput, OutputWriter, State, StateMut},
+    shell::StartupTime,
+};
+
+pub fn printf_builtin(
+    mut out: StateMut<OutputWriter>,
+    startup_time: State<StartupTime>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let mut operands = &args[1..];
+    if operands.first().is_some_and(|arg| arg == "--") {
+        operands = &operands[1..];
+    }
+    let Some((format, operands)) = operands.split_first() else {
+        out.eprintln("printf: usage: printf format [arguments]")?;
+        return Ok(CmdOutput::from_status(2));
+    };
+
+    let started = SystemTime::now() - startup_time.elapsed();
+    let start_time = started
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |time| time.as_secs() as i64);
+    let mut printer = Printer::new(operands, start_time);
+    let result = printer.run(format);
+
+    out.print_bytes(&printer.output)?;
+    for e in printer.errors.iter().chain(result.as_ref().err()) {
+        out.eprintln(format!("printf: {e}"))?;
+    }
+    match printer.errors.is_empty() && result.is_ok() {
+        true => Ok(CmdOutput::success()),
+        false => Ok(CmdOutput::from_status(1)),
+    }
+}
+
+/// Largest field width or precision, larger ones are rejected instead of padding the output
+/// until the shell runs out of memory
+const MAX_FIELD_SIZE: usize = 1 << 20;
+
+/// Flags, field width and precision of a conversion specification like `%-10.3s`
+#[derive(Default)]
+struct Spec {
+    left: bool,
+    plus: bool,
+    space: bool,
+    alternate: bool,
+    zero: bool,
+    width: usize,
+    precision: Option<usize>,
+}
+
+struct Printer<'a> {
+    args: &'a [String],
+    /// Index of the next argument to be consumed by a conversion
+    next: usize,
+    /// Escapes can write any byte, so the output isn't necessarily valid UTF-8
+    output: Vec<u8>,
+    /// Arguments that couldn't be converted, these don't stop the output
+    errors: Vec<String>,
+    /// Time the shell was started at, for `%(fmt)T` with an argument of -2
+    start_time: i64,
+}
+
+impl<'a> Printer<'a> {
+    fn new(args: &'a [String], start_time: i64) -> Self {
+        Printer {
+            args,
+            next: 0,
+            output: vec![],
+            errors: vec![],
+            start_time,
+        }
+    }
+
+    /// Write the format, which is reused for as long as it keeps consuming arguments
+    fn run(&mut self, format: &str) -> Result<(), String> {
+        loop {
+            match self.format(format)? {
+                false if self.next > 0 && self.next < self.args.len() => {},
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    /// Write the format once, returning whether a `\c` in a `%b` argument ended all output
+    fn format(&mut self, format: &str) -> Result<bool, String> {
+        let mut rest = format;
+        while let Some(percent) = rest.find('%') {
+            self.output
+                .extend(expand_escapes(&rest[..percent], false).0);
+            rest = &rest[percent + 1..];
+            if let Some(after) = rest.strip_prefix('%') {
+                self.output.push(b'%');
+                rest = after;
+                continue;
+            }
+
+            let (spec, after) = self.spec(rest)?;
+            rest = after;
+            let mut chars = rest.chars();
+            let conversion = chars.next().ok_or("`%': missing format character")?;
+            rest = chars.as_str();
+
+            let (field, stop) = match conversion {
+                's' => (precision(self.next_arg(), &spec).into(), false),
+                'q' => (quote_word(self.next_arg()).into(), false),
+                'b' => {
+                    let (mut expanded, stop) = expand_escapes(self.next_arg(), true);
+                    expanded.truncate(spec.precision.unwrap_or(expanded.len()));
+                    (expanded, stop)
+                },
+                'c' => (
+                    self.next_arg().chars().take(1).collect::<String>().into(),
+                    false,
+                ),
+                'd' | 'i' => {
+                    let value = self.integer();
+                    let digits = min_digits(value.unsigned_abs().to_string(), &spec);
+                    (
+                        pad_number(sign(value < 0, &spec), digits, &spec, true).into(),
+                        false,
+                    )
+                },
+                'u' | 'o' | 'x' | 'X' => {
+                    // negative numbers wrap around like they do in C
+                    let value = self.integer() as u64;
+                    let digits = match conversion {
+                        'u' => value.to_string(),
+                        'o' => format!("{value:o}"),
+                        'x' => format!("{value:x}"),
+                        _ => format!("{value:X}"),
+                    };
+                    let digits = min_digits(digits, &spec);
+                    let prefix = match conversion {
+                        'o' if spec.alternate && !digits.starts_with('0') => "0",
+                        'x' if spec.alternate && value != 0 => "0x",
+                        'X' if spec.alternate && value != 0 => "0X",
+                        _ => "",
+                    };
+                    (pad_number(prefix, digits, &spec, true).into(), false)
+                },
+                'e' | 'E' | 'f' | 'F' | 'g' | 'G' => {
+                    let value = self.float();
+                    let digits = format_float(value.abs(), conversion, &spec);
+                    let sign = sign(value.is_sign_negative() && !value.is_nan(), &spec);
+                    match value.is_finite() {
+                        true => (pad_number(sign, digits, &spec, false).into(), false),
+                        false => (format!("{sign}{digits}").into(), false),
+                    }
+                },
+                '(' => {
+                    let (time_format, after) = rest
+                        .split_once(")T")
+                        .ok_or("`(': missing time format terminator")?;
+                    rest = after;
+                    let formatted = self.time(time_format)?;
+                    (precision(&formatted, &spec).into(), false)
+                },
+                c => return Err(format!("`{c}': invalid format character")),
+            };
+
+            self.output.extend(pad(field, &spec));
+            if stop {
+                return Ok(true);
+            }
+        }
+        self.output.extend(expand_escapes(rest, false).0);
+        Ok(false)
+    }
+
+    /// Parse the flags, width and precision that come after a `%`
+    fn spec<'f>(&mut self, mut rest: &'f str) -> Result<(Spec, &'f str), String> {
+        let mut spec = Spec::default();
+        while let Some(c) = rest.chars().next() {
+            match c {
+                '-' => spec.left = true,
+                '+' => spec.plus = true,
+                ' ' => spec.space = true,
+                '#' => spec.alternate = true,
+                '0' => spec.zero = true,
+                _ => break,
+            }
+            rest = &rest[1..];
+        }
+
+        // a width given as `*` comes from the arguments, negative widths left justify
+        if let Some(after) = rest.strip_prefix('*') {
+            let width = self.integer();
+            spec.left |= width < 0;
+            spec.width = field_size(width.unsigned_abs(), "field width")?;
+            rest = after;
+        } else {
+            let (width, after) = leading_number(rest, "field width")?;
+            spec.width = width;
+            rest = after;
+        }
+        if let Some(after) = rest.strip_prefix('.') {
+            if let Some(after) = after.strip_prefix('*') {
+                // negative precisions are taken as if there was none
+                let precision = u64::try_from(self.integer()).ok();
+                spec.precision = precision
+                    .map(|precision| field_size(precision, "precision"))
+                    .transpose()?;
+                rest = after;
+            } else {
+                let (precision, after) = leading_number(after, "precision")?;
+                spec.precision = Some(precision);
+                rest = after;
+            }
+        }
+
+        // length modifiers from C don't mean anything here
+        Ok((spec, rest.trim_start_matches(['h', 'l', 'L'])))
+    }
+
+    /// The next argument, missing arguments are treated as empty strings
+    fn next_arg(&mut self) -> &'a str {
+        let arg = self.args.get(self.next).map_or("", |arg| arg.as_str());
+        self.next += 1;
+        arg
+    }
+
+    fn integer(&mut self) -> i64 {
+        let arg = self.next_arg();
+        parse_integer(arg).unwrap_or_else(|value| {
+            self.errors.push(format!("{arg}: invalid number"));
+            value
+        })
+    }
+
+    fn float(&mut self) -> f64 {
+        let arg = self.next_arg();
+        match arg.trim().parse::<f64>() {
+            Ok(value) => value,
+            // integers can also be written in hex and octal or as a character
+            Err(_) => parse_integer(arg).unwrap_or_else(|value| {
+                self.errors.push(format!("{arg}: invalid number"));
+                value
+            }) as f64,
+        }
+    }
+
+    /// Format the time given by the next argument, which is the number of seconds since the
+    /// epoch, or -1 or nothing for the current time and -2 for the time the shell started
+    fn time(&mut self, time_format: &str) -> Result<String, String> {
+        let seconds = match self.args.get(self.next).filter(|arg| !arg.is_empty()) {
+            Some(_) => self.integer(),
+            None => {
+                self.next += 1;
+                -1
+            },
+        };
+        let seconds = match seconds {
+            -1 => Local::now().timestamp(),
+            -2 => self.start_time,
+            seconds => seconds,
+        };
+        let time = Local
+            .timestamp_opt(seconds, 0)
+            .single()
+            .ok_or_else(|| format!("{seconds}: time out of range"))?;
+
+        let time_format = match time_format {
+            "" => "%X",
+            time_format => time_format,
+        };
+        let mut formatted = String::new();
+        write!(formatted, "{}", time.format(time_format))
+            .map_err(|_| format!("`{time_format}': invalid time format"))?;
+        Ok(formatted)
+    }
+}
+
+/// Parse an integer argument, which can be written in decimal, octal with a leading `0`, hex with
+/// a leading `0x`, or as the character code of the character after a leading quote
+///
+/// If the argument isn't a valid number, the value of its valid start is returned as the error.
+/// Numbers too large to be represented are an error too, with the closest value that can be.
+fn parse_integer(arg: &str) -> Result<i64, i64> {
+    let arg = arg.trim_start();
+    if let Some(quoted) = arg.strip_prefix(['\'', '"']) {
+        return Ok(quoted.chars().next().map_or(0, |c| c as i64));
+    }
+    if arg.is_empty() {
+        return Ok(0);
+    }
+
+    let (negative, digits) = match arg.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, arg.strip_prefix('+').unwrap_or(arg)),
+    };
+    let (radix, digits) = match digits.strip_prefix("0x").or(digits.strip_prefix("0X")) {
+        Some(digits) => (16, digits),
+        None if digits.starts_with('0') => (8, digits),
+        None => (10, digits),
+    };
+    let end = digits
+        .find(|c: char| !c.is_digit(radix))
+        .unwrap_or(digits.len());
+    let value = digits[..end].chars().fold(0i128, |value, c| {
+        value
+            .saturating_mul(radix as i128)
+            .saturating_add(c.to_digit(radix).unwrap() as i128)
+    });
+    let value = if negative { -value } else { value };
+    let clamped = value.clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+    match end > 0 && end == digits.len() && clamped as i128 == value {
+        true => Ok(clamped),
+        false => Err(clamped),
+    }
+}
+
+/// Parse the digits of a field width or precision at the start of a string, returning 0 if there
+/// are none
+fn leading_number<'f>(s: &'f str, name: &str) -> Result<(usize, &'f str), String> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, rest) = s.split_at(end);
+    let size = match digits {
+        "" => 0,
+        digits => match digits.parse() {
+            Ok(size) => field_size(size, name)?,
+            Err(_) => return Err(format!("{digits}: invalid {name}")),
+        },
+    };
+    Ok((size, rest))
+}
+
+/// Check that a field width or precision is at most [`MAX_FIELD_SIZE`]
+fn field_size(size: u64, name: &str) -> Result<usize, String> {
+    usize::try_from(size)
+        .ok()
+        .filter(|size| *size <= MAX_FIELD_SIZE)
+        .ok_or_else(|| format!("{size}: invalid {name}"))
+}
+
+fn sign(negative: bool, spec: &Spec) -> &'static str {
+    match (negative, spec.plus, spec.space) {
+        (true, ..) => "-",
+        (false, true, _) => "+",
+        (false, false, true) => " ",
+        _ => "",
+    }
+}
+
+/// For integers the precision is the minimum number of digits
+fn min_digits(digits: String, spec: &Spec) -> String {
+    match spec.precision {
+        Some(0) if digits == "0" => String::new(),
+        Some(precision) if digits.len() < precision => {
+            format!("{}{digits}", "0".repeat(precision - digits.len()))
+        },
+        _ => digits,
+    }
+}
+
+/// For strings the precision is the maximum number of characters
+fn precision<'s>(s: &'s str, spec: &Spec) -> &'s str {
+    match spec
+        .precision
+        .and_then(|precision| s.char_indices().nth(precision))
+    {
+        Some((end, _)) => &s[..end],
+        None => s,
+    }
+}
+
+/// Pad a number to the field width, with zeros between the sign and the digits if asked to
+///
+/// Integers ignore the `0` flag when they're given a precision, floats don't.
+fn pad_number(prefix: &str, digits: String, spec: &Spec, integer: bool) -> String {
+    let len = prefix.len() + digits.len();
+    let zero = spec.zero && !(integer && spec.precision.is_some());
+    match zero && !spec.left && len < spec.width {
+        true => format!("{prefix}{}{digits}", "0".repeat(spec.width - len)),
+        false => format!("{prefix}{digits}"),
+    }
+}
+
+/// Pad a field to the field width with spaces, the width is counted in characters
+fn pad(field: Vec<u8>, spec: &Spec) -> Vec<u8> {
+    let len = String::from_utf8_lossy(&field).chars().count();
+    if len >= spec.width {
+        return field;
+    }
+    let padding = vec![b' '; spec.width - len];
+    match spec.left {
+        true => [field, padding].concat(),
+        false => [padding, field].concat(),
+    }
+}
+
+/// Format a positive floating point number like C's printf does
+fn format_float(value: f64, conversion: char, spec: &Spec) -> String {
+    let formatted = if value.is_infinite() {
+        "inf".to_string()
+    } else if value.is_nan() {
+        "nan".to_string()
+    } else {
+        let precision = spec.precision.unwrap_or(6);
+        match conversion {
+            'f' | 'F' => fixed(value, precision, spec.alternate),
+            'e' | 'E' => exponent(value, precision, spec.alternate),
+            _ => {
+                // `%g` uses whichever of the two is shorter for the exponent of the number
+                let precision = precision.max(1);
+                let exp = match value == 0.0 {
+                    true => 0,
+                    false => format!("{value:.*e}", precision - 1)
+                        .split_once('e')
+                        .map_or(0, |(_, exp)| exp.parse::<i64>().unwrap()),
+                };
+                let formatted = match exp < -4 || exp >= precision as i64 {
+                    true => exponent(value, precision - 1, spec.alternate),
+                    false => fixed(value, (precision as i64 - 1 - exp) as usize, spec.alternate),
+                };
+                match spec.alternate {
+                    true => formatted,
+                    false => strip_zeros(&formatted),
+                }
+            },
+        }
+    };
+    match conversion.is_ascii_uppercase() {
+        true => formatted.to_uppercase(),
+        false => formatted,
+    }
+}
+
+fn fixed(value: f64, precision: usize, alternate: bool) -> String {
+    match alternate && precision == 0 {
+        true => format!("{value:.0}."),
+        false => format!("{value:.precision$}"),
+    }
+}
+
+fn exponent(value: f64, precision: usize, alternate: bool) -> String {
+    let formatted = format!("{value:.precision$e}");
+    let (mantissa, exp) = formatted.split_once('e').unwrap();
+    let exp = exp.parse::<i64>().unwrap();
+    let point = if alternate && precision == 0 { "." } else { "" };
+    let exp_sign = if exp < 0 { '-' } else { '+' };
+    format!("{mantissa}{point}e{exp_sign}{:02}", exp.abs())
+}
+
+/// Remove the trailing zeros after the decimal point, and the point itself if nothing is left
+fn strip_zeros(formatted: &str) -> String {
+    let (number, exp) = match formatted.find('e') {
+        Some(i) => formatted.split_at(i),
+        None => (formatted, ""),
+    };
+    let number = match number.contains('.') {
+        true => number.trim_end_matches('0').trim_end_matches('.'),
+        false => number,
+    };
+    format!("{number}{exp}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_float, parse_integer, Printer, Spec};
+
+    fn printf(format: &str, args: &[&str]) -> String {
+        let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        let mut printer = Printer::new(&args, 0);
+        printer.run(format).unwrap();
+        String::from_utf8(printer.output).unwrap()
+    }
+
+    #[test]
+    fn integers() {
+        assert_eq!(parse_integer("42"), Ok(42));
+        assert_eq!(parse_integer(" -7"), Ok(-7));
+        assert_eq!(parse_integer("+0x1F"), Ok(31));
+        assert_eq!(parse_integer("010"), Ok(8));
+        assert_eq!(parse_integer("0"), Ok(0));
+        assert_eq!(parse_integer(""), Ok(0));
+        assert_eq!(parse_integer("'a"), Ok(97));
+        assert_eq!(parse_integer("\"é"), Ok(233));
+        assert_eq!(parse_integer("'"), Ok(0));
+        assert_eq!(parse_integer("12abc"), Err(12));
+        assert_eq!(parse_integer("09"), Err(0));
+        assert_eq!(parse_integer("0x"), Err(0));
+        assert_eq!(parse_integer("-"), Err(0));
+    }
+
+    #[test]
+    fn integer_overflow() {
+        assert_eq!(parse_integer("9223372036854775807"), Ok(i64::MAX));
+        assert_eq!(parse_integer("-9223372036854775808"), Ok(i64::MIN));
+        assert_eq!(parse_integer("9223372036854775808"), Err(i64::MAX));
+        assert_eq!(parse_integer("0x8000000000000000"), Err(i64::MAX));
+        assert_eq!(
+            parse_integer("-99999999999999999999999999999999999999999"),
+            Err(i64::MIN)
+        );
+    }
+
+    #[test]
+    fn floats() {
+        let spec = |precision| Spec {
+            precision,
+            ..Default::default()
+        };
+        assert_eq!(format_float(1.23456, 'f', &spec(None)), "1.234560");
+        assert_eq!(format_float(2.75, 'f', &spec(Some(1))), "2.8");
+        assert_eq!(format_float(12345.678, 'e', &spec(None)), "1.234568e+04");
+        assert_eq!(format_float(0.000123, 'E', &spec(Some(2))), "1.23E-04");
+        assert_eq!(format_float(f64::INFINITY, 'F', &spec(None)), "INF");
+        assert_eq!(format_float(f64::NAN, 'g', &spec(None)), "nan");
+    }
+
+    #[test]
+    fn shortest_floats() {
+        let spec = |precision| Spec {
+            precision,
+            ..Default::default()
+        };
+        assert_eq!(format_float(100.0, 'g', &spec(None)), "100");
+        assert_eq!(format_float(0.0, 'g', &spec(None)), "0");
+        assert_eq!(format_float(0.0001, 'g', &spec(None)), "0.0001");
+        assert_eq!(format_float(0.00001, 'g', &spec(None)), "1e-05");
+        assert_eq!(format_float(123456789.0, 'G', &spec(None)), "1.23457E+08");
+        assert_eq!(format_float(1.25, 'g', &spec(Some(2))), "1.2");
+        assert_eq!(format_float(1.75, 'g', &spec(Some(0))), "2");
+        // the alternate form keeps trailing zeros
+        let alternate = Spec {
+            alternate: true,
+            ..spec(None)
+        };
+        assert_eq!(format_float(1.0, 'g', &alternate), "1.00000");
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(
+            printf("[%5s][%-5s][%.2s][%c%c]", &["ab", "cd", "efgh", "xyz", ""]),
+            "[   ab][cd   ][ef][x]"
+        );
+        assert_eq!(
+            printf(
+                "%05d|%+d|% d|%.3d|%-4d|%.0d",
+                &["-42", "5", "7", "7", "1", "0"]
+            ),
+            "-0042|+5| 7|007|1   |"
+        );
+        assert_eq!(
+            printf("%x %#X %o %#o %u", &["255", "255", "8", "8", "-1"]),
+            "ff 0XFF 10 010 18446744073709551615"
+        );
+        assert_eq!(
+            printf(
+                "%08.3f|%-9.2e|%+g|%6.1f",
+                &["1.23456", "1234.5", "0.5", "-2.25"]
+            ),
+            "0001.235|1.23e+03 |+0.5|  -2.2"
+        );
+        assert_eq!(
+            printf("%*d|%-*s|%.*s|%q", &["4", "1", "3", "a", "2", "xyz", "a b"]),
+            "   1|a  |xy|'a b'"
+        );
+        assert_eq!(printf("%(%s)T %(%s)T", &["86400", "-2"]), "86400 0");
+        assert_eq!(printf("%%\\t%b", &["\\0101"]), "%\tA");
+    }
+
+    #[test]
+    fn field_size_limits() {
+        let run = |format: &str, args: &[&str]| {
+            let args = args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+            Printer::new(&args, 0).run(format)
+        };
+        assert_eq!(
+            run("%99999999999d", &["1"]),
+            Err("99999999999: invalid field width".into())
+        );
+        assert_eq!(
+            run("%.99999999999999999999999f", &["1"]),
+            Err("99999999999999999999999: invalid precision".into())
+        );
+        assert_eq!(
+            run("%*d", &["-99999999999", "1"]),
+            Err("99999999999: invalid field width".into())
+        );
+        assert_eq!(
+            run("%.*s", &["99999999999", "a"]),
+            Err("99999999999: invalid precision".into())
+        );
+        assert_eq!(printf("%1048576s", &["a"]).len(), 1 << 20);
+        assert_eq!(printf("%.*s", &["-1", "abc"]), "abc");
+    }
+
+    #[test]
+    fn format_reuse() {
+        // the format is repeated until the arguments run out, missing ones are empty or zero
+        assert_eq!(
+            printf("%s=%d\n", &["a", "1", "b", "2", "c"]),
+            "a=1\nb=2\nc=0\n"
+        );
+        assert_eq!(printf("[%s]", &[]), "[]");
+        assert_eq!(printf("x\n", &["unused"]), "x\n");
+        // `\c` ends all output, including the repetitions
+        assert_eq!(printf("%b|", &["a", "b\\cc", "d"]), "a|b");
+    }
+
+    #[test]
+    fn errors() {
+        let args = ["1x".to_string(), "2".to_string()];
+        let mut printer = Printer::new(&args, 0);
+        assert_eq!(
+            printer.run("%d %z"),
+            Err("`z': invalid format character".to_string())
+        );
+        assert_eq!(printer.output, b"1 ");
+        assert_eq!(printer.errors, ["1x: invalid number"]);
+
+        let mut printer = Printer::new(&[], 0);
+        assert_eq!(
+            printer.run("%"),
+            Err("`%': missing format character".to_string())
+        );
+    }
+}
@@ -0,0 +1,463 @@
+use std::{
+    io::Write,
+    os::fd::RawFd,
+    time::{Duration, Instant},
+};
+
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, is_raw_mode_enabled};
+use nix::{
+    errno::Errno,
+    libc::c_int,
+    poll::{poll, PollFd, PollFlags},
+    sys::{
+        signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal},
+        termios::{tcgetattr, tcsetattr, LocalFlags, SetArg, SpecialCharacterIndices, Termios},
+    },
+    unistd::{isatty, read},
+};
+use shrs_lang::is_name;
+
+use crate::prelude::{CmdOutput, OutputWriter, Runtime, StateMut};
+
+/// Input is read straight from the file descriptor, one byte at a time, so that nothing after the
+/// delimiter is consumed from a pipe or file that later commands read from
+const STDIN: RawFd = 0;
+
+/// Characters input is split at when `IFS` is not set
+const DEFAULT_IFS: &str = " \t\n";
+
+#[derive(Default)]
+struct Options {
+    raw: bool,
+    silent: bool,
+    prompt: Option<String>,
+    timeout: Option<Duration>,
+    count: Option<usize>,
+    delimiter: Option<char>,
+    array: Option<String>,
+}
+
+/// Why reading input stopped
+enum Ending {
+    Delimiter,
+    Count,
+    Eof,
+    Timeout,
+    Interrupted,
+}
+
+pub fn read_builtin(
+    mut out: StateMut<OutputWriter>,
+    mut rt: StateMut<Runtime>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let (options, names) = match parse_options(&args[1..]) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            out.eprintln(format!("read: {e}"))?;
+            out.eprintln(
+                "read: usage: read [-rs] [-a array] [-d delim] [-n nchars] [-p prompt] [-t timeout] [name ...]",
+            )?;
+            return Ok(CmdOutput::from_status(2));
+        },
+    };
+    if let Some(name) = names
+        .iter()
+        .chain(&options.array)
+        .find(|name| !is_name(name))
+    {
+        out.eprintln(format!("read: `{name}': not a valid identifier"))?;
+        return Ok(CmdOutput::from_status(1));
+    }
+
+    let terminal = isatty(STDIN).unwrap_or(false);
+    // a timeout of zero only checks whether there is input to read
+    if options.timeout == Some(Duration::ZERO) {
+        let status = match wait_for_input(Some(Duration::ZERO)) {
+            Ok(true) => 0,
+            _ => 1,
+        };
+        return Ok(CmdOutput::from_status(status));
+    }
+    if let Some(prompt) = options.prompt.as_ref().filter(|_| terminal) {
+        let mut stderr = std::io::stderr();
+        stderr.write_all(prompt.as_bytes())?;
+        stderr.flush()?;
+    }
+
+    let input = {
+        let _terminal = terminal.then(|| {
+            let canonical = options.count.is_none() && options.delimiter.is_none();
+            TerminalGuard::new(options.silent, canonical)
+        });
+        read_input(&options)
+    };
+    let (chars, ending) = match input {
+        Ok(input) => input,
+        Err(e) => {
+            out.eprintln(format!("read: read error: {e}"))?;
+            return Ok(CmdOutput::from_status(1));
+        },
+    };
+
+    let ifs = rt
+        .vars
+        .get("IFS")
+        .or_else(|| rt.env.get("IFS").ok())
+        .cloned()
+        .unwrap_or_else(|| DEFAULT_IFS.to_string());
+    if let Some(array) = &options.array {
+        let fields = split_fields(&chars, &ifs, usize::MAX);
+        rt.vars.remove(array);
+        rt.arrays.insert(array.clone(), fields);
+    } else if names.is_empty() {
+        // without any names the whole line is kept, including surrounding whitespace
+        let line = chars.iter().map(|(c, _)| c).collect::<String>();
+        set_var(&mut rt, "REPLY", &line)?;
+    } else {
+        let mut fields = split_fields(&chars, &ifs, names.len()).into_iter();
+        for name in names {
+            set_var(&mut rt, name, &fields.next().unwrap_or_default())?;
+        }
+    }
+
+    let status = match ending {
+        Ending::Delimiter | Ending::Count => 0,
+        Ending::Eof => 1,
+        Ending::Timeout => 128 + Signal::SIGALRM as i32,
+        Ending::Interrupted => 128 + Signal::SIGINT as i32,
+    };
+    Ok(CmdOutput::from_status(status))
+}
+
+fn parse_options(args: &[String]) -> Result<(Options, &[String]), String> {
+    let mut options = Options::default();
+    let mut args = args;
+    while let Some((arg, rest)) = args.split_first() {
+        let flags = match arg.strip_prefix('-') {
+            _ if arg == "--" => {
+                args = rest;
+                break;
+            },
+            Some(flags) if !flags.is_empty() => flags,
+            _ => break,
+        };
+        args = rest;
+
+        for (i, flag) in flags.char_indices() {
+            match flag {
+                'r' => options.raw = true,
+                's' => options.silent = true,
+                'p' | 't' | 'n' | 'd' | 'a' => {
+                    // the value is either the rest of the argument or the next argument
+                    let value = match &flags[i + 1..] {
+                        "" => {
+                            let (value, rest) = args
+                                .split_first()
+                                .ok_or(format!("-{flag}: option requires an argument"))?;
+                            args = rest;
+                            value.as_str()
+                        },
+                        value => value,
+                    };
+                    match flag {
+                        'p' => options.prompt = Some(value.to_string()),
+                        't' => {
+                            let timeout = value
+                                .parse::<f64>()
+                                .ok()
+                                .and_then(|secs| Duration::try_from_secs_f64(secs).ok())
+                                .ok_or(format!("{value}: invalid timeout specification"))?;
+                            options.timeout = Some(timeout);
+                        },
+                        'n' => {
+                            let count = value
+                                .parse()
+                                .map_err(|_| format!("{value}: invalid number"))?;
+                            options.count = Some(count);
+                        },
+                        // an empty delimiter ends the input at a NUL character
+                        'd' => options.delimiter = Some(value.chars().next().unwrap_or('\0')),
+                        _ => options.array = Some(value.to_string()),
+                    }
+                    break;
+                },
+                _ => return Err(format!("-{flag}: invalid option")),
+            }
+        }
+    }
+    Ok((options, args))
+}
+
+/// Read characters up to the delimiter, along with whether they were escaped with a backslash
+fn read_input(options: &Options) -> nix::Result<(Vec<(char, bool)>, Ending)> {
+    let delimiter = options.delimiter.unwrap_or('\n');
+    let deadline = options.timeout.map(|timeout| Instant::now() + timeout);
+    let mut chars = vec![];
+    let mut bytes = vec![];
+    let mut escaped = false;
+    loop {
+        if options.count.is_some_and(|count| chars.len() >= count) {
+            return Ok((chars, Ending::Count));
+        }
+        let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+        if !wait_for_input(remaining)? {
+            return Ok((chars, Ending::Timeout));
+        }
+
+        let mut byte = [0];
+        match read(STDIN, &mut byte) {
+            Ok(0) => return Ok((chars, Ending::Eof)),
+            Ok(_) => bytes.push(byte[0]),
+            Err(Errno::EINTR) => return Ok((chars, Ending::Interrupted)),
+            Err(e) => return Err(e),
+        }
+        // wait for the rest of a multibyte character
+        let c = match std::str::from_utf8(&bytes) {
+            Ok(s) => s.chars().next().unwrap(),
+            Err(e) if e.error_len().is_none() => continue,
+            Err(_) => char::REPLACEMENT_CHARACTER,
+        };
+        bytes.clear();
+
+        if escaped {
+            escaped = false;
+            // an escaped newline continues the line
+            if c != '\n' {
+                chars.push((c, true));
+            }
+        } else if c == '\\' && !options.raw {
+            escaped = true;
+        } else if c == delimiter {
+            return Ok((chars, Ending::Delimiter));
+        } else {
+            chars.push((c, false));
+        }
+    }
+}
+
+/// Wait until there is input to read, returning false if the timeout runs out first
+fn wait_for_input(timeout: Option<Duration>) -> nix::Result<bool> {
+    let Some(timeout) = timeout else {
+        return Ok(true);
+    };
+    let mut fds = [PollFd::new(STDIN, PollFlags::POLLIN)];
+    let millis = timeout.as_millis().min(c_int::MAX as u128) as c_int;
+    match poll(&mut fds, millis) {
+        Ok(ready) => Ok(ready > 0),
+        // let the read report the interruption
+        Err(Errno::EINTR) => Ok(true),
+        Err(e) => Err(e),
+    }
+}
+
+/// Split the input into at most `max` fields at the unescaped characters in `IFS`, the last field
+/// gets the rest of the input
+fn split_fields(chars: &[(char, bool)], ifs: &str, max: usize) -> Vec<String> {
+    let is_ifs = |&(c, escaped): &(char, bool)| !escaped && ifs.contains(c);
+    let is_space = |c: &(char, bool)| is_ifs(c) && DEFAULT_IFS.contains(c.0);
+    let to_string = |chars: &[(char, bool)]| chars.iter().map(|(c, _)| c).collect::<String>();
+    let skip_spaces = |chars: &[(char, bool)]| {
+        let start = chars
+            .iter()
+            .position(|c| !is_space(c))
+            .unwrap_or(chars.len());
+        chars[start..].to_vec()
+    };
+
+    let mut fields = vec![];
+    let mut rest = skip_spaces(chars);
+    while !rest.is_empty() {
+        if fields.len() + 1 == max {
+            let end = rest.iter().rposition(|c| !is_space(c)).map_or(0, |i| i + 1);
+            let mut last = &rest[..end];
+            // a delimiter at the end is dropped if there is only one field left
+            if let Some((delimiter, field)) = last.split_last() {
+                if is_ifs(delimiter) && !field.iter().any(is_ifs) {
+                    last = field;
+                }
+            }
+            fields.push(to_string(last));
+            break;
+        }
+
+        let end = rest.iter().position(is_ifs).unwrap_or(rest.len());
+        fields.push(to_string(&rest[..end]));
+        // fields are separated by whitespace around at most one other character from `IFS`
+        rest = skip_spaces(&rest[end..]);
+        if rest.first().is_some_and(is_ifs) {
+            rest = skip_spaces(&rest[1..]);
+        }
+    }
+    fields
+}
+
+/// Set a variable like an assignment would, exported variables stay exported
+fn set_var(rt: &mut Runtime, name: &str, value: &str) -> anyhow::Result<()> {
+    if rt.env.get(name).is_ok() {
+        rt.env.set(name, value)?;
+    } else {
+        rt.vars.insert(name.to_string(), value.to_string());
+    }
+    Ok(())
+}
+
+extern "C" fn interrupt(_: c_int) {}
+
+/// Puts the terminal in the mode needed for reading and restores it afterwards
+struct TerminalGuard {
+    termios: Option<Termios>,
+    raw_mode: bool,
+    sigint: Option<SigAction>,
+}
+
+impl TerminalGuard {
+    fn new(silent: bool, canonical: bool) -> Self {
+        // the line editor might have left the terminal in raw mode, in which case the input
+        // wouldn't be echoed or read a line at a time
+        let raw_mode = is_raw_mode_enabled().unwrap_or(false);
+        if raw_mode {
+            let _ = disable_raw_mode();
+        }
+
+        let termios = tcgetattr(STDIN).ok();
+        if let Some(termios) = termios.clone().filter(|_| silent || !canonical) {
+            let mut termios = termios;
+            if silent {
+                termios.local_flags.remove(LocalFlags::ECHO);
+            }
+            if !canonical {
+                termios.local_flags.remove(LocalFlags::ICANON);
+                termios.control_chars[SpecialCharacterIndices::VMIN as usize] = 1;
+                termios.control_chars[SpecialCharacterIndices::VTIME as usize] = 0;
+            }
+            let _ = tcsetattr(STDIN, SetArg::TCSANOW, &termios);
+        }
+
+        // an interactive shell ignores interrupts, they should still stop the read though
+        let action = SigAction::new(
+            SigHandler::Handler(interrupt),
+            SaFlags::empty(),
+            SigSet::empty(),
+        );
+        let sigint = match unsafe { sigaction(Signal::SIGINT, &action) } {
+            Ok(old) if old.handler() == SigHandler::SigIgn => Some(old),
+            Ok(old) => {
+                let _ = unsafe { sigaction(Signal::SIGINT, &old) };
+                None
+            },
+            Err(_) => None,
+        };
+
+        Self {
+            termios,
+            raw_mode,
+            sigint,
+        }
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        if let Some(old) = &self.sigint {
+            let _ = unsafe { sigaction(Signal::SIGINT, old) };
+        }
+        if let Some(termios) = &self.termios {
+            let _ = tcsetattr(STDIN, SetArg::TCSADRAIN, termios);
+        }
+        if self.raw_mode {
+            let _ = enable_raw_mode();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{parse_options, split_fields, DEFAULT_IFS};
+
+    /// Split a string where backslashes mark the character after them as escaped
+    fn split(input: &str, ifs: &str, max: usize) -> Vec<String> {
+        let mut chars = vec![];
+        let mut input = input.chars();
+        while let Some(c) = input.next() {
+            match c {
+                '\\' => chars.extend(input.next().map(|c| (c, true))),
+                c => chars.push((c, false)),
+            }
+        }
+        split_fields(&chars, ifs, max)
+    }
+
+    fn strings(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn whitespace_fields() {
+        assert_eq!(split("  a  b   c  ", DEFAULT_IFS, 2), ["a", "b   c"]);
+        assert_eq!(split("a\tb", DEFAULT_IFS, 3), ["a", "b"]);
+        assert_eq!(split(" a b c ", DEFAULT_IFS, usize::MAX), ["a", "b", "c"]);
+        assert_eq!(split("   ", DEFAULT_IFS, 1), Vec::<String>::new());
+        assert_eq!(split(" a b ", "", 2), [" a b "]);
+    }
+
+    #[test]
+    fn delimiter_fields() {
+        assert_eq!(split("1,,3,4", ",", 3), ["1", "", "3,4"]);
+        assert_eq!(split(" 1 , 2 ,3", " ,", usize::MAX), ["1", "2", "3"]);
+        assert_eq!(split("1,2,3,", ",", usize::MAX), ["1", "2", "3"]);
+        assert_eq!(split(",1", ",", usize::MAX), ["", "1"]);
+        // the last field keeps the rest of the input, without a single trailing delimiter
+        assert_eq!(split("a,b,", ",", 2), ["a", "b"]);
+        assert_eq!(split("a,b,c,", ",", 2), ["a", "b,c,"]);
+        assert_eq!(split("a,b,,", ",", 2), ["a", "b,,"]);
+    }
+
+    #[test]
+    fn escaped_separators() {
+        assert_eq!(split(r"a\ b c", DEFAULT_IFS, 2), ["a b", "c"]);
+        assert_eq!(split(r"a\,b,c", ",", usize::MAX), ["a,b", "c"]);
+        assert_eq!(split(r"a b\ ", DEFAULT_IFS, 2), ["a", "b "]);
+        assert_eq!(split(r"a,b\,", ",", 2), ["a", "b,"]);
+    }
+
+    #[test]
+    fn options() {
+        let args = strings(&["-rn3", "-d", "", "-t", "1.5", "x", "y"]);
+        let (options, names) = parse_options(&args).unwrap();
+        assert!(options.raw && !options.silent);
+        assert_eq!(options.count, Some(3));
+        assert_eq!(options.delimiter, Some('\0'));
+        assert_eq!(options.timeout, Some(Duration::from_millis(1500)));
+        assert_eq!(names, ["x", "y"]);
+
+        let args = strings(&["-sp", "> ", "-d:", "-a", "arr", "--", "-n"]);
+        let (options, names) = parse_options(&args).unwrap();
+        assert!(options.silent);
+        assert_eq!(options.prompt.as_deref(), Some("> "));
+        assert_eq!(options.delimiter, Some(':'));
+        assert_eq!(options.array.as_deref(), Some("arr"));
+        assert_eq!(names, ["-n"]);
+    }
+
+    #[test]
+    fn invalid_options() {
+        let error = |args: &[&str]| parse_options(&strings(args)).err();
+        assert_eq!(
+            error(&["-t", "soon"]),
+            Some("soon: invalid timeout specification".to_string())
+        );
+        assert_eq!(
+            error(&["-t-1"]),
+            Some("-1: invalid timeout specification".to_string())
+        );
+        assert_eq!(error(&["-nx"]), Some("x: invalid number".to_string()));
+        assert_eq!(
+            error(&["-n"]),
+            Some("-n: option requires an argument".to_string())
+        );
+        assert_eq!(error(&["-rx"]), Some("-x: invalid option".to_string()));
+    }
+}
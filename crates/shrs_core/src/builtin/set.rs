@@ -0,0 +1,100 @@
+use shrs_lang::quote_word;
+
+use crate::prelude::{CmdOutput, OutputWriter, Runtime, ShellOptions, StateMut};
+
+pub fn set_builtin(
+    mut options: StateMut<ShellOptions>,
+    mut rt: StateMut<Runtime>,
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    // without arguments all the variables are listed
+    if args.len() == 1 {
+        let mut vars = rt
+            .vars
+            .iter()
+            .chain(rt.env.iter())
+            .map(|(var, val)| format!("{var}={}", quote_word(val)))
+            .collect::<Vec<_>>();
+        vars.sort();
+        for var in vars {
+            out.println(var)?;
+        }
+        return Ok(CmdOutput::success());
+    }
+
+    let mut args = args[1..].iter();
+    // positional parameters are only replaced when there are arguments after the options or `--`
+    let mut positional_args = None;
+    while let Some(arg) = args.next() {
+        let enable = match arg.chars().next() {
+            Some('-') => true,
+            Some('+') => false,
+            _ => {
+                positional_args =
+                    Some(std::iter::once(arg).chain(args.by_ref()).cloned().collect());
+                break;
+            },
+        };
+        match arg.as_str() {
+            "--" => {
+                positional_args = Some(args.by_ref().cloned().collect());
+                break;
+            },
+            // `set -` turns off tracing and ends the options
+            "-" => {
+                options.xtrace = false;
+                let rest = args.by_ref().cloned().collect::<Vec<_>>();
+                positional_args = Some(rest).filter(|rest| !rest.is_empty());
+                break;
+            },
+            _ => {},
+        }
+
+        for flag in arg.chars().skip(1) {
+            let name = match flag {
+                'o' => match args.next() {
+                    Some(name) => name.as_str(),
+                    // `set -o` lists the options, `set +o` prints the commands that restore them
+                    None => {
+                        list_options(&options, !enable, &mut out)?;
+                        break;
+                    },
+                },
+                flag => match ShellOptions::flag_name(flag) {
+                    Some(name) => name,
+                    None => {
+                        out.eprintln(format!("set: {}{flag}: invalid option", &arg[..1]))?;
+                        return Ok(CmdOutput::from_status(2));
+                    },
+                },
+            };
+            if !options.set(name, enable) {
+                out.eprintln(format!("set: {name}: invalid option name"))?;
+                return Ok(CmdOutput::from_status(2));
+            }
+        }
+    }
+
+    if let Some(positional_args) = positional_args {
+        rt.args = positional_args;
+    }
+
+    Ok(CmdOutput::success())
+}
+
+fn list_options(
+    options: &ShellOptions,
+    as_commands: bool,
+    out: &mut OutputWriter,
+) -> anyhow::Result<()> {
+    for name in ShellOptions::names() {
+        let enabled = options.get(name).unwrap_or_default();
+        if as_commands {
+            out.println(format!("set {}o {name}", if enabled { '-' } else { '+' }))?;
+        } else {
+            out.println(format!("{name:<15}{}", if enabled { "on" } else { "off" }))?;
+        }
+    }
+    Ok(())
+}
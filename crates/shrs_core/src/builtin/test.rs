@@ -0,0 +1,30 @@
+use shrs_lang::eval_test;
+
+use crate::prelude::{CmdOutput, OutputWriter, StateMut};
+
+pub fn test_builtin(
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let name = &args[0];
+    let mut operands = &args[1..];
+    // `[` has to be closed with a `]` argument
+    if name == "[" {
+        match operands.split_last() {
+            Some((last, rest)) if last == "]" => operands = rest,
+            _ => {
+                out.eprintln("[: missing `]'")?;
+                return Ok(CmdOutput::from_status(2));
+            },
+        }
+    }
+
+    match eval_test(operands) {
+        Ok(true) => Ok(CmdOutput::success()),
+        Ok(false) => Ok(CmdOutput::from_status(1)),
+        Err(e) => {
+            out.eprintln(format!("{name}: {e}"))?;
+            Ok(CmdOutput::from_status(2))
+        },
+    }
+}
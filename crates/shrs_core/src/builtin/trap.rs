@@ -0,0 +1,76 @@
+use shrs_lang::{catch_signal, quote_word, reset_signal, TrapCondition};
+
+use crate::prelude::{CmdOutput, OutputWriter, Runtime, StateMut};
+
+pub fn trap_builtin(
+    mut rt: StateMut<Runtime>,
+    mut out: StateMut<OutputWriter>,
+    args: &Vec<String>,
+) -> anyhow::Result<CmdOutput> {
+    let mut args = &args[1..];
+    if args.first().is_some_and(|arg| arg == "--") {
+        args = &args[1..];
+    }
+
+    // without an action, or with `-p`, the traps are printed as the commands that set them
+    let print = match args.first().map(String::as_str) {
+        None => true,
+        Some("-p") => {
+            args = &args[1..];
+            true
+        },
+        _ => false,
+    };
+    let (action, names) = match print {
+        true => (None, args),
+        false => (Some(&args[0]), &args[1..]),
+    };
+    if action.is_some() && names.is_empty() {
+        out.eprintln("trap: usage: trap [-p] [action condition ...]")?;
+        return Ok(CmdOutput::from_status(2));
+    }
+
+    let mut conditions = vec![];
+    let mut status = 0;
+    for name in names {
+        match name.parse::<TrapCondition>() {
+            Ok(condition) => conditions.push(condition),
+            Err(e) => {
+                out.eprintln(format!("trap: {e}"))?;
+                status = 1;
+            },
+        }
+    }
+
+    match action.map(String::as_str) {
+        None => {
+            if names.is_empty() {
+                conditions = TrapCondition::all().collect();
+            }
+            for condition in conditions {
+                if let Some(action) = rt.traps.get(&condition) {
+                    out.println(format!("trap -- {} {condition}", quote_word(action)))?;
+                }
+            }
+        },
+        // `-` resets the conditions to what they were before they were trapped
+        Some("-") => {
+            for condition in conditions {
+                rt.traps.remove(&condition);
+                if let TrapCondition::Signal(signal) = condition {
+                    reset_signal(signal)?;
+                }
+            }
+        },
+        Some(action) => {
+            for condition in conditions {
+                rt.traps.insert(condition, action.to_string());
+                if let TrapCondition::Signal(signal) = condition {
+                    catch_signal(signal, action.is_empty())?;
+                }
+            }
+        },
+    }
+
+    Ok(CmdOutput::from_status(status))
+}
@@ -54,6 +54,21 @@ fn analyze_name(
             }
         }
 
+        // check if name is a function
+        if rt.functions.contains_key(name) {
+            name_found = true;
+            if !path_result_only {
+                if type_only {
+                    out.println("function")?;
+                } else {
+                    out.println(format!("{} is a function", name))?;
+                }
+            }
+            if !all {
+                return Ok(CmdOutput::success());
+            }
+        }
+
         // check if name is a builtin
         if sh.builtins.builtins.contains_key(name as &str) {
             name_found = true;
@@ -0,0 +1,29 @@
+use clap::Parser;
+
+use crate::prelude::{CmdOutput, Runtime, StateMut};
+
+#[derive(Parser)]
+struct Cli {
+    /// Treat each name as a function
+    #[arg(short)]
+    f: bool,
+    /// Treat each name as a variable
+    #[arg(short)]
+    v: bool,
+    names: Vec<String>,
+}
+
+pub fn unset_builtin(mut rt: StateMut<Runtime>, args: &Vec<String>) -> anyhow::Result<CmdOutput> {
+    let cli = Cli::try_parse_from(args)?;
+
+    for name in cli.names.iter() {
+        if cli.f {
+            rt.functions.remove(name);
+        } else {
+            rt.vars.remove(name);
+            rt.env.remove(name)?;
+        }
+    }
+
+    Ok(CmdOutput::success())
+}
@@ -22,6 +22,15 @@ impl CmdOutput {
         }
     }
 
+    /// Create a new [CmdOutput] with the exit status of a process
+    pub fn from_exit_status(status: ExitStatus) -> Self {
+        CmdOutput {
+            stdout: String::new(),
+            stderr: String::new(),
+            status,
+        }
+    }
+
     /// Create a new [CmdOutput] with a successful exit code of 0
     pub fn success() -> Self {
         CmdOutput::from_status(0)
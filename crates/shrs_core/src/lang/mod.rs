@@ -8,10 +8,33 @@ pub use posix_lang::PosixLang;
 use crate::{cmd_output::CmdOutput, prelude::States, shell::Shell};
 
 /// Trait to implement a shell command language
+///
+/// Languages run builtins themselves, with [`Builtins::run`] or [`Builtins::run_line`].
+///
+/// [`Builtins::run`]: crate::builtin::Builtins::run
+/// [`Builtins::run_line`]: crate::builtin::Builtins::run_line
 pub trait Lang {
     fn eval(&self, sh: &Shell, ctx: &States, cmd: String) -> anyhow::Result<CmdOutput>;
     fn name(&self) -> String;
     /// Called when enter is pressed in line to check if the command is complete or needs another
     /// line. Use `state.line.get_full_command()`
     fn needs_line_check(&self, sh: &Shell, ctx: &States) -> bool;
+    /// Check whether input read without the line editor, like the lines of a script read so far,
+    /// makes up a whole command that can be evaluated
+    ///
+    /// By default every line is a command of its own.
+    fn input_status(&self, _sh: &Shell, _ctx: &States, _input: &str) -> InputStatus {
+        InputStatus::Complete
+    }
+}
+
+/// Whether some input can be evaluated as it is, see [`Lang::input_status`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputStatus {
+    /// The input is one or more complete commands
+    Complete,
+    /// More input could complete the command, like when a quote or an `if` isn't closed yet
+    Incomplete,
+    /// The input has an error that more input can't fix
+    Invalid,
 }
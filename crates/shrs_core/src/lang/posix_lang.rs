@@ -1,36 +1,175 @@
-use shrs_job::{initialize_job_control, JobManager};
-use shrs_lang::{Lexer, Parser, ParserError, Token, PosixError};
+use std::process::ExitStatus;
+
+use shrs_job::JobManager;
+use shrs_lang::{
+    ast, Lexer, LexerError, Parser, ParserError, ShellContext, ShellOptions, Token, TrapCondition,
+};
 use thiserror::Error;
 
-use super::Lang;
+use super::{InputStatus, Lang};
 use crate::{
-    prelude::{CmdOutput, LineContents, States},
-    shell::Shell,
+    prelude::{Alias, AliasRuleCtx, CmdOutput, LineContents, States},
+    shell::{Runtime, Shell},
 };
 
 /// Posix implementation of shell command language
+#[derive(Default)]
 pub struct PosixLang {}
 
-impl Default for PosixLang {
-    fn default() -> Self {
-        initialize_job_control().unwrap();
-        Self {}
+/// Shell state made available to [`shrs_lang::eval`]
+struct PosixContext<'a> {
+    sh: &'a Shell,
+    states: &'a States,
+}
+
+impl ShellContext for PosixContext<'_> {
+    fn get_var(&self, name: &str) -> Option<String> {
+        let rt = self.states.get::<Runtime>();
+        rt.vars
+            .get(name)
+            .or_else(|| rt.env.get(name).ok())
+            .cloned()
+            // the environment the shell was started with might not have been loaded into `env`
+            .or_else(|| std::env::var(name).ok())
+    }
+
+    fn set_var(&mut self, name: &str, value: &str) {
+        let mut rt = self.states.get_mut::<Runtime>();
+        // exported variables stay exported
+        if rt.env.get(name).is_ok() {
+            let _ = rt.env.set(name, value);
+        } else {
+            rt.vars.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    fn unset_var(&mut self, name: &str) {
+        let mut rt = self.states.get_mut::<Runtime>();
+        rt.vars.remove(name);
+        rt.arrays.remove(name);
+        let _ = rt.env.remove(name);
+    }
+
+    fn get_array(&self, name: &str) -> Option<Vec<String>> {
+        self.states.get::<Runtime>().arrays.get(name).cloned()
+    }
+
+    fn set_array(&mut self, name: &str, values: Vec<String>) {
+        self.states
+            .get_mut::<Runtime>()
+            .arrays
+            .insert(name.to_string(), values);
+    }
+
+    fn exit_status(&self) -> i32 {
+        self.states.get::<Runtime>().exit_status
+    }
+
+    fn set_exit_status(&mut self, status: i32) {
+        self.states.get_mut::<Runtime>().exit_status = status;
+    }
+
+    fn shell_name(&self) -> String {
+        self.states.get::<Runtime>().name.clone()
+    }
+
+    fn last_background_pid(&self) -> Option<u32> {
+        self.states.get::<Runtime>().last_background_pid
+    }
+
+    fn set_last_background_pid(&mut self, pid: u32) {
+        self.states.get_mut::<Runtime>().last_background_pid = Some(pid);
+    }
+
+    fn options(&self) -> ShellOptions {
+        *self.states.get::<ShellOptions>()
+    }
+
+    fn positional_args(&self) -> Vec<String> {
+        self.states.get::<Runtime>().args.clone()
+    }
+
+    fn set_positional_args(&mut self, args: Vec<String>) {
+        self.states.get_mut::<Runtime>().args = args;
+    }
+
+    fn get_function(&self, name: &str) -> Option<Box<ast::Command>> {
+        self.states.get::<Runtime>().functions.get(name).cloned()
+    }
+
+    fn set_function(&mut self, name: &str, body: Box<ast::Command>) {
+        self.states
+            .get_mut::<Runtime>()
+            .functions
+            .insert(name.to_string(), body);
+    }
+
+    fn get_trap(&self, condition: TrapCondition) -> Option<String> {
+        self.states.get::<Runtime>().traps.get(&condition).cloned()
+    }
+
+    fn remove_trap(&mut self, condition: TrapCondition) {
+        self.states.get_mut::<Runtime>().traps.remove(&condition);
+    }
+
+    fn is_builtin(&self, name: &str) -> bool {
+        self.sh.builtins.contains(name)
+    }
+
+    fn run_builtin(&mut self, args: &[String]) -> ExitStatus {
+        self.sh
+            .builtins
+            .run(self.sh, self.states, args)
+            .unwrap_or_else(|| CmdOutput::from_status(127))
+            .status
     }
 }
 
-impl Lang for PosixLang {
+impl PosixLang {
+    /// Parse a command, substituting aliases if they are enabled
+    fn parse(&self, sh: &Shell, states: &States, input: &str) -> Result<ast::Command, ParserError> {
+        // TODO why are we creating a new lexer and parser each eval? is this necessary?
+        // aliases are looked up while parsing, the state is released before evaluating since the
+        // command can change it
+        let aliases = states.get::<Alias>();
+        let lookup = |alias_name: &str| {
+            let alias_ctx = AliasRuleCtx {
+                alias_name,
+                sh,
+                states,
+            };
+            aliases.resolve(&alias_ctx).map(|subst| subst.as_str())
+        };
+        let mut lexer = Lexer::new(input);
+        if states.get::<ShellOptions>().expand_aliases {
+            lexer = lexer.with_aliases(&lookup);
+        }
+        Parser::default().parse(lexer)
+    }
+}
 
-    fn eval(&self, _sh: &Shell, states: &States, line: String) -> anyhow::Result<CmdOutput> {
+impl Lang for PosixLang {
+    fn eval(&self, sh: &Shell, states: &States, line: String) -> anyhow::Result<CmdOutput> {
         // TODO rewrite the error handling here better
-        // TODO why are we creating a new lexer and parser each eval? is this necessary?
-        let lexer = Lexer::new(&line);
-        let parser = Parser::default();
+        let parsed = match self.parse(sh, states, &line) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                eprintln!("{}", e.diagnostic(&line));
+                // syntax errors are reported with the same status as other shells use
+                states.get_mut::<Runtime>().exit_status = 2;
+                return Ok(CmdOutput::from_status(2));
+            },
+        };
+
         let job_manger = &mut states.get_mut::<JobManager>();
+        let mut ctx = PosixContext { sh, states };
 
-        match shrs_lang::eval(job_manger, parser, lexer) {
-            Ok(_) => Ok(CmdOutput::success()),
-            Err(_e) => Ok(CmdOutput::error()),
-        }
+        let cmd_output = match shrs_lang::eval_command(job_manger, &mut ctx, &parsed) {
+            Ok(status) => CmdOutput::from_exit_status(status),
+            Err(_e) => CmdOutput::error(),
+        };
+        states.get_mut::<Runtime>().exit_status = shrs_lang::exit_code(cmd_output.status);
+        Ok(cmd_output)
     }
 
     fn name(&self) -> String {
@@ -38,7 +177,6 @@ impl Lang for PosixLang {
     }
 
     fn needs_line_check(&self, _sh: &Shell, ctx: &States) -> bool {
-        //TODO check if open quotes or brackets
         let command = ctx.get::<LineContents>().get_full_command();
 
         if let Some(last_char) = command.chars().last() {
@@ -51,7 +189,16 @@ impl Lang for PosixLang {
 
         let lexer = Lexer::new(command.as_str());
 
-        for token in lexer.flatten() {
+        for token in lexer {
+            // keep reading lines until quotes and substitutions are closed, and here-documents
+            // reach their delimiter
+            let token = match token {
+                Ok(token) => token,
+                Err(LexerError::Unterminated(..) | LexerError::MissingDelimiter(..)) => {
+                    return true
+                },
+                Err(_) => continue,
+            };
             match token.1 {
                 Token::LBRACE => brackets.push(token.1),
                 Token::LPAREN => brackets.push(token.1),
@@ -73,36 +220,18 @@ impl Lang for PosixLang {
                         }
                     }
                 },
-                Token::WORD(w) => {
-                    if let Some(c) = w.chars().next() {
-                        if c == '\'' {
-                            if w.len() == 1 {
-                                return true;
-                            }
-                            if let Some(e) = w.chars().last() {
-                                return e != '\'';
-                            } else {
-                                return true;
-                            }
-                        }
-                        if c == '\"' {
-                            if w.len() == 1 {
-                                return true;
-                            }
-
-                            if let Some(e) = w.chars().last() {
-                                return e != '\"';
-                            } else {
-                                return true;
-                            }
-                        }
-                    }
-                },
-
                 _ => (),
             }
         }
 
         !brackets.is_empty()
     }
+
+    fn input_status(&self, sh: &Shell, states: &States, input: &str) -> InputStatus {
+        match self.parse(sh, states, input) {
+            Ok(_) => InputStatus::Complete,
+            Err(e) if e.is_incomplete() => InputStatus::Incomplete,
+            Err(_) => InputStatus::Invalid,
+        }
+    }
 }
@@ -31,6 +31,7 @@ pub mod prelude {
     //! Conveniently import commonly used types
 
     pub use shrs_core_macros::*;
+    pub use shrs_lang::ShellOptions;
 
     pub use crate::{
         alias::{Alias, AliasInfo, AliasRule, AliasRuleCtx},
@@ -43,7 +44,7 @@ pub mod prelude {
         hooks::{events::*, Hook, HookEventMarker, Hooks, IntoHook},
         jobs::{JobId, JobInfo, Jobs},
         keybinding::*,
-        lang::{Lang, PosixLang},
+        lang::{InputStatus, Lang, PosixLang},
         output_writer::OutputWriter,
         plugin::*,
         prompt_content_queue::{PromptContent, PromptContentQueue},
@@ -2,7 +2,7 @@
 
 use std::{
     fmt::Display,
-    io::{stderr, stdout, BufWriter, Write},
+    io::{stderr, stdout, BufWriter, IsTerminal, Write},
 };
 
 use crossterm::{
@@ -14,7 +14,8 @@ use shrs_utils::StyledBuf;
 ///
 /// Printing in handlers should be done through `OutputWriter`,
 /// which automatically uses the configured out and err colors.
-/// It also records output in commands so that it can be collected into `CmdOutput`
+/// It also records output in commands so that it can be collected into `CmdOutput`.
+/// When output is redirected to a file or pipe it is written without any styling.
 /// ```
 /// # use shrs_core::prelude::*;
 /// fn hello(mut out: StateMut<OutputWriter>) -> anyhow::Result<()> {
@@ -55,8 +56,12 @@ impl OutputWriter {
             self.err.push_str(s.to_string().as_str());
         }
 
-        self.stderr
-            .queue(PrintStyledContent(self.err_style.apply(s.to_string())))?;
+        if self.stderr.get_ref().is_terminal() {
+            self.stderr
+                .queue(PrintStyledContent(self.err_style.apply(s.to_string())))?;
+        } else {
+            write!(self.stderr, "{s}")?;
+        }
         self.stderr.flush()?;
         Ok(())
     }
@@ -64,7 +69,7 @@ impl OutputWriter {
     /// Calls eprint, then prints a newline
     pub fn eprintln<T: Display>(&mut self, s: T) -> anyhow::Result<()> {
         self.eprint(s)?;
-        self.eprint("\r\n")?;
+        self.eprint(newline(self.stderr.get_ref().is_terminal()))?;
         Ok(())
     }
 
@@ -73,15 +78,33 @@ impl OutputWriter {
         if self.collecting {
             self.out.push_str(s.to_string().as_str());
         }
-        self.stdout
-            .queue(PrintStyledContent(self.out_style.apply(s.to_string())))?;
+        if self.stdout.get_ref().is_terminal() {
+            self.stdout
+                .queue(PrintStyledContent(self.out_style.apply(s.to_string())))?;
+        } else {
+            write!(self.stdout, "{s}")?;
+        }
+        self.stdout.flush()?;
+        Ok(())
+    }
+    /// Prints bytes to stdout, which don't have to be valid UTF-8
+    ///
+    /// Output that isn't valid UTF-8 is written as it is, without any styling.
+    pub fn print_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<()> {
+        if let Ok(s) = std::str::from_utf8(bytes) {
+            return self.print(s);
+        }
+        if self.collecting {
+            self.out.push_str(&String::from_utf8_lossy(bytes));
+        }
+        self.stdout.write_all(bytes)?;
         self.stdout.flush()?;
         Ok(())
     }
     ///Calls print, then prints a newline.
     pub fn println<T: Display>(&mut self, s: T) -> anyhow::Result<()> {
         self.print(s)?;
-        self.print("\r\n")?;
+        self.print(newline(self.stdout.get_ref().is_terminal()))?;
         Ok(())
     }
 
@@ -93,13 +116,18 @@ impl OutputWriter {
     /// If there are multiple lines, if will print \r\n between them.
     pub fn print_buf(&mut self, buf: StyledBuf) -> anyhow::Result<()> {
         let lines = buf.lines();
+        let is_terminal = self.stdout.get_ref().is_terminal();
 
         for (i, line) in lines.iter().enumerate() {
             if i > 0 {
-                self.print("\r\n")?;
+                self.print(newline(is_terminal))?;
             }
             for span in line {
-                self.stdout.queue(PrintStyledContent(span.clone()))?;
+                if is_terminal {
+                    self.stdout.queue(PrintStyledContent(span.clone()))?;
+                } else {
+                    write!(self.stdout, "{}", span.content())?;
+                }
             }
         }
         self.stdout.flush()?;
@@ -107,6 +135,15 @@ impl OutputWriter {
         Ok(())
     }
 }
+/// Line ending to print, the terminal may be in raw mode where a newline doesn't return the cursor
+fn newline(is_terminal: bool) -> &'static str {
+    if is_terminal {
+        "\r\n"
+    } else {
+        "\n"
+    }
+}
+
 impl Default for OutputWriter {
     fn default() -> Self {
         Self::new(ContentStyle::new().white(), ContentStyle::new().red())
@@ -7,7 +7,7 @@ use std::marker::PhantomData;
 
 use anyhow::Result;
 use crossterm::style::{Color, ContentStyle};
-use shrs_lang::{Lexer, Token};
+use shrs_lang::{Lexer, Parser, Token};
 use shrs_utils::StyledBuf;
 
 use super::super::prelude::Param;
@@ -85,11 +85,13 @@ impl Highlighter for SyntaxHighlighter {
 
 /// Implementation of a highlighter for the shrs language.
 ///
-/// Utilizes the shrs parser to parse and highlight various tokens based on their type
+/// Utilizes the shrs parser to parse and highlight various tokens based on their type, and marks
+/// the part of the line that has a syntax error
 pub struct ShrsTheme {
     cmd_style: ContentStyle,
     string_style: ContentStyle,
     reserved_style: ContentStyle,
+    error_style: ContentStyle,
 }
 impl Default for ShrsTheme {
     fn default() -> Self {
@@ -106,6 +108,10 @@ impl Default for ShrsTheme {
                 foreground_color: Some(Color::Yellow),
                 ..Default::default()
             },
+            ContentStyle {
+                foreground_color: Some(Color::Red),
+                ..Default::default()
+            },
         )
     }
 }
@@ -114,11 +120,13 @@ impl ShrsTheme {
         cmd_style: ContentStyle,
         string_style: ContentStyle,
         reserved_style: ContentStyle,
+        error_style: ContentStyle,
     ) -> Self {
         ShrsTheme {
             cmd_style,
             string_style,
             reserved_style,
+            error_style,
         }
     }
 }
@@ -176,6 +184,16 @@ impl SyntaxTheme for ShrsTheme {
                 }
             }
         }
+
+        // errors that go away with more input are expected while the command is being typed
+        if let Err(e) = Parser::default().parse(Lexer::new(content.as_str())) {
+            if !e.is_incomplete() {
+                let span = e.span();
+                let start = content[..span.start].chars().count();
+                let end = start + content[span].chars().count();
+                buf.apply_style_in_range(start..end, self.error_style);
+            }
+        }
     }
 }
 
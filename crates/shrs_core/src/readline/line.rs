@@ -17,10 +17,12 @@ use shrs_vi::{Action, Command, Motion, Parser};
 use super::painter::Painter;
 use crate::{
     prelude::{
-        BufferHistory, Completer, Completion, CompletionCtx, DefaultMenuState, InsertPosition,
-        LineModeSwitchEvent, ReplaceMethod, Shell, Snippets, Theme, ViCursorBuffer,
+        cmdname_pred, default_format, BufferHistory, Completer, Completion, CompletionCtx,
+        DefaultMenuState, InsertPosition, LineModeSwitchEvent, ReplaceMethod, Runtime, Shell,
+        ShellOptions, Snippets, Theme, ViCursorBuffer,
     },
     prompt_content_queue::PromptContentQueue,
+    shell::exit_shell,
     state::States,
 };
 
@@ -392,10 +394,10 @@ impl Line {
             }) => {
                 // if current input is empty exit the shell, otherwise treat it as enter
                 if states.get::<LineContents>().cb.is_empty() {
-                    // TODO maybe unify exiting the shell
                     let _ = disable_raw_mode(); // TODO this is temp fix, should be more graceful way of
                                                 // handling cleanup code
-                    std::process::exit(0);
+                    self.painter.newline()?;
+                    exit_shell(sh, states, 0);
                 } else {
                     states.get_mut::<Box<dyn BufferHistory>>().clear();
                     self.painter.newline()?;
@@ -570,9 +572,10 @@ impl Line {
             }) => {
                 self.history_up(sh, states)?;
             },
+            // normal mode is only entered with vi style line editing
             Event::Key(KeyEvent {
                 code: KeyCode::Esc, ..
-            }) => {
+            }) if states.get::<ShellOptions>().vi => {
                 self.to_normal_mode(sh, states)?;
                 states
                     .get_mut::<Box<dyn BufferHistory>>()
@@ -762,24 +765,29 @@ impl Line {
 
     // recalculate the current completions
     fn populate_completions(&mut self, states: &mut States) -> anyhow::Result<()> {
-        // TODO IFS
         let line_contents = states.get::<LineContents>();
         let cursor = line_contents.cb.cursor();
 
-        let args = line_contents
-            .cb
-            .slice(..cursor)
-            .as_str()
-            .unwrap()
-            .split(' ')
-            .map(|s| s.to_owned())
-            .collect::<Vec<_>>();
+        let args = shrs_lang::split_words(line_contents.cb.slice(..cursor).as_str().unwrap());
         *states.get_mut::<CurrentWord>() =
             CurrentWord(args.last().unwrap_or(&String::new()).clone());
 
         let comp_states = CompletionCtx::new(args);
 
-        let completions = states.get::<Box<dyn Completer>>().complete(&comp_states);
+        let mut completions = states.get::<Box<dyn Completer>>().complete(&comp_states);
+
+        // functions are defined while the shell is running, so no completion rule knows about them
+        if cmdname_pred(&comp_states) {
+            let cur_word = comp_states.cur_word().cloned().unwrap_or_default();
+            let functions = states
+                .get::<Runtime>()
+                .functions
+                .keys()
+                .filter(|name| name.starts_with(&cur_word))
+                .cloned()
+                .collect::<Vec<_>>();
+            completions.extend(default_format(functions));
+        }
         let completions = completions.iter().collect::<Vec<_>>();
 
         let menuitems = completions
@@ -4,7 +4,9 @@
 //! with a [`ShellBuilder`], configure to your liking, finalize it, and then run the shell.
 
 use std::{
-    env,
+    collections::HashMap,
+    env, fs,
+    io::{self, IsTerminal},
     path::{Path, PathBuf},
     process::ExitStatus,
     time::Instant,
@@ -14,7 +16,8 @@ use anyhow::anyhow;
 use dirs::home_dir;
 use log::{info, warn};
 use pino_deref::Deref;
-use shrs_job::JobManager;
+use shrs_job::{initialize_job_control, JobManager};
+use shrs_lang::{ast, TrapCondition};
 
 use crate::{
     commands::{Command, Commands},
@@ -112,16 +115,26 @@ pub struct Runtime {
     pub working_dir: PathBuf,
     /// Environment variables
     pub env: Env,
+    /// Shell variables, unlike environment variables these are not passed on to commands
+    pub vars: HashMap<String, String>,
+    /// Indexed arrays, like the `BASH_REMATCH` array set by `[[ ... =~ ... ]]`
+    pub arrays: HashMap<String, Vec<String>>,
     /// Name of the shell or shell script
     pub name: String,
-    /// Arguments this shell was called with
+    /// Positional parameters, initially the arguments this shell was called with
     pub args: Vec<String>,
     /// Exit status of most recent pipeline
     pub exit_status: i32,
+    /// Process ID of the most recent background command
+    pub last_background_pid: Option<u32>,
     /// Directory for configuration files
     pub config_dir: PathBuf,
-    // /// List of defined functions
-    // pub functions: HashMap<String, Box<ast::Command>>,
+    /// List of defined functions
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub functions: HashMap<String, Box<ast::Command>>,
+    /// Commands set with the `trap` builtin
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pub traps: HashMap<TrapCondition, String>,
 }
 
 /// Unified shell config struct
@@ -273,8 +286,13 @@ impl ShellConfig {
     /// Start up the shell
     ///
     /// This function contains the main loop of the shell and thus will block for the entire
-    /// execution of the shell.
+    /// execution of the shell. When the shell is started with a script, as `shell script.sh
+    /// args...` or `shell -c 'command' name args...`, or with commands piped to stdin, the
+    /// commands are ran without the line editor and the process exits with the status of the
+    /// last one.
     pub fn run(mut self) -> anyhow::Result<()> {
+        let invocation = Invocation::from_args(env::args());
+
         // TODO some default values for Context and Runtime are duplicated by the #[builder(default = "...")]
         // calls in ShellBuilder, so we are sort of defining the full default here. Maybe end
         // up implementing Default for Context and Runtime
@@ -303,16 +321,19 @@ impl ShellConfig {
         }
         let rt = Runtime {
             env: self.env,
+            vars: HashMap::new(),
+            arrays: HashMap::new(),
             working_dir: std::env::current_dir().unwrap(),
-            // TODO currently hardcoded
-            name: "shrs".into(),
-            // TODO currently unused (since we have not implemented functions etc)
-            args: vec![],
+            name: invocation.name,
+            args: invocation.args,
             exit_status: 0,
+            last_background_pid: None,
             config_dir: self.config_dir,
-            // functions: self.functions,
+            functions: HashMap::new(),
+            traps: HashMap::new(),
         };
         self.states.insert(rt);
+        self.states.insert(ShellOptions::default());
         self.states.insert(self.alias);
         self.states.insert(OutputWriter::new(
             self.theme.out_style,
@@ -368,7 +389,187 @@ impl ShellConfig {
             }
         }
 
-        run_shell(&mut self.states, &mut sh, &mut self.readline)
+        match invocation.script {
+            Some(script) => {
+                self.states.get_mut::<JobManager>().set_job_control(false);
+                // like other shells, aliases aren't expanded in scripts unless they are enabled
+                self.states.get_mut::<ShellOptions>().expand_aliases = false;
+                let status = run_script(&mut self.states, &mut sh, script);
+                exit_shell(&sh, &self.states, status)
+            },
+            None => {
+                initialize_job_control()?;
+                run_shell(&mut self.states, &mut sh, &mut self.readline)
+            },
+        }
+    }
+}
+
+/// What the shell was asked to run by the arguments it was started with
+struct Invocation {
+    /// Name of the shell or script, which is `$0`
+    name: String,
+    /// Positional parameters
+    args: Vec<String>,
+    /// Commands to run without the line editor, the shell is interactive without them
+    script: Option<Script>,
+}
+
+/// Where the commands of a script come from
+enum Script {
+    /// Commands given as a whole, by `-c` or in a file
+    Text(String),
+    /// Commands piped to the shell, which are read a line at a time so that the commands can
+    /// read the rest of the input themselves
+    Stdin,
+}
+
+impl Script {
+    /// Read the next line of the script, including its newline
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        match self {
+            Script::Text(text) => {
+                let end = text.find('\n').map_or(text.len(), |i| i + 1);
+                let line = text.drain(..end).collect::<String>();
+                Ok(Some(line).filter(|line| !line.is_empty()))
+            },
+            Script::Stdin => {
+                // stdin is read unbuffered, nothing past the end of the line is consumed
+                let mut line = vec![];
+                let mut byte = [0];
+                loop {
+                    match nix::unistd::read(0, &mut byte) {
+                        Ok(0) => break,
+                        Ok(_) => {
+                            line.push(byte[0]);
+                            if byte[0] == b'\n' {
+                                break;
+                            }
+                        },
+                        Err(nix::errno::Errno::EINTR) => {},
+                        Err(e) => return Err(e.into()),
+                    }
+                }
+                let line = String::from_utf8_lossy(&line).into_owned();
+                Ok(Some(line).filter(|line| !line.is_empty()))
+            },
+        }
+    }
+}
+
+impl Invocation {
+    /// Parse the arguments of the shell, which are either `-c command [name [args...]]`,
+    /// `script [args...]` or nothing
+    ///
+    /// Errors are reported like other shells do, by exiting with status 2 for bad usage and with
+    /// 127 or 126 when the script can't be read.
+    fn from_args(mut args: impl Iterator<Item = String>) -> Self {
+        let shell_name = args.next().unwrap_or_else(|| "shrs".into());
+        let usage_error = |msg: String| -> ! {
+            eprintln!("{shell_name}: {msg}");
+            std::process::exit(2)
+        };
+
+        match args.next() {
+            Some(flag) if flag == "-c" => {
+                let Some(command) = args.next() else {
+                    usage_error("-c: option requires an argument".into())
+                };
+                Invocation {
+                    name: args.next().unwrap_or_else(|| shell_name.clone()),
+                    args: args.collect(),
+                    script: Some(Script::Text(command)),
+                }
+            },
+            Some(flag) if flag.starts_with('-') => usage_error(format!("{flag}: invalid option")),
+            Some(path) => match fs::read_to_string(&path) {
+                Ok(script) => Invocation {
+                    name: path,
+                    args: args.collect(),
+                    script: Some(Script::Text(script)),
+                },
+                Err(e) => {
+                    eprintln!("{shell_name}: {path}: {e}");
+                    std::process::exit(if e.kind() == io::ErrorKind::NotFound {
+                        127
+                    } else {
+                        126
+                    })
+                },
+            },
+            // commands piped to the shell are ran as a script
+            None if !io::stdin().is_terminal() => Invocation {
+                name: shell_name,
+                args: vec![],
+                script: Some(Script::Stdin),
+            },
+            None => Invocation {
+                name: shell_name,
+                args: vec![],
+                script: None,
+            },
+        }
+    }
+}
+
+/// Exit the shell with the given status, after running the EXIT trap if one is set
+pub(crate) fn exit_shell(sh: &Shell, states: &States, status: i32) -> ! {
+    let action = states
+        .get_mut::<Runtime>()
+        .traps
+        .remove(&TrapCondition::Exit);
+    if let Some(action) = action {
+        states.get_mut::<Runtime>().exit_status = status;
+        let _ = sh.lang.eval(sh, states, action);
+    }
+    std::process::exit(status)
+}
+
+/// Run a script without the line editor, returning the exit status of the last command
+///
+/// Each command is evaluated as soon as all of its lines have been read, so commands can change
+/// how the ones after them are parsed, like by defining aliases. Errors that stop a script, like
+/// syntax errors, end it with their exit status as POSIX requires of non-interactive shells.
+fn run_script(states: &mut States, sh: &mut Shell, mut script: Script) -> i32 {
+    let mut status = 0;
+    let mut input = String::new();
+    loop {
+        let line = match script.read_line() {
+            Ok(line) => line,
+            Err(e) => {
+                eprintln!(
+                    "{}: failed reading commands: {e}",
+                    states.get::<Runtime>().name
+                );
+                return 2;
+            },
+        };
+        let at_end = line.is_none();
+        input.push_str(&line.unwrap_or_default());
+        if input.trim().is_empty() {
+            if at_end {
+                return status;
+            }
+            input.clear();
+            continue;
+        }
+
+        // the command is evaluated once it is complete, errors are reported by evaluating it
+        let input_status = sh.lang.input_status(sh, states, &input);
+        if input_status == InputStatus::Incomplete && !at_end {
+            continue;
+        }
+        status = match sh.lang.eval(sh, states, std::mem::take(&mut input)) {
+            Ok(cmd_output) => shrs_lang::exit_code(cmd_output.status),
+            Err(e) => {
+                eprintln!("{}: {e:#}", states.get::<Runtime>().name);
+                1
+            },
+        };
+        sh.apply_queue(states);
+        if input_status != InputStatus::Complete || at_end {
+            return status;
+        }
     }
 }
 
@@ -387,27 +588,6 @@ fn run_shell(
     loop {
         let line = readline.read_line(sh, states);
 
-        // attempt to expand alias
-        // TODO IFS
-        let mut words = line
-            .split(' ')
-            .map(|s| s.trim_start_matches("\\\n").trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect::<Vec<_>>();
-        if let Some(first) = words.get_mut(0) {
-            let alias_ctx = AliasRuleCtx {
-                alias_name: first,
-                sh,
-                states,
-            };
-
-            // Currently only use the last alias, can also render a menu
-            if let Some(expanded) = states.get::<Alias>().get(&alias_ctx).last() {
-                *first = expanded.to_string();
-            }
-        }
-        let line = words.join(" ");
-
         // TODO not sure if hook should run here (since not all vars are expanded yet)
         let hook_ctx = BeforeCommandCtx {
             raw_command: line.clone(),
@@ -415,35 +595,20 @@ fn run_shell(
         };
         sh.run_hooks_in_core(states, hook_ctx);
 
-        // Retrieve command name or return immediately (empty command)
-        let cmd_name = match words.first() {
-            Some(cmd_name) => cmd_name,
-            None => continue,
-        };
-
-        let builtin_cmd = sh
-            .builtins
-            .iter()
-            .find(|(builtin_name, _)| *builtin_name == cmd_name)
-            .map(|(_, builtin_cmd)| builtin_cmd);
+        // Return immediately on an empty command
+        if line.trim().is_empty() {
+            continue;
+        }
 
+        // builtins are ran by the language, along with the rest of the command
         let mut cmd_output: CmdOutput = CmdOutput::error();
         states.get_mut::<OutputWriter>().begin_collecting();
-        if let Some(builtin_cmd) = builtin_cmd {
-            let output = builtin_cmd.run(sh, states, &words);
-            match output {
-                Ok(o) => cmd_output = o,
-                Err(e) => eprintln!("error: {e:?}"),
-            }
-
-            sh.apply_queue(states);
-        } else {
-            let output = sh.lang.eval(sh, states, line.clone());
-            match output {
-                Ok(o) => cmd_output = o,
-                Err(e) => eprintln!("error: {e:?}"),
-            }
+        let output = sh.lang.eval(sh, states, line.clone());
+        match output {
+            Ok(o) => cmd_output = o,
+            Err(e) => eprintln!("{}: {e:#}", states.get::<Runtime>().name),
         }
+        sh.apply_queue(states);
         let (out, err) = states.get_mut::<OutputWriter>().end_collecting();
         cmd_output.stdout(out);
         cmd_output.stderr(err);
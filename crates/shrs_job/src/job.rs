@@ -63,14 +63,38 @@ impl<T: Job> AsJob for T {
     }
 }
 
-#[derive(Default)]
 pub struct JobManager {
     jobs: Vec<JobImpl>,
     job_count: u32,
     current_job: Option<JobId>,
+    job_control: bool,
+}
+
+impl Default for JobManager {
+    fn default() -> Self {
+        Self {
+            jobs: vec![],
+            job_count: 0,
+            current_job: None,
+            job_control: true,
+        }
+    }
 }
 
 impl JobManager {
+    /// Whether jobs are put in their own process groups and given control of the terminal
+    pub fn job_control(&self) -> bool {
+        self.job_control
+    }
+
+    /// Enable or disable job control
+    ///
+    /// Job control is usually disabled in subshells, where all processes stay in the process group
+    /// of the subshell.
+    pub fn set_job_control(&mut self, job_control: bool) {
+        self.job_control = job_control;
+    }
+
     pub fn create_job(&mut self, input: &str, process_group: ProcessGroup) -> JobId {
         let job_id = self.get_next_job_id();
         self.jobs.push(JobImpl::new(
@@ -123,7 +147,9 @@ impl JobManager {
             self.jobs[job_index].set_last_running_in_foreground(true);
             let job_pgid = self.jobs[job_index].pgid();
             let job_tmodes = self.jobs[job_index].tmodes().clone();
-            let _terminal_state = job_pgid.map(|pgid| TerminalState::new(Pid::from_raw(pgid)));
+            let _terminal_state = job_pgid
+                .filter(|_| self.job_control)
+                .map(|pgid| TerminalState::new(Pid::from_raw(pgid)));
 
             // Send the job a continue signal if necessary
             if cont {
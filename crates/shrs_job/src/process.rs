@@ -1,12 +1,26 @@
 use std::{
     ffi::OsStr,
-    fmt, iter,
-    os::fd::AsRawFd,
+    fmt,
+    fs::File,
+    iter,
+    os::fd::{AsRawFd, RawFd},
     process::{Child, Command, ExitStatus},
+    sync::{Mutex, PoisonError},
 };
+#[cfg(unix)]
+use std::os::unix::process::ExitStatusExt;
 
 use log::*;
-use nix::libc::{STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO};
+use nix::{
+    errno::Errno,
+    libc::{dev_t, ino_t, STDERR_FILENO, STDIN_FILENO, STDOUT_FILENO},
+    sys::{
+        signal::{self, SigHandler, Signal},
+        stat::fstat,
+        wait::{waitpid, WaitPidFlag, WaitStatus},
+    },
+    unistd::{self, ForkResult, Pid},
+};
 
 use super::{io::Stdin, pid_t, util, Output};
 use crate::warn_if_err;
@@ -20,6 +34,12 @@ impl From<u32> for ProcessId {
     }
 }
 
+impl From<ProcessId> for u32 {
+    fn from(value: ProcessId) -> Self {
+        value.0
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum ProcessStatus {
     Running,
@@ -188,13 +208,253 @@ impl Process for ExternalProcess {
     }
 }
 
+/// Process that is a forked copy of the shell
+struct ForkedProcess {
+    argv: String,
+    pid: Pid,
+    status: ProcessStatus,
+    status_code: Option<ExitStatus>,
+}
+
+impl ForkedProcess {
+    fn update(&mut self, wait_status: WaitStatus) -> Option<ExitStatus> {
+        let exit_status = match wait_status {
+            WaitStatus::Exited(_, code) => ExitStatus::from_raw(code << 8),
+            WaitStatus::Signaled(_, signal, core_dumped) => {
+                ExitStatus::from_raw(signal as i32 | if core_dumped { 0x80 } else { 0 })
+            },
+            _ => return None,
+        };
+        self.status = ProcessStatus::Completed;
+        self.status_code = Some(exit_status);
+        Some(exit_status)
+    }
+}
+
+impl Process for ForkedProcess {
+    fn id(&self) -> Option<ProcessId> {
+        Some((self.pid.as_raw() as u32).into())
+    }
+
+    fn argv(&self) -> String {
+        self.argv.clone()
+    }
+
+    fn status(&self) -> ProcessStatus {
+        self.status
+    }
+
+    fn status_code(&self) -> Option<ExitStatus> {
+        self.status_code
+    }
+
+    fn stdout(&mut self) -> Option<Stdin> {
+        None
+    }
+
+    fn kill(&mut self) -> anyhow::Result<()> {
+        signal::kill(self.pid, Signal::SIGKILL)?;
+        Ok(())
+    }
+
+    fn wait(&mut self) -> anyhow::Result<ExitStatus> {
+        if let Some(exit_status) = self.status_code {
+            return Ok(exit_status);
+        }
+        loop {
+            if let Some(exit_status) = self.update(waitpid(self.pid, None)?) {
+                return Ok(exit_status);
+            }
+        }
+    }
+
+    fn try_wait(&mut self) -> anyhow::Result<Option<ExitStatus>> {
+        if self.status_code.is_some() {
+            return Ok(self.status_code);
+        }
+        let wait_status = waitpid(self.pid, Some(WaitPidFlag::WNOHANG))?;
+        Ok(self.update(wait_status))
+    }
+}
+
+/// Signals the shell handles itself for job control, which processes it starts get the default
+/// handling of
+const JOB_CONTROL_SIGNALS: [Signal; 6] = [
+    Signal::SIGINT,
+    Signal::SIGQUIT,
+    Signal::SIGTSTP,
+    Signal::SIGTTIN,
+    Signal::SIGTTOU,
+    Signal::SIGCHLD,
+];
+
+/// Descriptors the shell opened for the pipes and redirections of commands, along with the device
+/// and inode they refer to so that ones that were closed and reused for something else can be
+/// told apart
+static COMMAND_FDS: Mutex<Vec<(RawFd, dev_t, ino_t)>> = Mutex::new(Vec::new());
+
+/// Record a descriptor the shell opened for the pipes or redirections of a command
+///
+/// Since there is no exec after forking, a forked shell would otherwise keep pipes used by other
+/// commands open. See [`run_forked`].
+pub fn track_command_fd(fd: RawFd) {
+    if let Ok(stat) = fstat(fd) {
+        let mut fds = COMMAND_FDS.lock().unwrap_or_else(PoisonError::into_inner);
+        fds.retain(|(tracked, ..)| *tracked != fd);
+        fds.push((fd, stat.st_dev, stat.st_ino));
+    }
+}
+
+/// Descriptors recorded with [`track_command_fd`] that are still open
+fn command_fds() -> Vec<RawFd> {
+    let mut fds = COMMAND_FDS.lock().unwrap_or_else(PoisonError::into_inner);
+    fds.retain(|(fd, dev, ino)| {
+        fstat(*fd).is_ok_and(|stat| stat.st_dev == *dev && stat.st_ino == *ino)
+    });
+    fds.iter().map(|(fd, ..)| *fd).collect()
+}
+
+/// Put a process in a process group
+///
+/// Both the shell and the new process do this to avoid a race, so whichever of them is second can
+/// find that the process has already exec'd (`EACCES`) or exited (`ESRCH`), which is not an error.
+fn set_process_group(pid: Pid, pgid: Pid) -> nix::Result<()> {
+    match unistd::setpgid(pid, pgid) {
+        Err(Errno::EACCES | Errno::ESRCH) => Ok(()),
+        result => result,
+    }
+}
+
+/// Fork the shell and run `f` in the child as part of the process group `pgid`
+///
+/// The child process exits with the code returned by `f`. This is used to run commands that are
+/// evaluated by the shell itself (such as subshells) in their own process, so they can take part
+/// in pipelines and be put in the background. See [`run_external_command`] for a description of
+/// the other parameters.
+///
+/// Descriptors recorded with [`track_command_fd`] are closed in the child, apart from the ones
+/// its redirections are set up on. Anything else the shell has open, like a log file, stays
+/// usable.
+#[allow(clippy::too_many_arguments)]
+pub fn run_forked<F, S>(
+    f: F,
+    argv: S,
+    stdin: Stdin,
+    stdout: Output,
+    stderr: Output,
+    fds: Vec<(RawFd, Option<File>)>,
+    pgid: Option<u32>,
+    foreground: bool,
+) -> std::io::Result<(Box<dyn Process>, Option<u32>)>
+where
+    F: FnOnce() -> i32,
+    S: ToString,
+{
+    let shell_terminal = util::get_terminal();
+    let command_fds = command_fds();
+
+    match unsafe { unistd::fork() }? {
+        ForkResult::Child => {
+            let pid = unistd::getpid();
+            let pgid = pgid.map(|pgid| Pid::from_raw(pgid as pid_t)).unwrap_or(pid);
+            let temp_result = set_process_group(pid, pgid);
+            warn_if_err!(
+                temp_result,
+                "failed to set pgid ({}) for forked process",
+                pgid
+            );
+            if foreground {
+                let temp_result = unistd::tcsetpgrp(shell_terminal, pgid);
+                warn_if_err!(temp_result, "failed to give terminal to forked process");
+            }
+            for signal in JOB_CONTROL_SIGNALS {
+                let temp_result = unsafe { signal::signal(signal, SigHandler::SigDfl) };
+                warn_if_err!(temp_result, "failed to reset handler of {}", signal);
+            }
+
+            // Redirections have to be set up by hand since there is no exec
+            let redirect = || -> std::io::Result<()> {
+                let stdin_fd = stdin.as_raw_fd();
+                if stdin_fd != STDIN_FILENO {
+                    unistd::dup2(stdin_fd, STDIN_FILENO)?;
+                }
+                for (stream, fd) in [(stdout, STDOUT_FILENO), (stderr, STDERR_FILENO)] {
+                    match stream {
+                        Output::Inherit => {},
+                        Output::File(file) => {
+                            unistd::dup2(file.as_raw_fd(), fd)?;
+                        },
+                        Output::FileDescriptor(src) => {
+                            unistd::dup2(src, fd)?;
+                        },
+                        Output::CreatePipe => panic!("forked process can not create pipe"),
+                    }
+                }
+                for (fd, file) in fds.iter() {
+                    match file {
+                        Some(file) => {
+                            unistd::dup2(file.as_raw_fd(), *fd)?;
+                        },
+                        None => unistd::close(*fd)?,
+                    }
+                }
+                Ok(())
+            };
+            let code = match redirect() {
+                Ok(_) => {
+                    let redirected = |fd: &RawFd| fds.iter().any(|(target, _)| target == fd);
+                    for fd in command_fds.iter().filter(|fd| **fd > STDERR_FILENO) {
+                        if !redirected(fd) {
+                            let _ = unistd::close(*fd);
+                        }
+                    }
+                    f()
+                },
+                Err(e) => {
+                    eprintln!("{e}");
+                    1
+                },
+            };
+            std::process::exit(code)
+        },
+        ForkResult::Parent { child } => {
+            let pgid = pgid.unwrap_or(child.as_raw() as u32);
+            let temp_result = set_process_group(child, Pid::from_raw(pgid as pid_t));
+            warn_if_err!(
+                temp_result,
+                "failed to set pgid ({}) for pid ({})",
+                child,
+                pgid
+            );
+
+            let process = ForkedProcess {
+                argv: argv.to_string(),
+                pid: child,
+                status: ProcessStatus::Running,
+                status_code: None,
+            };
+            Ok((Box::new(process), Some(pgid)))
+        },
+    }
+}
+
+/// Spawn an external program as part of the process group `pgid`
+///
+/// `env` holds variables that are added to the environment of the program only. `fds` lists
+/// additional file descriptors to set up in the child after the standard streams, applied in
+/// order. `Some(file)` duplicates the file onto the descriptor and `None` closes it.
+/// Only processes of a `foreground` job are given control of the terminal.
+#[allow(clippy::too_many_arguments)]
 pub fn run_external_command<S1, S2>(
     program: S1,
     args: &[S2],
+    env: &[(String, String)],
     stdin: Stdin,
     stdout: Output,
     stderr: Output,
+    fds: Vec<(RawFd, Option<File>)>,
     pgid: Option<u32>,
+    foreground: bool,
 ) -> std::io::Result<(Box<dyn Process>, Option<u32>)>
 where
     S1: AsRef<str>,
@@ -202,13 +462,9 @@ where
 {
     use std::os::unix::process::CommandExt;
 
-    use nix::{
-        sys::signal::{self, SigHandler, Signal},
-        unistd::{self, Pid},
-    };
-
     let mut command = Command::new(OsStr::new(program.as_ref()));
     command.args(args.iter().map(AsRef::as_ref).map(OsStr::new));
+    command.envs(env.iter().map(|(var, val)| (var, val)));
 
     // Configure stdout and stderr (e.g. pipe, redirect). Do not configure
     // stdin, as we need to do that manually in before_exec *after* we have
@@ -241,9 +497,9 @@ where
                 let pid = unistd::getpid();
                 let pgid = pgid.map(|pgid| Pid::from_raw(pgid as i32)).unwrap_or(pid);
 
-                // setpgid(2) failing represents programmer error, e.g.
-                // 1) invalid pid or pgid
-                unistd::setpgid(pid, pgid).expect("setpgid failed");
+                // setpgid(2) failing for any other reason than losing the race with the
+                // shell means the process group can't be joined, so the command isn't run
+                set_process_group(pid, pgid)?;
 
                 // Set the terminal control device in both parent process (see job
                 // manager) and child process to avoid race conditions
@@ -255,21 +511,16 @@ where
                 //     pipeline, as Command::stdin configures stdin *before*
                 //     before_exec runs.
                 // 3) incorrect permissions
-                unistd::tcsetpgrp(shell_terminal, pgid).expect("tcsetpgrp failed");
+                if foreground {
+                    unistd::tcsetpgrp(shell_terminal, pgid).expect("tcsetpgrp failed");
+                }
 
                 // Reset job control signal handling back to default
                 // signal(3) failing represents programmer error, e.g.
                 // 1) signal argument is not a valid signal number
                 // 2) an attempt is made to supply a signal handler for a
                 //    signal that cannot have a custom signal handler
-                for signal in [
-                    Signal::SIGINT,
-                    Signal::SIGQUIT,
-                    Signal::SIGTSTP,
-                    Signal::SIGTTIN,
-                    Signal::SIGTTOU,
-                    Signal::SIGCHLD,
-                ] {
+                for signal in JOB_CONTROL_SIGNALS {
                     signal::signal(signal, SigHandler::SigDfl)
                         .expect("failed to reset signal handler");
                 }
@@ -298,6 +549,16 @@ where
                 }
             }
 
+            // Remaining redirections, these can fail due to user error (e.g. bad file descriptor)
+            for (fd, file) in fds.iter() {
+                match file {
+                    Some(file) => {
+                        unistd::dup2(file.as_raw_fd(), *fd)?;
+                    },
+                    None => unistd::close(*fd)?,
+                }
+            }
+
             Ok(())
         });
     }
@@ -305,7 +566,7 @@ where
     let child = match command.spawn() {
         Ok(child) => child,
         Err(e) => {
-            if job_control_is_enabled {
+            if job_control_is_enabled && foreground {
                 warn!("failed to spawn child, resetting terminal's pgrp");
                 // see above comment for tcsetpgrp(2) failing being programmer
                 // error
@@ -317,7 +578,7 @@ where
 
     let pgid = pgid.unwrap_or_else(|| child.id());
     if job_control_is_enabled {
-        let temp_result = unistd::setpgid(
+        let temp_result = set_process_group(
             Pid::from_raw(child.id() as pid_t),
             Pid::from_raw(pgid as pid_t),
         );
@@ -372,3 +633,48 @@ where
     ))
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::OpenOptions, io::Write};
+
+    use nix::{
+        fcntl::{fcntl, FcntlArg, OFlag},
+        unistd::{close, pipe2},
+    };
+
+    use super::{run_forked, track_command_fd};
+    use crate::{Output, Stdin};
+
+    #[test]
+    fn forked_fds() {
+        let (read, write) = pipe2(OFlag::O_CLOEXEC).unwrap();
+        track_command_fd(write);
+        // opened close-on-exec like a log file would be
+        let log = OpenOptions::new().write(true).open("/dev/null").unwrap();
+
+        let child = move || {
+            let closed = fcntl(write, FcntlArg::F_GETFD).is_err();
+            let logged = writeln!(&log, "log").is_ok();
+            match closed && logged {
+                true => 0,
+                false => 1,
+            }
+        };
+        let (mut proc, _) = run_forked(
+            child,
+            "test",
+            Stdin::Inherit,
+            Output::Inherit,
+            Output::Inherit,
+            vec![],
+            None,
+            false,
+        )
+        .unwrap();
+        assert!(proc.wait().unwrap().success());
+
+        close(read).unwrap();
+        close(write).unwrap();
+    }
+}
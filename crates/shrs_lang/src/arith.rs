@@ -0,0 +1,554 @@
+//! Arithmetic expressions used by arithmetic expansion `$(( ... ))`
+//!
+//! Supports the signed integer arithmetic of the C language, with variables taken from and
+//! assigned to the shell's variables.
+
+use thiserror::Error;
+
+use crate::ShellContext;
+
+/// Maximum number of variables referring to other expressions that are followed
+const MAX_DEPTH: usize = 64;
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub(crate) enum ArithError {
+    #[error("syntax error: {0}")]
+    Syntax(String),
+    #[error("division by 0")]
+    DivisionByZero,
+    #[error("{0}: invalid number")]
+    InvalidNumber(String),
+    #[error("{0}: expression recursion level exceeded")]
+    Recursion(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Num(i64),
+    Name(String),
+    Op(&'static str),
+}
+
+/// Operators, longest first so that they are matched greedily
+const OPERATORS: &[&str] = &[
+    "<<=", ">>=", "++", "--", "<<", ">>", "<=", ">=", "==", "!=", "&&", "||", "*=", "/=", "%=",
+    "+=", "-=", "&=", "^=", "|=", "+", "-", "*", "/", "%", "<", ">", "&", "^", "|", "!", "~", "?",
+    ":", "=", "(", ")",
+];
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Num(n) => write!(f, "{n}"),
+            Token::Name(name) => write!(f, "{name}"),
+            Token::Op(op) => write!(f, "{op}"),
+        }
+    }
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, ArithError> {
+    let mut tokens = vec![];
+    let mut rest = expr.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Num(parse_number(&rest[..len])?));
+            len
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let len = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Name(rest[..len].to_string()));
+            len
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+            tokens.push(Token::Op(op));
+            op.len()
+        } else {
+            return Err(ArithError::Syntax(format!(
+                "invalid arithmetic operator (error token is \"{rest}\")"
+            )));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Parse an integer constant, which can be in decimal, octal (leading `0`) or hexadecimal
+/// (leading `0x`)
+fn parse_number(s: &str) -> Result<i64, ArithError> {
+    let (digits, radix) = if let Some(hex) = s.strip_prefix("0x").or(s.strip_prefix("0X")) {
+        (hex, 16)
+    } else if s.len() > 1 && s.starts_with('0') {
+        (&s[1..], 8)
+    } else {
+        (s, 10)
+    };
+    // wrap around on overflow like other shells do
+    u64::from_str_radix(digits, radix)
+        .map(|n| n as i64)
+        .map_err(|_| ArithError::InvalidNumber(s.to_string()))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BinOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Shl,
+    Shr,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    BitAnd,
+    BitXor,
+    BitOr,
+}
+
+impl BinOp {
+    fn from_op(op: &str) -> Option<Self> {
+        let op = match op {
+            "*" => BinOp::Mul,
+            "/" => BinOp::Div,
+            "%" => BinOp::Rem,
+            "+" => BinOp::Add,
+            "-" => BinOp::Sub,
+            "<<" => BinOp::Shl,
+            ">>" => BinOp::Shr,
+            "<" => BinOp::Lt,
+            "<=" => BinOp::Le,
+            ">" => BinOp::Gt,
+            ">=" => BinOp::Ge,
+            "==" => BinOp::Eq,
+            "!=" => BinOp::Ne,
+            "&" => BinOp::BitAnd,
+            "^" => BinOp::BitXor,
+            "|" => BinOp::BitOr,
+            _ => return None,
+        };
+        Some(op)
+    }
+
+    /// Binding power of the operator, higher binds tighter
+    fn precedence(self) -> u8 {
+        match self {
+            BinOp::Mul | BinOp::Div | BinOp::Rem => 10,
+            BinOp::Add | BinOp::Sub => 9,
+            BinOp::Shl | BinOp::Shr => 8,
+            BinOp::Lt | BinOp::Le | BinOp::Gt | BinOp::Ge => 7,
+            BinOp::Eq | BinOp::Ne => 6,
+            BinOp::BitAnd => 5,
+            BinOp::BitXor => 4,
+            BinOp::BitOr => 3,
+        }
+    }
+
+    fn apply(self, a: i64, b: i64) -> Result<i64, ArithError> {
+        let n = match self {
+            BinOp::Mul => a.wrapping_mul(b),
+            BinOp::Div if b == 0 => return Err(ArithError::DivisionByZero),
+            BinOp::Div => a.wrapping_div(b),
+            BinOp::Rem if b == 0 => return Err(ArithError::DivisionByZero),
+            BinOp::Rem => a.wrapping_rem(b),
+            BinOp::Add => a.wrapping_add(b),
+            BinOp::Sub => a.wrapping_sub(b),
+            BinOp::Shl => a.wrapping_shl(b as u32),
+            BinOp::Shr => a.wrapping_shr(b as u32),
+            BinOp::Lt => (a < b) as i64,
+            BinOp::Le => (a <= b) as i64,
+            BinOp::Gt => (a > b) as i64,
+            BinOp::Ge => (a >= b) as i64,
+            BinOp::Eq => (a == b) as i64,
+            BinOp::Ne => (a != b) as i64,
+            BinOp::BitAnd => a & b,
+            BinOp::BitXor => a ^ b,
+            BinOp::BitOr => a | b,
+        };
+        Ok(n)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Num(i64),
+    Var(String),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    BitNot(Box<Expr>),
+    Binary(BinOp, Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Cond(Box<Expr>, Box<Expr>, Box<Expr>),
+    /// Assignment to a variable, optionally combined with a binary operator like `+=`
+    Assign(String, Option<BinOp>, Box<Expr>),
+    /// `++` or `--` before or after a variable
+    Step {
+        var: String,
+        delta: i64,
+        prefix: bool,
+    },
+}
+
+/// Recursive descent parser over the tokens of an expression
+struct ExprParser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn eat(&mut self, op: &str) -> bool {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn unexpected(&self) -> ArithError {
+        match self.tokens.get(self.pos) {
+            Some(token) => ArithError::Syntax(format!("unexpected token `{token}'")),
+            None => ArithError::Syntax(String::from("operand expected")),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr, ArithError> {
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> Result<Expr, ArithError> {
+        if let (Some(Token::Name(name)), Some(Token::Op(op))) =
+            (self.tokens.get(self.pos), self.tokens.get(self.pos + 1))
+        {
+            let bin_op = match op.strip_suffix('=') {
+                Some("") => Some(None),
+                Some(op) if !matches!(op, "=" | "!" | "<" | ">") => BinOp::from_op(op).map(Some),
+                _ => None,
+            };
+            if let Some(bin_op) = bin_op {
+                let name = name.clone();
+                self.pos += 2;
+                let value = self.assignment()?;
+                return Ok(Expr::Assign(name, bin_op, Box::new(value)));
+            }
+        }
+        self.conditional()
+    }
+
+    fn conditional(&mut self) -> Result<Expr, ArithError> {
+        let cond = self.logical_or()?;
+        if !self.eat("?") {
+            return Ok(cond);
+        }
+        let then = self.expr()?;
+        if !self.eat(":") {
+            return Err(ArithError::Syntax(String::from("`:' expected")));
+        }
+        let otherwise = self.assignment()?;
+        Ok(Expr::Cond(
+            Box::new(cond),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    fn logical_or(&mut self) -> Result<Expr, ArithError> {
+        let mut lhs = self.logical_and()?;
+        while self.eat("||") {
+            lhs = Expr::Or(Box::new(lhs), Box::new(self.logical_and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn logical_and(&mut self) -> Result<Expr, ArithError> {
+        let mut lhs = self.binary(0)?;
+        while self.eat("&&") {
+            lhs = Expr::And(Box::new(lhs), Box::new(self.binary(0)?));
+        }
+        Ok(lhs)
+    }
+
+    /// Parse binary operators binding tighter than `min_precedence` with precedence climbing
+    fn binary(&mut self, min_precedence: u8) -> Result<Expr, ArithError> {
+        let mut lhs = self.unary()?;
+        while let Some(op) = self.peek_op().and_then(BinOp::from_op) {
+            if op.precedence() <= min_precedence {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(op.precedence())?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ArithError> {
+        let Some(op) = self.peek_op() else {
+            return self.postfix();
+        };
+        match op {
+            "++" | "--" => {
+                self.pos += 1;
+                match self.tokens.get(self.pos) {
+                    Some(Token::Name(var)) => {
+                        let var = var.clone();
+                        self.pos += 1;
+                        Ok(Expr::Step {
+                            var,
+                            delta: if op == "++" { 1 } else { -1 },
+                            prefix: true,
+                        })
+                    },
+                    // not an increment, such as in `--5`
+                    _ => {
+                        let operand = self.unary()?;
+                        match op {
+                            "++" => Ok(operand),
+                            _ => Ok(Expr::Neg(Box::new(Expr::Neg(Box::new(operand))))),
+                        }
+                    },
+                }
+            },
+            "+" => {
+                self.pos += 1;
+                self.unary()
+            },
+            "-" => {
+                self.pos += 1;
+                Ok(Expr::Neg(Box::new(self.unary()?)))
+            },
+            "!" => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            },
+            "~" => {
+                self.pos += 1;
+                Ok(Expr::BitNot(Box::new(self.unary()?)))
+            },
+            _ => self.postfix(),
+        }
+    }
+
+    fn postfix(&mut self) -> Result<Expr, ArithError> {
+        let primary = self.primary()?;
+        if let Expr::Var(var) = &primary {
+            for (op, delta) in [("++", 1), ("--", -1)] {
+                if self.eat(op) {
+                    return Ok(Expr::Step {
+                        var: var.clone(),
+                        delta,
+                        prefix: false,
+                    });
+                }
+            }
+        }
+        Ok(primary)
+    }
+
+    fn primary(&mut self) -> Result<Expr, ArithError> {
+        let expr = match self.tokens.get(self.pos) {
+            Some(Token::Num(n)) => Expr::Num(*n),
+            Some(Token::Name(name)) => Expr::Var(name.clone()),
+            Some(Token::Op("(")) => {
+                self.pos += 1;
+                let expr = self.expr()?;
+                if !self.eat(")") {
+                    return Err(ArithError::Syntax(String::from("`)' expected")));
+                }
+                return Ok(expr);
+            },
+            _ => return Err(self.unexpected()),
+        };
+        self.pos += 1;
+        Ok(expr)
+    }
+}
+
+fn parse(expr: &str) -> Result<Expr, ArithError> {
+    let mut parser = ExprParser {
+        tokens: tokenize(expr)?,
+        pos: 0,
+    };
+    // an empty expression evaluates to 0
+    if parser.tokens.is_empty() {
+        return Ok(Expr::Num(0));
+    }
+    let parsed = parser.expr()?;
+    if parser.pos != parser.tokens.len() {
+        return Err(parser.unexpected());
+    }
+    Ok(parsed)
+}
+
+/// Variables that arithmetic expressions read and assign
+pub(crate) trait ArithVars {
+    fn get_var(&self, name: &str) -> Option<String>;
+
+    fn set_var(&mut self, name: &str, value: &str);
+}
+
+impl<C: ShellContext + ?Sized> ArithVars for C {
+    fn get_var(&self, name: &str) -> Option<String> {
+        ShellContext::get_var(self, name)
+    }
+
+    fn set_var(&mut self, name: &str, value: &str) {
+        ShellContext::set_var(self, name, value)
+    }
+}
+
+/// Evaluate an arithmetic expression, reading and assigning variables in `vars`
+pub(crate) fn eval_arith<V: ArithVars + ?Sized>(
+    vars: &mut V,
+    expr: &str,
+) -> Result<i64, ArithError> {
+    eval_arith_depth(vars, expr, 0)
+}
+
+fn eval_arith_depth<V: ArithVars + ?Sized>(
+    vars: &mut V,
+    expr: &str,
+    depth: usize,
+) -> Result<i64, ArithError> {
+    let parsed = parse(expr)?;
+    Evaluator { vars, depth }.eval(&parsed)
+}
+
+struct Evaluator<'a, V: ?Sized> {
+    vars: &'a mut V,
+    /// Number of variables being expanded to get to this expression
+    depth: usize,
+}
+
+impl<V: ArithVars + ?Sized> Evaluator<'_, V> {
+    /// Value of a variable, which can itself contain an expression
+    fn var(&mut self, name: &str) -> Result<i64, ArithError> {
+        let value = self.vars.get_var(name).unwrap_or_default();
+        if value.trim().is_empty() {
+            return Ok(0);
+        }
+        if self.depth >= MAX_DEPTH {
+            return Err(ArithError::Recursion(name.to_string()));
+        }
+        eval_arith_depth(self.vars, &value, self.depth + 1)
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<i64, ArithError> {
+        let n = match expr {
+            Expr::Num(n) => *n,
+            Expr::Var(name) => self.var(name)?,
+            Expr::Neg(expr) => self.eval(expr)?.wrapping_neg(),
+            Expr::Not(expr) => (self.eval(expr)? == 0) as i64,
+            Expr::BitNot(expr) => !self.eval(expr)?,
+            Expr::Binary(op, a, b) => {
+                let a = self.eval(a)?;
+                let b = self.eval(b)?;
+                op.apply(a, b)?
+            },
+            Expr::And(a, b) => (self.eval(a)? != 0 && self.eval(b)? != 0) as i64,
+            Expr::Or(a, b) => (self.eval(a)? != 0 || self.eval(b)? != 0) as i64,
+            Expr::Cond(cond, then, otherwise) => match self.eval(cond)? {
+                0 => self.eval(otherwise)?,
+                _ => self.eval(then)?,
+            },
+            Expr::Assign(var, op, value) => {
+                let value = self.eval(value)?;
+                let value = match op {
+                    Some(op) => op.apply(self.var(var)?, value)?,
+                    None => value,
+                };
+                self.vars.set_var(var, &value.to_string());
+                value
+            },
+            Expr::Step { var, delta, prefix } => {
+                let old = self.var(var)?;
+                let new = old.wrapping_add(*delta);
+                self.vars.set_var(var, &new.to_string());
+                if *prefix {
+                    new
+                } else {
+                    old
+                }
+            },
+        };
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::{eval_arith, ArithError, ArithVars};
+
+    #[derive(Default)]
+    struct Vars(HashMap<String, String>);
+
+    impl ArithVars for Vars {
+        fn get_var(&self, name: &str) -> Option<String> {
+            self.0.get(name).cloned()
+        }
+
+        fn set_var(&mut self, name: &str, value: &str) {
+            self.0.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    #[test]
+    fn operators() {
+        let mut vars = Vars::default();
+        let mut eval = |expr| eval_arith(&mut vars, expr);
+        assert_eq!(eval("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval("7 / 2 + 7 % 2"), Ok(4));
+        assert_eq!(eval("-3 - -3"), Ok(0));
+        assert_eq!(eval("1 << 4 | 1 ^ 3 & 2"), Ok(19));
+        assert_eq!(eval("2 < 3 && 3 <= 3 && !(1 == 2)"), Ok(1));
+        assert_eq!(eval("0 || 0"), Ok(0));
+        assert_eq!(eval("~0"), Ok(-1));
+        assert_eq!(eval("1 ? 2 : 3"), Ok(2));
+        assert_eq!(eval("0 ? 2 : 0 ? 3 : 4"), Ok(4));
+        assert_eq!(eval("0x1f + 010"), Ok(39));
+        assert_eq!(eval(""), Ok(0));
+        assert_eq!(eval("1 / 0"), Err(ArithError::DivisionByZero));
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1").is_err());
+        assert!(eval("08").is_err());
+    }
+
+    #[test]
+    fn variables() {
+        let mut vars = Vars::default();
+        vars.set_var("i", "5");
+        assert_eq!(eval_arith(&mut vars, "i + 1"), Ok(6));
+        assert_eq!(eval_arith(&mut vars, "i++"), Ok(5));
+        assert_eq!(eval_arith(&mut vars, "++i"), Ok(7));
+        assert_eq!(eval_arith(&mut vars, "i -= 2"), Ok(5));
+        assert_eq!(eval_arith(&mut vars, "j = k = i * 2"), Ok(10));
+        assert_eq!(vars.get_var("k").as_deref(), Some("10"));
+        assert_eq!(eval_arith(&mut vars, "unset + 1"), Ok(1));
+
+        // short circuiting skips assignments
+        assert_eq!(eval_arith(&mut vars, "0 && (i = 100)"), Ok(0));
+        assert_eq!(eval_arith(&mut vars, "1 ? i : (i = 100)"), Ok(5));
+
+        // variables can contain expressions
+        vars.set_var("e", "j + 1");
+        assert_eq!(eval_arith(&mut vars, "e * 2"), Ok(22));
+        vars.set_var("r", "r");
+        assert!(eval_arith(&mut vars, "r").is_err());
+    }
+}
@@ -1,11 +1,66 @@
 //! Structs that make up the parsed AST of the POSIX shell language
 
+use std::ops::{Deref, Range};
+
+/// Byte range of the source that a node was parsed from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Span { start, end }
+    }
+
+    /// Source text that was parsed into the node, `None` if the span is not inside of `input`
+    pub fn text(self, input: &str) -> Option<&str> {
+        input.get(self.start..self.end)
+    }
+}
+
+impl From<Span> for Range<usize> {
+    fn from(span: Span) -> Self {
+        span.start..span.end
+    }
+}
+
+/// Word of a command, as written in the source
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    pub text: String,
+    pub span: Span,
+}
+
+impl Word {
+    pub fn new(text: impl ToString, start: usize, end: usize) -> Self {
+        Word {
+            text: text.to_string(),
+            span: Span::new(start, end),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+}
+
+impl Deref for Word {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.text
+    }
+}
+
 /// File redirection
 #[derive(Debug, Clone)]
 pub struct Redirect {
     pub n: Option<usize>,
     pub file: String,
     pub mode: RedirectMode,
+    pub span: Span,
 }
 
 /// File redirection modes
@@ -13,11 +68,49 @@ pub struct Redirect {
 pub enum RedirectMode {
     Read,
     Write,
-    ReadAppend,
+    /// Write with `>|`, which overwrites existing files even when `set -C` is on
+    Clobber,
     WriteAppend,
     ReadDup,
     WriteDup,
     ReadWrite,
+    /// Here-document, the file of the redirection is its delimiter
+    ///
+    /// ```sh
+    /// cat <<EOF
+    /// hello $USER
+    /// EOF
+    /// ```
+    /// The body is expanded like a double quoted word unless part of the delimiter is quoted.
+    HereDoc {
+        body: String,
+        expand: bool,
+    },
+    /// Here-string, the file of the redirection is the word given to the command as input
+    ///
+    /// ```sh
+    /// cat <<< "hello $USER"
+    /// ```
+    HereString,
+}
+
+impl RedirectMode {
+    /// Here-document with the given delimiter, as written, and body
+    ///
+    /// With `strip_tabs`, as for `<<-`, leading tabs are removed from each line of the body.
+    pub fn here_doc(delimiter: &str, body: &str, strip_tabs: bool) -> Self {
+        let body = match strip_tabs {
+            true => body
+                .split_inclusive('\n')
+                .map(|line| line.trim_start_matches('\t'))
+                .collect(),
+            false => body.to_string(),
+        };
+        RedirectMode::HereDoc {
+            body,
+            expand: !delimiter.contains(['\'', '"', '\\']),
+        }
+    }
 }
 
 /// Assignment
@@ -25,6 +118,7 @@ pub enum RedirectMode {
 pub struct Assign {
     pub var: String,
     pub val: String,
+    pub span: Span,
 }
 
 /// Separator character between commands
@@ -36,8 +130,24 @@ pub enum SeparatorOp {
     Semi,
 }
 
+/// Command along with where it is in the source
 #[derive(Debug, Clone)]
-pub enum Command {
+pub struct Command {
+    pub kind: CommandKind,
+    pub span: Span,
+}
+
+impl Command {
+    pub fn new(kind: CommandKind, start: usize, end: usize) -> Self {
+        Command {
+            kind,
+            span: Span::new(start, end),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CommandKind {
     /// Basic command
     ///
     /// ```sh
@@ -46,7 +156,7 @@ pub enum Command {
     Simple {
         assigns: Vec<Assign>,
         redirects: Vec<Redirect>,
-        args: Vec<String>,
+        args: Vec<Word>,
     },
 
     /// Two commands joined by a pipe
@@ -107,25 +217,64 @@ pub enum Command {
     /// For loops
     For {
         name: String,
-        wordlist: Vec<String>,
+        wordlist: Vec<Word>,
         body: Box<Command>,
     },
 
     /// Case statements
-    Case { word: String, arms: Vec<CaseArm> },
+    Case { word: Word, arms: Vec<CaseArm> },
 
     /// Function definition
     Fn { fname: String, body: Box<Command> },
 
+    /// Conditional expression, whose operands are not split or matched against files
+    /// ```sh
+    /// [[ -f $file && $name == *.rs ]]
+    /// ```
+    Conditional(CondExpr),
+
     /// No op
     None,
 }
 
+/// Expression inside of a `[[ ... ]]` conditional command, along with where it is in the source
+#[derive(Debug, Clone)]
+pub struct CondExpr {
+    pub kind: CondExprKind,
+    pub span: Span,
+}
+
+impl CondExpr {
+    pub fn new(kind: CondExprKind, start: usize, end: usize) -> Self {
+        CondExpr {
+            kind,
+            span: Span::new(start, end),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub enum CondExprKind {
+    /// Word that is true if it is not empty
+    Word(String),
+    /// Unary operator like `-f` or `-z` followed by its operand
+    Unary(String, String),
+    /// Binary operator like `==`, `=~` or `-lt` between its operands
+    Binary(String, String, String),
+    /// Negation of an expression, `! expr`
+    Not(Box<CondExpr>),
+    /// `expr && expr`
+    And(Box<CondExpr>, Box<CondExpr>),
+    /// `expr || expr`
+    Or(Box<CondExpr>, Box<CondExpr>),
+}
+
 /// Represents each match arm in case statement
 #[derive(Debug, Clone)]
 pub struct CaseArm {
-    pub pattern: Vec<String>,
+    pub pattern: Vec<Word>,
     pub body: Box<Command>,
+    pub span: Span,
 }
 
 /// Corresponds to a condition followed by a body to execute in an 'if' or 'elif' block
@@ -133,4 +282,5 @@ pub struct CaseArm {
 pub struct Condition {
     pub cond: Box<Command>,
     pub body: Box<Command>,
+    pub span: Span,
 }
@@ -0,0 +1,170 @@
+//! Brace expansion, which generates several words from a single one before any of the other
+//! expansions happen
+//!
+//! ```sh
+//! echo a{b,c}d    # abd acd
+//! echo {1..3}     # 1 2 3
+//! echo {01..10..3} # 01 04 07 10
+//! ```
+
+use crate::word::unquoted_chars;
+
+/// Expand the brace expressions in a word into the words they stand for, in order
+///
+/// Braces that are quoted, or that don't contain a comma or a valid sequence expression, are left
+/// as they are.
+pub(crate) fn expand_braces(word: &str) -> Vec<String> {
+    let unquoted = unquoted_chars(word);
+    for (n, &(open, c)) in unquoted.iter().enumerate() {
+        if c != '{' {
+            continue;
+        }
+        let Some((close, commas)) = find_closing_brace(&unquoted[n + 1..]) else {
+            continue;
+        };
+        let items = if commas.is_empty() {
+            match sequence(&word[open + 1..close]) {
+                Some(items) => items,
+                None => continue,
+            }
+        } else {
+            let mut start = open + 1;
+            let mut items = vec![];
+            for comma in commas.into_iter().chain([close]) {
+                items.push(word[start..comma].to_string());
+                start = comma + 1;
+            }
+            items
+        };
+
+        // the items can contain more braces to expand, as can the rest of the word
+        let (preamble, postscript) = (&word[..open], &word[close + 1..]);
+        return items
+            .iter()
+            .flat_map(|item| expand_braces(&format!("{preamble}{item}{postscript}")))
+            .collect();
+    }
+    vec![word.to_string()]
+}
+
+/// Find the brace closing one that was just opened, along with the commas that separate the items
+/// directly inside of it
+fn find_closing_brace(after: &[(usize, char)]) -> Option<(usize, Vec<usize>)> {
+    let mut depth = 0;
+    let mut commas = vec![];
+    for &(i, c) in after {
+        match c {
+            '{' => depth += 1,
+            '}' if depth == 0 => return Some((i, commas)),
+            '}' => depth -= 1,
+            ',' if depth == 0 => commas.push(i),
+            _ => {},
+        }
+    }
+    None
+}
+
+/// Items of a sequence expression like `1..10`, `10..1..2` or `a..z`
+///
+/// Numbers are padded with zeros to the same width if either end of the sequence is written with
+/// a leading zero.
+fn sequence(expr: &str) -> Option<Vec<String>> {
+    let (start, end, step) = match expr.split("..").collect::<Vec<_>>()[..] {
+        [start, end] => (start, end, 1),
+        [start, end, step] => (
+            start,
+            end,
+            step.parse::<i64>().ok()?.unsigned_abs().max(1) as usize,
+        ),
+        _ => return None,
+    };
+
+    if let (Ok(first), Ok(last)) = (start.parse::<i64>(), end.parse::<i64>()) {
+        let padded = |s: &str| {
+            let digits = s.trim_start_matches(['-', '+']);
+            digits.len() > 1 && digits.starts_with('0')
+        };
+        let width = match padded(start) || padded(end) {
+            true => start.len().max(end.len()),
+            false => 0,
+        };
+        return Some(
+            range(first, last, step)
+                .into_iter()
+                .map(|n| format!("{n:0width$}"))
+                .collect(),
+        );
+    }
+
+    let mut start_chars = start.chars();
+    let mut end_chars = end.chars();
+    match (
+        start_chars.next(),
+        start_chars.next(),
+        end_chars.next(),
+        end_chars.next(),
+    ) {
+        (Some(first), None, Some(last), None)
+            if first.is_ascii_alphabetic() && last.is_ascii_alphabetic() =>
+        {
+            Some(
+                range(first as i64, last as i64, step)
+                    .into_iter()
+                    .map(|c| char::from(c as u8).to_string())
+                    .collect(),
+            )
+        },
+        _ => None,
+    }
+}
+
+/// Numbers from `first` to `last`, counting down if `last` is the smaller one
+fn range(first: i64, last: i64, step: usize) -> Vec<i64> {
+    match first <= last {
+        true => (first..=last).step_by(step).collect(),
+        false => (last..=first).rev().step_by(step).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::expand_braces;
+
+    #[test]
+    fn lists() {
+        assert_eq!(expand_braces("src/{bin,lib}"), ["src/bin", "src/lib"]);
+        assert_eq!(expand_braces("file{,.bak}"), ["file", "file.bak"]);
+        assert_eq!(expand_braces("{a,b}{c,d}"), ["ac", "ad", "bc", "bd"]);
+        assert_eq!(expand_braces("{a,b{1,2},c}"), ["a", "b1", "b2", "c"]);
+        assert_eq!(expand_braces("{a,{b}}"), ["a", "{b}"]);
+    }
+
+    #[test]
+    fn sequences() {
+        assert_eq!(expand_braces("{1..4}"), ["1", "2", "3", "4"]);
+        assert_eq!(expand_braces("{3..1}"), ["3", "2", "1"]);
+        assert_eq!(expand_braces("{01..10..3}"), ["01", "04", "07", "10"]);
+        assert_eq!(expand_braces("{-2..2..2}"), ["-2", "0", "2"]);
+        assert_eq!(expand_braces("{a..e..2}"), ["a", "c", "e"]);
+        assert_eq!(expand_braces("x{1..2}{a,b}"), ["x1a", "x1b", "x2a", "x2b"]);
+    }
+
+    #[test]
+    fn not_expanded() {
+        for word in [
+            "{a}",
+            "{}",
+            "{a,b",
+            "'{a,b}'",
+            r"\{a,b}",
+            "\"{a,b}\"",
+            "${a,b}",
+            "{1..}",
+            "{a..3}",
+        ] {
+            assert_eq!(expand_braces(word), [word]);
+        }
+        assert_eq!(expand_braces("{x,'a,b'}"), ["x", "'a,b'"]);
+        assert_eq!(expand_braces("{$(echo a,b),c}"), ["$(echo a,b)", "c"]);
+    }
+}
@@ -0,0 +1,377 @@
+//! Conditional expressions, as evaluated by the `test` and `[` builtins and by `[[ ... ]]`
+//! commands
+//!
+//! ```sh
+//! test -d src -a ! -e build
+//! [ "$answer" = yes ]
+//! [[ $file == *.rs && $(wc -l < $file) -gt 100 ]]
+//! ```
+
+use std::{
+    fs,
+    os::unix::fs::{FileTypeExt, MetadataExt},
+};
+
+use nix::unistd::{access, getegid, geteuid, isatty, AccessFlags};
+use regex::Regex;
+
+use crate::{
+    arith::eval_arith,
+    ast::{CondExpr, CondExprKind},
+    eval::Evaluator,
+    glob::Pattern,
+    PosixError,
+};
+
+/// Operators that test a single string or file
+const UNARY_OPS: [&str; 21] = [
+    "-b", "-c", "-d", "-e", "-f", "-g", "-G", "-h", "-k", "-L", "-n", "-O", "-p", "-r", "-s", "-S",
+    "-t", "-u", "-w", "-x", "-z",
+];
+
+/// Operators that compare two strings, integers or files
+const BINARY_OPS: [&str; 14] = [
+    "=", "==", "!=", "<", ">", "-eq", "-ne", "-lt", "-le", "-gt", "-ge", "-nt", "-ot", "-ef",
+];
+
+/// Operators that compare integers
+const INTEGER_OPS: [&str; 6] = ["-eq", "-ne", "-lt", "-le", "-gt", "-ge"];
+
+/// Evaluate the arguments of the `test` builtin, returning an error message if they aren't a
+/// valid expression
+///
+/// Expressions of up to four arguments are evaluated as POSIX specifies, so that operands which
+/// look like operators are still taken as operands, as in `test ! = x`. Longer expressions are
+/// combined with `!`, `-a`, `-o` and parentheses, where `-a` binds tighter than `-o`.
+pub fn eval_test(args: &[String]) -> Result<bool, String> {
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    test_args(&args)
+}
+
+fn test_args(args: &[&str]) -> Result<bool, String> {
+    match *args {
+        [] => Ok(false),
+        [arg] => Ok(!arg.is_empty()),
+        ["!", arg] => Ok(arg.is_empty()),
+        [op, operand] if is_unary_op(op) => Ok(unary_test(op, operand)),
+        [op, _] => Err(format!("{op}: unary operator expected")),
+        [left, op, right] if is_binary_op(op) => binary_test(left, op, right),
+        [left, "-a", right] => Ok(!left.is_empty() && !right.is_empty()),
+        [left, "-o", right] => Ok(!left.is_empty() || !right.is_empty()),
+        ["!", ..] if args.len() <= 4 => Ok(!test_args(&args[1..])?),
+        ["(", .., ")"] if args.len() <= 4 => test_args(&args[1..args.len() - 1]),
+        [_, op, _] => Err(format!("{op}: binary operator expected")),
+        _ => {
+            let mut parser = TestParser { args, pos: 0 };
+            let result = parser.or()?;
+            match parser.args.get(parser.pos) {
+                Some(arg) => Err(format!("{arg}: too many arguments")),
+                None => Ok(result),
+            }
+        },
+    }
+}
+
+/// Recursive descent parser for expressions made up of more than four arguments
+struct TestParser<'a> {
+    args: &'a [&'a str],
+    pos: usize,
+}
+
+impl<'a> TestParser<'a> {
+    fn peek(&self, offset: usize) -> Option<&'a str> {
+        self.args.get(self.pos + offset).copied()
+    }
+
+    fn eat(&mut self, arg: &str) -> bool {
+        let matched = self.peek(0) == Some(arg);
+        if matched {
+            self.pos += 1;
+        }
+        matched
+    }
+
+    fn or(&mut self) -> Result<bool, String> {
+        let mut result = self.and()?;
+        while self.eat("-o") {
+            // both sides are parsed even if the result is already known
+            let right = self.and()?;
+            result = result || right;
+        }
+        Ok(result)
+    }
+
+    fn and(&mut self) -> Result<bool, String> {
+        let mut result = self.not()?;
+        while self.eat("-a") {
+            let right = self.not()?;
+            result = result && right;
+        }
+        Ok(result)
+    }
+
+    fn not(&mut self) -> Result<bool, String> {
+        match self.eat("!") {
+            true => Ok(!self.not()?),
+            false => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<bool, String> {
+        let Some(arg) = self.peek(0) else {
+            return Err(String::from("argument expected"));
+        };
+        if let (Some(op), Some(right)) = (self.peek(1), self.peek(2)) {
+            if is_binary_op(op) {
+                self.pos += 3;
+                return binary_test(arg, op, right);
+            }
+        }
+        if arg == "(" {
+            self.pos += 1;
+            let result = self.or()?;
+            if !self.eat(")") {
+                return Err(String::from("`)' expected"));
+            }
+            return Ok(result);
+        }
+        if let Some(operand) = self.peek(1).filter(|_| is_unary_op(arg)) {
+            self.pos += 2;
+            return Ok(unary_test(arg, operand));
+        }
+        self.pos += 1;
+        Ok(!arg.is_empty())
+    }
+}
+
+pub(crate) fn is_unary_op(op: &str) -> bool {
+    UNARY_OPS.contains(&op)
+}
+
+pub(crate) fn is_binary_op(op: &str) -> bool {
+    BINARY_OPS.contains(&op)
+}
+
+/// Evaluate a unary operator like `-f` or `-z`, which has to be one of [`UNARY_OPS`]
+pub(crate) fn unary_test(op: &str, operand: &str) -> bool {
+    match op {
+        "-n" => !operand.is_empty(),
+        "-z" => operand.is_empty(),
+        "-t" => operand
+            .trim()
+            .parse()
+            .is_ok_and(|fd| isatty(fd).unwrap_or(false)),
+        "-r" => access(operand, AccessFlags::R_OK).is_ok(),
+        "-w" => access(operand, AccessFlags::W_OK).is_ok(),
+        "-x" => access(operand, AccessFlags::X_OK).is_ok(),
+        // the other tests follow symbolic links
+        "-h" | "-L" => fs::symlink_metadata(operand).is_ok_and(|m| m.file_type().is_symlink()),
+        op => {
+            let Ok(metadata) = fs::metadata(operand) else {
+                return false;
+            };
+            let file_type = metadata.file_type();
+            match op {
+                "-e" => true,
+                "-f" => file_type.is_file(),
+                "-d" => file_type.is_dir(),
+                "-b" => file_type.is_block_device(),
+                "-c" => file_type.is_char_device(),
+                "-p" => file_type.is_fifo(),
+                "-S" => file_type.is_socket(),
+                "-s" => metadata.len() > 0,
+                "-u" => metadata.mode() & 0o4000 != 0,
+                "-g" => metadata.mode() & 0o2000 != 0,
+                "-k" => metadata.mode() & 0o1000 != 0,
+                "-O" => metadata.uid() == geteuid().as_raw(),
+                "-G" => metadata.gid() == getegid().as_raw(),
+                _ => false,
+            }
+        },
+    }
+}
+
+/// Evaluate a binary operator, which has to be one of [`BINARY_OPS`]
+pub(crate) fn binary_test(left: &str, op: &str, right: &str) -> Result<bool, String> {
+    match op {
+        "=" | "==" => Ok(left == right),
+        "!=" => Ok(left != right),
+        "<" => Ok(left < right),
+        ">" => Ok(left > right),
+        "-nt" | "-ot" | "-ef" => Ok(compare_files(left, op, right)),
+        op => Ok(compare_integers(
+            parse_integer(left)?,
+            op,
+            parse_integer(right)?,
+        )),
+    }
+}
+
+fn parse_integer(s: &str) -> Result<i64, String> {
+    s.trim()
+        .parse()
+        .map_err(|_| format!("{s}: integer expression expected"))
+}
+
+fn compare_integers(left: i64, op: &str, right: i64) -> bool {
+    match op {
+        "-eq" => left == right,
+        "-ne" => left != right,
+        "-lt" => left < right,
+        "-le" => left <= right,
+        "-gt" => left > right,
+        "-ge" => left >= right,
+        _ => false,
+    }
+}
+
+/// Compare the modification times of two files with `-nt` and `-ot`, or check if they are the
+/// same file with `-ef`
+///
+/// A file that exists is newer than one that doesn't.
+fn compare_files(left: &str, op: &str, right: &str) -> bool {
+    let (left, right) = (fs::metadata(left).ok(), fs::metadata(right).ok());
+    let modified = |metadata: &Option<fs::Metadata>| {
+        metadata
+            .as_ref()
+            .map(|metadata| (metadata.mtime(), metadata.mtime_nsec()))
+    };
+    match op {
+        "-nt" => left.is_some() && modified(&left) > modified(&right),
+        "-ot" => right.is_some() && modified(&left) < modified(&right),
+        "-ef" => match (left, right) {
+            (Some(left), Some(right)) => left.dev() == right.dev() && left.ino() == right.ino(),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+impl Evaluator<'_> {
+    /// Evaluate the expression of a `[[ ... ]]` command
+    ///
+    /// Operands are expanded without being split or matched against files. The right side of `==`
+    /// and `!=` is a pattern, in which extended patterns are recognized even without `extglob`,
+    /// and the right side of `=~` a regular expression. Quoted characters are taken literally in
+    /// both. Operands of integer comparisons are arithmetic expressions.
+    pub(crate) fn eval_conditional(&mut self, expr: &CondExpr) -> Result<bool, PosixError> {
+        match &expr.kind {
+            CondExprKind::Word(word) => Ok(!self.expand_word(word)?.is_empty()),
+            CondExprKind::Unary(op, operand) => {
+                if !is_unary_op(op) {
+                    return Err(PosixError::Conditional(format!(
+                        "{op}: unary operator expected"
+                    )));
+                }
+                Ok(unary_test(op, &self.expand_word(operand)?))
+            },
+            CondExprKind::Binary(left, op, right) => {
+                let left = self.expand_word(left)?;
+                match op.as_str() {
+                    "=" | "==" | "!=" => {
+                        // extended patterns are always recognized here
+                        let pattern = Pattern::new(&self.expand_pattern(right)?, true);
+                        Ok(pattern.matches(&left) == (op != "!="))
+                    },
+                    "=~" => self.match_regex(&left, right),
+                    op if INTEGER_OPS.contains(&op) => {
+                        let right = self.expand_word(right)?;
+                        let left = self.arithmetic_operand(&left)?;
+                        let right = self.arithmetic_operand(&right)?;
+                        Ok(compare_integers(left, op, right))
+                    },
+                    op if is_binary_op(op) => {
+                        let right = self.expand_word(right)?;
+                        binary_test(&left, op, &right).map_err(PosixError::Conditional)
+                    },
+                    op => Err(PosixError::Conditional(format!(
+                        "{op}: binary operator expected"
+                    ))),
+                }
+            },
+            CondExprKind::Not(expr) => Ok(!self.eval_conditional(expr)?),
+            CondExprKind::And(a, b) => Ok(self.eval_conditional(a)? && self.eval_conditional(b)?),
+            CondExprKind::Or(a, b) => Ok(self.eval_conditional(a)? || self.eval_conditional(b)?),
+        }
+    }
+
+    fn arithmetic_operand(&mut self, operand: &str) -> Result<i64, PosixError> {
+        eval_arith(&mut *self.ctx, operand)
+            .map_err(|e| PosixError::Conditional(format!("{}: {e}", operand.trim())))
+    }
+
+    /// Match a string against the regular expression on the right of `=~`, setting the
+    /// `BASH_REMATCH` array to the text that matched and the text each group matched
+    fn match_regex(&mut self, text: &str, word: &str) -> Result<bool, PosixError> {
+        let regex = self.expand_regex(word)?;
+        let regex = Regex::new(&regex)
+            .map_err(|_| PosixError::Conditional(format!("{regex}: invalid regular expression")))?;
+        let matches = match regex.captures(text) {
+            Some(captures) => captures
+                .iter()
+                .map(|group| group.map_or("", |group| group.as_str()).to_string())
+                .collect(),
+            None => vec![],
+        };
+        let matched = !matches.is_empty();
+        self.ctx.set_array("BASH_REMATCH", matches);
+        Ok(matched)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::eval_test;
+
+    fn test(args: &str) -> Result<bool, String> {
+        eval_test(&args.split(' ').map(String::from).collect::<Vec<_>>())
+    }
+
+    #[test]
+    fn strings_and_integers() {
+        assert_eq!(eval_test(&[]), Ok(false));
+        assert_eq!(test(""), Ok(false));
+        assert_eq!(test("x"), Ok(true));
+        assert_eq!(test("-n x"), Ok(true));
+        assert_eq!(test("-z x"), Ok(false));
+        assert_eq!(test("a = a"), Ok(true));
+        assert_eq!(test("a != a"), Ok(false));
+        assert_eq!(test("a < b"), Ok(true));
+        assert_eq!(test("10 -gt 9"), Ok(true));
+        assert_eq!(test("-3 -le -3"), Ok(true));
+        assert!(test("a -eq 1").is_err());
+        assert!(test("-q x").is_err());
+    }
+
+    #[test]
+    fn operands_that_look_like_operators() {
+        assert_eq!(test("! = x"), Ok(false));
+        assert_eq!(test("-n"), Ok(true));
+        assert_eq!(test("!"), Ok(true));
+        assert_eq!(test("( = ("), Ok(true));
+        assert_eq!(test("-a -a -a"), Ok(true));
+        assert_eq!(test("! -z x"), Ok(true));
+        assert_eq!(test("( -n x )"), Ok(true));
+    }
+
+    #[test]
+    fn combined_expressions() {
+        assert_eq!(test("a = b -o b = b -a ! c = d"), Ok(true));
+        assert_eq!(test("( a = b -o b = b ) -a c = d"), Ok(false));
+        assert_eq!(test("-d / -a -e /nonexistent"), Ok(false));
+        assert_eq!(test("! ( -f / ) -a -d /"), Ok(true));
+        assert!(test("( a = a -a b = b").is_err());
+        assert!(test("a = a -a b = b c").is_err());
+    }
+
+    #[test]
+    fn files() {
+        assert_eq!(test("-d /"), Ok(true));
+        assert_eq!(test("-f /"), Ok(false));
+        assert_eq!(test("-e /nonexistent"), Ok(false));
+        assert_eq!(test("-e "), Ok(false));
+        assert_eq!(test("/ -ef /."), Ok(true));
+        assert_eq!(test("/ -nt /nonexistent"), Ok(true));
+        assert_eq!(test("/nonexistent -nt /"), Ok(false));
+    }
+}
@@ -0,0 +1,68 @@
+//! Access to the state of the shell commands are evaluated in
+
+use std::process::ExitStatus;
+
+use crate::{ast, ShellOptions, TrapCondition};
+
+/// State of the shell that is read and modified while evaluating commands
+///
+/// The evaluator does not own any of the shell's state, the shell running it provides it through
+/// this trait instead.
+pub trait ShellContext {
+    /// Value of a shell or environment variable
+    fn get_var(&self, name: &str) -> Option<String>;
+
+    /// Set the value of a shell variable
+    fn set_var(&mut self, name: &str, value: &str);
+
+    /// Remove a shell or environment variable, or an array
+    fn unset_var(&mut self, name: &str);
+
+    /// Values of an indexed array like `BASH_REMATCH`
+    fn get_array(&self, name: &str) -> Option<Vec<String>>;
+
+    /// Set the values of an indexed array, replacing the ones it had
+    fn set_array(&mut self, name: &str, values: Vec<String>);
+
+    /// Exit status of the most recent pipeline, as expanded by `$?`
+    fn exit_status(&self) -> i32;
+
+    /// Record the exit status of the pipeline that just completed
+    fn set_exit_status(&mut self, status: i32);
+
+    /// Name of the shell or shell script, as expanded by `$0`
+    fn shell_name(&self) -> String;
+
+    /// Process ID of the most recent background command, as expanded by `$!`
+    fn last_background_pid(&self) -> Option<u32>;
+
+    /// Record the process ID of a command that was started in the background
+    fn set_last_background_pid(&mut self, pid: u32);
+
+    /// Options set with the `set` builtin
+    fn options(&self) -> ShellOptions;
+
+    /// Positional parameters, starting from `$1`
+    fn positional_args(&self) -> Vec<String>;
+
+    /// Replace the positional parameters
+    fn set_positional_args(&mut self, args: Vec<String>);
+
+    /// Body of a defined function
+    fn get_function(&self, name: &str) -> Option<Box<ast::Command>>;
+
+    /// Define a function, replacing any existing function with the same name
+    fn set_function(&mut self, name: &str, body: Box<ast::Command>);
+
+    /// Command of the trap set for a condition, an empty command means the condition is ignored
+    fn get_trap(&self, condition: TrapCondition) -> Option<String>;
+
+    /// Remove the trap set for a condition
+    fn remove_trap(&mut self, condition: TrapCondition);
+
+    /// Check if there is a builtin with the given name
+    fn is_builtin(&self, name: &str) -> bool;
+
+    /// Run a builtin in the shell itself, `args` starts with the name of the builtin
+    fn run_builtin(&mut self, args: &[String]) -> ExitStatus;
+}
@@ -1,4 +1,3 @@
-
 use thiserror::Error;
 
 use crate::ParserError;
@@ -17,9 +16,18 @@ pub enum PosixError {
     /// Issue evaluating command
     #[error("Failed evaluating command: {0}")]
     Eval(anyhow::Error),
+    /// Error when expanding a word
+    #[error("Expansion Error: {0}")]
+    Expansion(String),
+    /// Expression of a `[[ ... ]]` command that can't be evaluated
+    #[error("Conditional Error: {0}")]
+    Conditional(String),
     /// Command not found
     #[error("Command not found: {0}")]
     CommandNotFound(String),
+    /// Command exists but can't be executed
+    #[error("Permission denied: {0}")]
+    CommandNotExecutable(String),
     /// Job manager specific error
     #[error("Job manager error: {0}")]
     Job(anyhow::Error),
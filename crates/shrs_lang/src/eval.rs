@@ -1,160 +1,1694 @@
 // Lot of code based off of https://github.com/nuta/nsh/blob/main/src/eval.rs
 
-use glob::glob;
-use shrs_job::{run_external_command, JobManager, Output, Process, ProcessGroup, Stdin};
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, Seek, SeekFrom, Write},
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd, RawFd},
+        unix::process::ExitStatusExt,
+    },
+    path::PathBuf,
+    process::ExitStatus,
+};
 
-use crate::{ast, Lexer, Parser, PosixError};
+use nix::{
+    fcntl::{fcntl, FcntlArg, OFlag},
+    sys::signal::Signal,
+    unistd::{close, dup2, getpgrp, mkstemp, pipe2},
+};
+use shrs_job::{
+    run_external_command, run_forked, track_command_fd, JobManager, Output, Process, ProcessGroup,
+    Stdin,
+};
 
-pub fn eval(job_manager: &mut JobManager, parser: Parser, lexer: Lexer) -> Result<(), PosixError> {
+use crate::{
+    ast, catch_signal,
+    expand::is_name,
+    glob::Pattern,
+    quote_word,
+    trap::{default_signal, take_pending_signals},
+    Lexer, Parser, PosixError, ShellContext, TrapCondition,
+};
 
+/// Parse and evaluate a command, returning the exit status of the last pipeline that ran
+pub fn eval(
+    job_manager: &mut JobManager,
+    ctx: &mut dyn ShellContext,
+    parser: Parser,
+    lexer: Lexer,
+) -> Result<ExitStatus, PosixError> {
+    let input = lexer.input();
     let parsed = match parser.parse(lexer) {
         Ok(parsed) => parsed,
         Err(e) => {
-            // TODO detailed parse errors
-            eprintln!("parse error: {e}");
+            eprintln!("{}", e.diagnostic(input));
             return Err(PosixError::Parse(e));
         },
     };
 
-    let (procs, pgid) =
-        match eval_command(job_manager, &parsed, None, None) {
+    eval_command(job_manager, ctx, &parsed)
+}
+
+/// Evaluate a command that was already parsed, returning the exit status of the last pipeline
+/// that ran
+pub fn eval_command(
+    job_manager: &mut JobManager,
+    ctx: &mut dyn ShellContext,
+    cmd: &ast::Command,
+) -> Result<ExitStatus, PosixError> {
+    let mut evaluator = Evaluator::new(job_manager, ctx);
+    evaluator.run_command(cmd).map_err(|e| {
+        eprintln!("{e}");
+        e
+    })
+}
+
+/// Construct an [`ExitStatus`] from an exit code
+fn exit_status(code: i32) -> ExitStatus {
+    ExitStatus::from_raw(code << 8)
+}
+
+/// Exit code a shell reports for an [`ExitStatus`], commands killed by a signal are given the
+/// code 128 + the signal number
+pub fn exit_code(status: ExitStatus) -> i32 {
+    status
+        .code()
+        .unwrap_or_else(|| 128 + status.signal().unwrap_or_default())
+}
+
+/// Exit status reported for a job that was stopped instead of completing
+fn stopped_status() -> ExitStatus {
+    exit_status(128 + Signal::SIGTSTP as i32)
+}
+
+/// Processes started for a command, along with the process group they were put in
+type SpawnedJob = (Vec<Box<dyn Process>>, Option<u32>);
+
+/// Pending change in control flow that stops the rest of a command list from executing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Flow {
+    /// Exit from the given number of enclosing loops
+    Break(usize),
+    /// Exit from one less than the given number of enclosing loops and continue with the next
+    /// iteration of the outermost of them
+    Continue(usize),
+    /// Return from the function being called with the given status
+    Return(ExitStatus),
+    /// Foreground job was interrupted by the user
+    Interrupt,
+}
+
+/// State kept while evaluating a command
+pub(crate) struct Evaluator<'a> {
+    job_manager: &'a mut JobManager,
+    pub(crate) ctx: &'a mut dyn ShellContext,
+    /// Number of loops the command currently being evaluated is nested in
+    loop_depth: usize,
+    /// Number of function calls the command currently being evaluated is nested in
+    function_depth: usize,
+    flow: Option<Flow>,
+    /// Number of conditions the command currently being evaluated is part of, failing commands
+    /// only make `set -e` exit the shell outside of them
+    errexit_ignored: usize,
+    /// Whether the command of a trap is running, traps aren't run from inside of other traps
+    running_trap: bool,
+    /// Exit status of the last command that completed
+    last_status: ExitStatus,
+    /// Process ID of the shell, which subshells keep reporting as `$$`
+    pub(crate) shell_pid: u32,
+    /// Exit status of the last command substitution
+    pub(crate) last_substitution: Option<ExitStatus>,
+    /// Processes of the process substitutions that are still running, along with the end of the
+    /// pipe to them that the shell holds open for the command using them
+    pub(crate) process_substitutions: Vec<(Box<dyn Process>, File)>,
+}
+
+impl<'a> Evaluator<'a> {
+    fn new(job_manager: &'a mut JobManager, ctx: &'a mut dyn ShellContext) -> Self {
+        let last_status = exit_status(ctx.exit_status());
+        Self {
+            job_manager,
+            ctx,
+            loop_depth: 0,
+            function_depth: 0,
+            flow: None,
+            errexit_ignored: 0,
+            running_trap: false,
+            last_status,
+            shell_pid: std::process::id(),
+            last_substitution: None,
+            process_substitutions: vec![],
+        }
+    }
+
+    /// Flags of the shell options that are enabled, as expanded by `$-`
+    pub(crate) fn option_flags(&self) -> String {
+        let mut flags = self.ctx.options().flags();
+        if self.job_manager.job_control() {
+            flags.push('m');
+        }
+        flags
+    }
+
+    /// Process group and whether to give it the terminal for the processes of a new job
+    fn process_group(&self, foreground: bool) -> (Option<u32>, bool) {
+        if self.job_manager.job_control() {
+            (None, foreground)
+        } else {
+            (Some(getpgrp().as_raw() as u32), false)
+        }
+    }
+
+    pub(crate) fn run_job(
+        &mut self,
+        procs: Vec<Box<dyn Process>>,
+        pgid: Option<u32>,
+        foreground: bool,
+    ) -> Result<ExitStatus, PosixError> {
+        let proc_group = ProcessGroup {
+            id: pgid,
+            processes: procs,
+            foreground,
+        };
+
+        let last_pid = proc_group.processes.last().and_then(|proc| proc.id());
+        let job_id = self.job_manager.create_job("", proc_group);
+
+        if foreground {
+            let status = self
+                .job_manager
+                .put_job_in_foreground(Some(job_id), false)
+                .map_err(PosixError::Job)?
+                .unwrap_or_else(stopped_status);
+            // with pipefail, the status is the one of the last command in the pipeline that failed
+            let status = match self.ctx.options().pipefail {
+                true => self
+                    .job_manager
+                    .get_jobs()
+                    .iter()
+                    .find(|job| job.id() == job_id)
+                    .and_then(|job| {
+                        job.processes()
+                            .iter()
+                            .rev()
+                            .filter_map(|proc| proc.status_code())
+                            .find(|status| !status.success())
+                    })
+                    .unwrap_or(status),
+                false => status,
+            };
+            if status.signal() == Some(Signal::SIGINT as i32) {
+                self.flow = Some(Flow::Interrupt);
+            }
+            Ok(status)
+        } else {
+            self.job_manager
+                .put_job_in_background(Some(job_id), false)
+                .map_err(PosixError::Job)?;
+            if let Some(pid) = last_pid {
+                self.ctx.set_last_background_pid(pid.into());
+            }
+            Ok(exit_status(0))
+        }
+    }
+
+    /// Spawn the processes for a command and run them as a single job
+    fn spawn_job(
+        &mut self,
+        cmd: &ast::Command,
+        foreground: bool,
+    ) -> Result<ExitStatus, PosixError> {
+        let substitutions = self.process_substitutions.len();
+        let (mut procs, pgid) = match self.eval_command(cmd, None, None, foreground) {
+            Ok((procs, pgid)) => (procs, pgid),
+            Err(e) => return command_error(e),
+        };
+
+        // process substitutions of a background job are reaped along with the rest of the job
+        if !foreground {
+            let mut substituted = self
+                .process_substitutions
+                .split_off(substitutions)
+                .into_iter()
+                .map(|(proc, _)| proc)
+                .collect::<Vec<_>>();
+            substituted.append(&mut procs);
+            procs = substituted;
+        }
+        self.run_job(procs, pgid, foreground)
+    }
+
+    /// Run a pipeline whose last command is a builtin
+    ///
+    /// The builtin is ran by the shell itself so that it can change the state of the shell, while
+    /// the rest of the pipeline is spawned as a job.
+    fn run_builtin_pipeline(
+        &mut self,
+        a_cmd: &ast::Command,
+        redirects: &[ast::Redirect],
+        assigns: &[(&str, &str)],
+        args: &[ast::Word],
+    ) -> Result<ExitStatus, PosixError> {
+        let (read, write) = create_pipe().map_err(|e| PosixError::Eval(e.into()))?;
+        // the builtin keeps the terminal until the job is waited on
+        let (procs, pgid) = match self.eval_command(a_cmd, None, Some(Output::File(write)), false) {
             Ok((procs, pgid)) => (procs, pgid),
-            Err(PosixError::CommandNotFound(_)) => {
-                // let _ = cmd.run_hook(CommandNotFoundCtx {});
-                // TODO return error code 127
-                return Ok(());
+            Err(e) => return command_error(e),
+        };
+        let status = self.run_builtin(redirects, assigns, args, Stdin::File(read))?;
+        if !procs.is_empty() {
+            let job_status = self.run_job(procs, pgid, true)?;
+            if self.ctx.options().pipefail && status.success() {
+                return Ok(job_status);
+            }
+        }
+        Ok(status)
+    }
+
+    /// Handle `break` and `continue`, these need to be evaluated by the shell itself since they
+    /// modify its control flow
+    fn loop_control(&mut self, args: &[ast::Word]) -> ExitStatus {
+        let name = args[0].as_str();
+        if self.loop_depth == 0 {
+            eprintln!("{name}: only meaningful in a loop");
+            return exit_status(0);
+        }
+        let n = match args.get(1).map(|n| n.text.parse::<usize>()) {
+            None => 1,
+            Some(Ok(n)) if n > 0 => n,
+            Some(_) => {
+                eprintln!("{name}: {}: loop count out of range", args[1].text);
+                return exit_status(1);
             },
-            _ => return Ok(()),
         };
+        // Exiting more loops than there are exits all of them
+        let n = n.min(self.loop_depth);
+        self.flow = Some(if name == "break" {
+            Flow::Break(n)
+        } else {
+            Flow::Continue(n)
+        });
+        exit_status(0)
+    }
 
-    run_job(job_manager, procs, pgid, true)?;
-    Ok(())
-}
+    /// Handle `return`, which stops the execution of the function or sourced script currently
+    /// being called
+    fn return_builtin(&mut self, args: &[ast::Word]) -> ExitStatus {
+        if self.function_depth == 0 {
+            eprintln!("return: can only `return' from a function or sourced script");
+            return exit_status(1);
+        }
+        let status = match args.get(1).map(|n| n.text.parse::<i32>()) {
+            None => self.last_status,
+            Some(Ok(n)) => exit_status(n & 0xff),
+            Some(Err(_)) => {
+                eprintln!("return: {}: numeric argument required", args[1].text);
+                exit_status(2)
+            },
+        };
+        self.flow = Some(Flow::Return(status));
+        status
+    }
 
-fn run_job(
-    job_manager: &mut JobManager,
-    procs: Vec<Box<dyn Process>>,
-    pgid: Option<u32>,
-    foreground: bool,
-) -> Result<(), PosixError> {
-    let proc_group = ProcessGroup {
-        id: pgid,
-        processes: procs,
-        foreground,
-    };
+    /// Handle `exit`, which the shell handles itself so that the EXIT trap can run
+    ///
+    /// Without a status, the shell exits with the status of the last command.
+    fn exit_builtin(&mut self, args: &[String]) -> ! {
+        let status = match args.get(1).map(|n| n.parse::<i32>()) {
+            None => self.last_status,
+            Some(Ok(n)) => exit_status(n & 0xff),
+            Some(Err(_)) => {
+                eprintln!("exit: {}: numeric argument required", args[1]);
+                exit_status(2)
+            },
+        };
+        self.exit(status)
+    }
+
+    /// Call a shell function with the arguments of a simple command
+    ///
+    /// Variables assigned before the name of the function are only set while it is running.
+    fn call_function(
+        &mut self,
+        body: &ast::Command,
+        assigns: &[(&str, &str)],
+        args: &[ast::Word],
+    ) -> Result<ExitStatus, PosixError> {
+        let mut args = self.expand_args(args)?;
+        self.trace(&[], &args);
+        args.remove(0);
+        let saved_vars = self.set_temporary_vars(assigns)?;
+        let caller_args = self.ctx.positional_args();
+        self.ctx.set_positional_args(args);
 
-    let job_id = job_manager.create_job("", proc_group);
+        // loops in the caller can't be exited from inside the function
+        let loop_depth = std::mem::take(&mut self.loop_depth);
+        self.function_depth += 1;
+        let result = self.run_command(body);
+        self.function_depth -= 1;
+        self.loop_depth = loop_depth;
+        self.ctx.set_positional_args(caller_args);
+        self.restore_vars(saved_vars);
 
-    if foreground {
-        job_manager
-            .put_job_in_foreground(Some(job_id), false)
-            .map_err(|e| PosixError::Job(e))?;
-    } else {
-        job_manager
-            .put_job_in_background(Some(job_id), false)
-            .map_err(|e| PosixError::Job(e))?;
+        let status = result?;
+        match self.flow {
+            Some(Flow::Return(status)) => {
+                self.flow = None;
+                Ok(status)
+            },
+            _ => Ok(status),
+        }
     }
-    Ok(())
-}
 
-fn expand_arg(arg: &String) -> Vec<String> {
-    let mut a = arg.clone();
+    /// Call a function in the shell itself, with its redirections applied to the file
+    /// descriptors of the shell for as long as it is running
+    fn run_function(
+        &mut self,
+        body: &ast::Command,
+        redirects: &[ast::Redirect],
+        assigns: &[(&str, &str)],
+        args: &[ast::Word],
+    ) -> Result<ExitStatus, PosixError> {
+        if redirects.is_empty() {
+            return self.call_function(body, assigns, args);
+        }
+        let io = match self.apply_redirects(redirects, Stdin::Inherit, Output::Inherit) {
+            Ok(io) => io,
+            Err(e) => return command_error(e),
+        };
+        let saved_fds = redirect_shell(io).map_err(PosixError::Redirect)?;
+        let result = self.call_function(body, assigns, args);
+        restore_shell(saved_fds);
+        result
+    }
+
+    /// Handle `.` and `source`, which run the commands in a file in the current shell
+    ///
+    /// Arguments after the file name are the positional parameters while the file is running.
+    fn source(&mut self, args: &[String]) -> Result<ExitStatus, PosixError> {
+        let name = args[0].as_str();
+        let Some(file) = args.get(1) else {
+            eprintln!("{name}: filename argument required");
+            return Ok(exit_status(2));
+        };
+        let path = self.find_source_file(file);
+        let input = match fs::read_to_string(&path) {
+            Ok(input) => input,
+            Err(e) => {
+                eprintln!("{name}: {file}: {e}");
+                return Ok(exit_status(1));
+            },
+        };
+        let parsed = match Parser::default().parse(Lexer::new(&input)) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                eprintln!("{file}: {}", e.diagnostic(&input));
+                return Ok(exit_status(2));
+            },
+        };
+
+        let caller_args = (args.len() > 2).then(|| {
+            let caller_args = self.ctx.positional_args();
+            self.ctx.set_positional_args(args[2..].to_vec());
+            caller_args
+        });
+        self.function_depth += 1;
+        let result = self.run_command(&parsed);
+        self.function_depth -= 1;
+        if let Some(caller_args) = caller_args {
+            self.ctx.set_positional_args(caller_args);
+        }
+
+        let status = result?;
+        match self.flow {
+            Some(Flow::Return(status)) => {
+                self.flow = None;
+                Ok(status)
+            },
+            _ => Ok(status),
+        }
+    }
+
+    /// Path of a file to source, names without a slash are searched for in `PATH` before the
+    /// current directory
+    fn find_source_file(&self, file: &str) -> PathBuf {
+        if !file.contains('/') {
+            let path = self.ctx.get_var("PATH").unwrap_or_default();
+            if let Some(found) = std::env::split_paths(&path)
+                .map(|dir| dir.join(file))
+                .find(|candidate| candidate.is_file())
+            {
+                return found;
+            }
+        }
+        PathBuf::from(file)
+    }
+
+    /// Check if a command is a builtin, including the ones the evaluator runs itself
+    fn is_builtin(&self, name: &str) -> bool {
+        matches!(name, "." | "source") || self.ctx.is_builtin(name)
+    }
+
+    /// Check if a simple command calls a builtin, functions take precedence over builtins with the
+    /// same name
+    fn calls_builtin(&self, assigns: &[ast::Assign], args: &[ast::Word]) -> bool {
+        let (_, words) = split_assignments(assigns, args);
+        words
+            .first()
+            .is_some_and(|name| self.ctx.get_function(name).is_none() && self.is_builtin(name))
+    }
 
-    // expand ~
-    if let Some(remaining) = arg.strip_prefix("~") {
-        a = format!(
-            "{}{}",
-            dirs::home_dir().unwrap().to_string_lossy(),
-            remaining
+    /// Call a builtin with the arguments of a simple command
+    ///
+    /// Like with functions, variables assigned before the name of the builtin are only set while
+    /// it is running.
+    fn call_builtin(
+        &mut self,
+        assigns: &[(&str, &str)],
+        args: &[ast::Word],
+    ) -> Result<ExitStatus, PosixError> {
+        let args = self.expand_args(args)?;
+        if args.is_empty() {
+            return Ok(exit_status(0));
+        }
+        self.trace(&[], &args);
+        let saved_vars = self.set_temporary_vars(assigns)?;
+        let result = match args[0].as_str() {
+            "." | "source" => self.source(&args),
+            "exit" => self.exit_builtin(&args),
+            _ => Ok(self.ctx.run_builtin(&args)),
+        };
+        self.restore_vars(saved_vars);
+        result
+    }
+
+    /// Run a builtin in the shell itself, so that it can change the state of the shell
+    ///
+    /// Redirections are applied to the file descriptors of the shell for as long as the builtin
+    /// is running.
+    fn run_builtin(
+        &mut self,
+        redirects: &[ast::Redirect],
+        assigns: &[(&str, &str)],
+        args: &[ast::Word],
+        stdin: Stdin,
+    ) -> Result<ExitStatus, PosixError> {
+        let io = match self.apply_redirects(redirects, stdin, Output::Inherit) {
+            Ok(io) => io,
+            Err(e) => return command_error(e),
+        };
+        let saved_fds = redirect_shell(io).map_err(PosixError::Redirect)?;
+        let result = self.call_builtin(assigns, args);
+        restore_shell(saved_fds);
+        result
+    }
+
+    /// Set variables for as long as a function or builtin is running, returning their previous
+    /// values
+    fn set_temporary_vars<'c>(
+        &mut self,
+        assigns: &[(&'c str, &str)],
+    ) -> Result<Vec<(&'c str, Option<String>)>, PosixError> {
+        let mut saved_vars = vec![];
+        for (var, val) in assigns {
+            let val = self.expand_assignment(val)?;
+            saved_vars.push((*var, self.ctx.get_var(var)));
+            self.ctx.set_var(var, &val);
+        }
+        Ok(saved_vars)
+    }
+
+    /// Restore the variables changed by [`Evaluator::set_temporary_vars`]
+    fn restore_vars(&mut self, saved_vars: Vec<(&str, Option<String>)>) {
+        for (var, val) in saved_vars.into_iter().rev() {
+            match val {
+                Some(val) => self.ctx.set_var(var, &val),
+                None => self.ctx.unset_var(var),
+            }
+        }
+    }
+
+    /// Run a loop body, returning true if the loop should keep going
+    fn run_loop_body(
+        &mut self,
+        body: &ast::Command,
+        status: &mut ExitStatus,
+    ) -> Result<bool, PosixError> {
+        self.loop_depth += 1;
+        let result = self.run_command(body);
+        self.loop_depth -= 1;
+        *status = result?;
+
+        match self.flow {
+            None => Ok(true),
+            Some(Flow::Break(1)) => {
+                self.flow = None;
+                Ok(false)
+            },
+            Some(Flow::Continue(1)) => {
+                self.flow = None;
+                Ok(true)
+            },
+            Some(Flow::Break(n)) => {
+                self.flow = Some(Flow::Break(n - 1));
+                Ok(false)
+            },
+            Some(Flow::Continue(n)) => {
+                self.flow = Some(Flow::Continue(n - 1));
+                Ok(false)
+            },
+            Some(Flow::Return(_) | Flow::Interrupt) => Ok(false),
+        }
+    }
+
+    /// Run a `while` or `until` loop, `until` keeps running while the condition fails
+    fn run_loop(
+        &mut self,
+        cond: &ast::Command,
+        body: &ast::Command,
+        until: bool,
+    ) -> Result<ExitStatus, PosixError> {
+        let mut status = exit_status(0);
+        loop {
+            let cond_status = self.run_condition(cond)?;
+            if self.flow.is_some() || cond_status.success() == until {
+                break;
+            }
+            if !self.run_loop_body(body, &mut status)? {
+                break;
+            }
+        }
+        Ok(status)
+    }
+
+    /// Run a command to completion, returning the exit status of the last pipeline ran
+    pub(crate) fn run_command(&mut self, cmd: &ast::Command) -> Result<ExitStatus, PosixError> {
+        let substitutions = self.process_substitutions.len();
+        let result = self.eval_compound(cmd);
+        self.finish_process_substitutions(substitutions);
+        let status = result?;
+        self.last_status = status;
+        self.ctx.set_exit_status(exit_code(status));
+
+        // the ERR trap runs and `set -e` exits the shell when a command fails outside of a
+        // condition, failures of lists and compound commands come from the commands inside of them
+        let command = matches!(
+            cmd.kind,
+            ast::CommandKind::Simple { .. }
+                | ast::CommandKind::Pipeline(..)
+                | ast::CommandKind::Subshell(..)
+                | ast::CommandKind::Conditional(..)
         );
+        if command && !status.success() && self.errexit_ignored == 0 && self.flow.is_none() {
+            self.run_trap(TrapCondition::Error);
+            if self.ctx.options().errexit {
+                self.exit(status);
+            }
+        }
+
+        for signal in take_pending_signals() {
+            self.run_trap(TrapCondition::Signal(signal));
+        }
+        Ok(status)
     }
 
-    // quotes escape all special characters
-    let first = arg.chars().next().unwrap();
-    if first == '\'' || first == '\"' {
-        return a
-            .trim_matches(|c| c == '\'' || c == '\"')
-            .split_whitespace()
-            .map(ToString::to_string)
-            .collect();
-    }
-    // match globbed files only if the glob actually works
-    else if glob::Pattern::escape(a.as_str()) != a.as_str() {
-        if let Ok(files) = glob(a.as_str()) {
-            return files
-                .filter_map(|file| match file {
-                    Ok(s) => Some(s.to_string_lossy().to_string()),
-                    Err(s) => Some(s.to_string()),
+    /// Run the command of the trap set for a condition, if there is one
+    fn run_trap(&mut self, condition: TrapCondition) {
+        if self.running_trap {
+            return;
+        }
+        if let Some(action) = self.ctx.get_trap(condition) {
+            self.run_trap_command(&action);
+        }
+    }
+
+    /// Run the command of a trap, keeping the exit status and control flow as they were before
+    fn run_trap_command(&mut self, action: &str) {
+        let parsed = match Parser::default().parse(Lexer::new(action)) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                eprintln!("trap: {}", e.diagnostic(action));
+                return;
+            },
+        };
+
+        let status = self.last_status;
+        let flow = self.flow.take();
+        let running_trap = std::mem::replace(&mut self.running_trap, true);
+        if let Err(e) = self.run_command(&parsed) {
+            eprintln!("{e}");
+        }
+        self.running_trap = running_trap;
+        self.flow = flow;
+        self.last_status = status;
+        self.ctx.set_exit_status(exit_code(status));
+    }
+
+    /// Remove the traps set in the shell, for running a subshell
+    ///
+    /// Signals with a trap get their default handling, like the rest of the signals in a forked
+    /// shell do. Signals that are ignored stay ignored.
+    fn reset_traps(&mut self) {
+        for condition in TrapCondition::all() {
+            let Some(action) = self.ctx.get_trap(condition) else {
+                continue;
+            };
+            match (condition, action.is_empty()) {
+                (TrapCondition::Signal(signal), true) => {
+                    let _ = catch_signal(signal, true);
+                },
+                (_, true) => {},
+                (condition, false) => {
+                    self.ctx.remove_trap(condition);
+                    if let TrapCondition::Signal(signal) = condition {
+                        let _ = default_signal(signal);
+                    }
+                },
+            }
+        }
+    }
+
+    /// Run a command whose failure doesn't make `set -e` exit the shell, like the condition of an
+    /// `if` or the left side of `&&`
+    fn run_condition(&mut self, cmd: &ast::Command) -> Result<ExitStatus, PosixError> {
+        self.errexit_ignored += 1;
+        let result = self.run_command(cmd);
+        self.errexit_ignored -= 1;
+        result
+    }
+
+    /// Exit the shell with the given status, after running the EXIT trap
+    fn exit(&mut self, status: ExitStatus) -> ! {
+        self.last_status = status;
+        self.ctx.set_exit_status(exit_code(status));
+        // the trap is removed first so that exiting from inside of it doesn't run it again
+        if let Some(action) = self.ctx.get_trap(TrapCondition::Exit) {
+            self.ctx.remove_trap(TrapCondition::Exit);
+            self.run_trap_command(&action);
+        }
+        std::process::exit(exit_code(status))
+    }
+
+    /// Print a command about to be run to stderr when `set -x` is on, prefixed with `$PS4`
+    fn trace(&self, assigns: &[(String, String)], words: &[String]) {
+        if !self.ctx.options().xtrace {
+            return;
+        }
+        let prefix = self
+            .ctx
+            .get_var("PS4")
+            .unwrap_or_else(|| String::from("+ "));
+        let line = assigns
+            .iter()
+            .map(|(var, val)| format!("{var}={}", quote_word(val)))
+            .chain(words.iter().map(|word| quote_word(word)))
+            .collect::<Vec<_>>()
+            .join(" ");
+        eprintln!("{prefix}{line}");
+    }
+
+    fn eval_compound(&mut self, cmd: &ast::Command) -> Result<ExitStatus, PosixError> {
+        match &cmd.kind {
+            ast::CommandKind::Simple {
+                assigns,
+                redirects,
+                args,
+            } => {
+                let (assigns, words) = split_assignments(assigns, args);
+                match words.first().map(ast::Word::as_str) {
+                    // assignments without a command set variables in the shell itself
+                    None => {
+                        self.last_substitution = None;
+                        let mut expanded = vec![];
+                        for (var, val) in assigns {
+                            let val = self.expand_assignment(val)?;
+                            self.ctx.set_var(var, &val);
+                            expanded.push((var.to_string(), val));
+                        }
+                        self.trace(&expanded, &[]);
+                        // the status is that of the last command substitution, if there was one
+                        Ok(self.last_substitution.unwrap_or(exit_status(0)))
+                    },
+                    Some("break" | "continue") => Ok(self.loop_control(words)),
+                    Some("return") => Ok(self.return_builtin(words)),
+                    Some(name) => match self.ctx.get_function(name) {
+                        Some(body) => self.run_function(&body, redirects, &assigns, words),
+                        None if self.is_builtin(name) => {
+                            self.run_builtin(redirects, &assigns, words, Stdin::Inherit)
+                        },
+                        _ => self.spawn_job(cmd, true),
+                    },
+                }
+            },
+            ast::CommandKind::Pipeline(a_cmd, b_cmd) => match &b_cmd.kind {
+                ast::CommandKind::Simple {
+                    assigns,
+                    redirects,
+                    args,
+                } if self.calls_builtin(assigns, args) => {
+                    let (assigns, words) = split_assignments(assigns, args);
+                    self.run_builtin_pipeline(a_cmd, redirects, &assigns, words)
+                },
+                _ => self.spawn_job(cmd, true),
+            },
+            ast::CommandKind::Fn { fname, body } => {
+                self.ctx.set_function(fname, body.clone());
+                Ok(exit_status(0))
+            },
+            ast::CommandKind::And(a_cmd, b_cmd) => {
+                let status = self.run_condition(a_cmd)?;
+                if status.success() && self.flow.is_none() {
+                    self.run_command(b_cmd)
+                } else {
+                    Ok(status)
+                }
+            },
+            ast::CommandKind::Or(a_cmd, b_cmd) => {
+                let status = self.run_condition(a_cmd)?;
+                if status.success() || self.flow.is_some() {
+                    Ok(status)
+                } else {
+                    self.run_command(b_cmd)
+                }
+            },
+            ast::CommandKind::Not(cmd) => {
+                let status = self.run_condition(cmd)?;
+                Ok(exit_status(if status.success() { 1 } else { 0 }))
+            },
+            ast::CommandKind::SeqList(a_cmd, b_cmd) => {
+                let status = self.run_command(a_cmd)?;
+                match b_cmd {
+                    Some(b_cmd) if self.flow.is_none() => self.run_command(b_cmd),
+                    _ => Ok(status),
+                }
+            },
+            ast::CommandKind::AsyncList(a_cmd, b_cmd) => {
+                // TODO double check stdin and stdout
+                let status = self.spawn_job(a_cmd, false)?;
+                match b_cmd {
+                    Some(b_cmd) => self.run_command(b_cmd),
+                    None => Ok(status),
+                }
+            },
+            ast::CommandKind::If { conds, else_part } => {
+                for cond in conds {
+                    let status = self.run_condition(&cond.cond)?;
+                    if self.flow.is_some() {
+                        return Ok(status);
+                    }
+                    if status.success() {
+                        return self.run_command(&cond.body);
+                    }
+                }
+                match else_part {
+                    Some(else_part) => self.run_command(else_part),
+                    None => Ok(exit_status(0)),
+                }
+            },
+            ast::CommandKind::While { cond, body } => self.run_loop(cond, body, false),
+            ast::CommandKind::Until { cond, body } => self.run_loop(cond, body, true),
+            ast::CommandKind::For {
+                name,
+                wordlist,
+                body,
+            } => {
+                let words = self.expand_args(wordlist)?;
+                let mut status = exit_status(0);
+                for word in words {
+                    self.ctx.set_var(name, &word);
+                    if !self.run_loop_body(body, &mut status)? {
+                        break;
+                    }
+                }
+                Ok(status)
+            },
+            ast::CommandKind::Case { word, arms } => {
+                let word = self.expand_word(word)?;
+                let extglob = self.ctx.options().extglob;
+                for arm in arms {
+                    for pattern in &arm.pattern {
+                        if Pattern::new(&self.expand_pattern(pattern)?, extglob).matches(&word) {
+                            return self.run_command(&arm.body);
+                        }
+                    }
+                }
+                Ok(exit_status(0))
+            },
+            ast::CommandKind::Conditional(expr) => match self.eval_conditional(expr) {
+                Ok(result) => Ok(exit_status(if result { 0 } else { 1 })),
+                // invalid expressions fail with the same status as syntax errors
+                Err(PosixError::Conditional(e)) => {
+                    eprintln!("[[: {e}");
+                    Ok(exit_status(2))
+                },
+                Err(e) => Err(e),
+            },
+            ast::CommandKind::None => Ok(exit_status(0)),
+            _ => self.spawn_job(cmd, true),
+        }
+    }
+
+    /// Returns group of processes and also the pgid if it has one
+    fn eval_command(
+        &mut self,
+        cmd: &ast::Command,
+        stdin: Option<Stdin>,
+        stdout: Option<Output>,
+        foreground: bool,
+    ) -> Result<SpawnedJob, PosixError> {
+        match &cmd.kind {
+            ast::CommandKind::Simple {
+                assigns,
+                redirects,
+                args,
+            } => {
+                let (assigns, words) = split_assignments(assigns, args);
+                let io = self.apply_redirects(
+                    redirects,
+                    stdin.unwrap_or(Stdin::Inherit),
+                    stdout.unwrap_or(Output::Inherit),
+                )?;
+
+                if let Some(body) = words.first().and_then(|name| self.ctx.get_function(name)) {
+                    return self.fork(&words[0], io, foreground, |evaluator| {
+                        evaluator.call_function(&body, &assigns, words)
+                    });
+                }
+                // builtins that are part of a pipeline are ran in a subshell
+                if words.first().is_some_and(|name| self.is_builtin(name)) {
+                    return self.fork(&words[0], io, foreground, |evaluator| {
+                        evaluator.call_builtin(&assigns, words)
+                    });
+                }
+
+                let words = self.expand_args(words)?;
+                let Some((program, args)) = words.split_first() else {
+                    // assignments in a subshell or pipeline don't affect the shell
+                    return Ok((vec![], None));
+                };
+                let env = assigns
+                    .iter()
+                    .map(|(var, val)| Ok((var.to_string(), self.expand_assignment(val)?)))
+                    .collect::<Result<Vec<_>, PosixError>>()?;
+                self.trace(&env, &words);
+                let (pgid, foreground) = self.process_group(foreground);
+
+                let (proc, pgid) = match run_external_command(
+                    program, args, &env, io.stdin, io.stdout, io.stderr, io.fds, pgid, foreground,
+                ) {
+                    Ok((proc, pgid)) => (proc, pgid),
+                    Err(e) => match e.kind() {
+                        std::io::ErrorKind::NotFound => {
+                            return Err(PosixError::CommandNotFound(program.clone()))
+                        },
+                        std::io::ErrorKind::PermissionDenied => {
+                            return Err(PosixError::CommandNotExecutable(program.clone()))
+                        },
+                        _ => return Err(PosixError::Eval(e.into())),
+                    },
+                };
+                Ok((vec![proc], pgid))
+            },
+            ast::CommandKind::Pipeline(a_cmd, b_cmd) => {
+                // create the pipe ourselves so that redirections like `2>&1` can duplicate it
+                let (read, write) = create_pipe().map_err(|e| PosixError::Eval(e.into()))?;
+                let (mut a_procs, _a_pgid) =
+                    self.eval_command(a_cmd, stdin, Some(Output::File(write)), foreground)?;
+                let (b_procs, b_pgid) =
+                    self.eval_command(b_cmd, Some(Stdin::File(read)), stdout, foreground)?;
+                a_procs.extend(b_procs);
+                Ok((a_procs, b_pgid))
+            },
+            ast::CommandKind::None => Ok((vec![], None)),
+            // Everything else is evaluated by a copy of the shell running as its own process
+            kind => {
+                let cmd = match kind {
+                    ast::CommandKind::Subshell(cmd) => cmd.as_ref(),
+                    _ => cmd,
+                };
+                let io = CommandIo {
+                    stdin: stdin.unwrap_or(Stdin::Inherit),
+                    stdout: stdout.unwrap_or(Output::Inherit),
+                    stderr: Output::Inherit,
+                    fds: vec![],
+                };
+                self.fork("subshell", io, foreground, |evaluator| {
+                    evaluator.run_command(cmd)
                 })
-                .collect();
+            },
         }
     }
 
-    vec![a]
+    /// Evaluate a command in a copy of the shell running as its own process
+    pub(crate) fn fork<F>(
+        &mut self,
+        argv: &str,
+        io: CommandIo,
+        foreground: bool,
+        f: F,
+    ) -> Result<SpawnedJob, PosixError>
+    where
+        F: FnOnce(&mut Self) -> Result<ExitStatus, PosixError>,
+    {
+        let (pgid, foreground) = self.process_group(foreground);
+        let child = || {
+            // Jobs are owned by the parent shell
+            *self.job_manager = JobManager::default();
+            self.job_manager.set_job_control(false);
+            self.reset_traps();
+            match f(self) {
+                Ok(status) => exit_code(status),
+                Err(e) => {
+                    eprintln!("{e}");
+                    1
+                },
+            }
+        };
+        let (proc, pgid) = run_forked(
+            child, argv, io.stdin, io.stdout, io.stderr, io.fds, pgid, foreground,
+        )
+        .map_err(|e| PosixError::Eval(e.into()))?;
+        Ok((vec![proc], pgid))
+    }
 }
 
-/// Returns group of processes and also the pgid if it has one
-fn eval_command(
-    job_manager: &mut JobManager,
-    cmd: &ast::Command,
-    stdin: Option<Stdin>,
-    stdout: Option<Output>,
-) -> Result<(Vec<Box<dyn Process>>, Option<u32>), PosixError> {
-    match cmd {
-        ast::Command::Simple {
-            assigns: _,
-            redirects: _,
-            args,
-        } => {
-            let mut args_it = args.iter();
-            let program = args_it.next().unwrap();
-            let args = args_it.flat_map(expand_arg).collect::<Vec<_>>();
-
-            let proc_stdin = stdin.unwrap_or(Stdin::Inherit);
-            let proc_stdout = stdout.unwrap_or(Output::Inherit);
-
-            let (proc, pgid) = match run_external_command(
-                program,
-                &args,
-                proc_stdin,
-                proc_stdout,
-                Output::Inherit,
-                None,
-            ) {
-                Ok((proc, pgid)) => (proc, pgid),
-                Err(e) => match e.kind() {
-                    std::io::ErrorKind::NotFound => {
-                        return Err(PosixError::CommandNotFound(program.clone()))
-                    },
-                    _ => return Err(PosixError::Eval(e.into())),
+/// Exit status for an error that kept a command from starting, other errors are passed on
+fn command_error(e: PosixError) -> Result<ExitStatus, PosixError> {
+    match e {
+        PosixError::CommandNotFound(_) => {
+            // let _ = cmd.run_hook(CommandNotFoundCtx {});
+            eprintln!("{e}");
+            Ok(exit_status(127))
+        },
+        PosixError::CommandNotExecutable(_) => {
+            eprintln!("{e}");
+            Ok(exit_status(126))
+        },
+        PosixError::Redirect(_) => {
+            eprintln!("{e}");
+            Ok(exit_status(1))
+        },
+        e => Err(e),
+    }
+}
+
+/// Split the variable assignments at the start of a simple command from the words that make up
+/// the command itself
+fn split_assignments<'c>(
+    assigns: &'c [ast::Assign],
+    args: &'c [ast::Word],
+) -> (Vec<(&'c str, &'c str)>, &'c [ast::Word]) {
+    let mut assigns = assigns
+        .iter()
+        .map(|assign| (assign.var.as_str(), assign.val.as_str()))
+        .collect::<Vec<_>>();
+    let mut words = args;
+    while let Some((assign, rest)) = words.split_first().and_then(|(word, rest)| {
+        let (var, val) = word.text.split_once('=')?;
+        is_name(var).then_some(((var, val), rest))
+    }) {
+        assigns.push(assign);
+        words = rest;
+    }
+    (assigns, words)
+}
+
+/// Lowest file descriptor used for files opened by the shell on behalf of a command
+///
+/// Keeping them out of the way of small numbers means applying a redirection never clobbers the
+/// source of a later one
+pub(crate) const MIN_SHELL_FD: RawFd = 10;
+
+/// File descriptors a command is run with after its redirections are applied
+pub(crate) struct CommandIo {
+    pub(crate) stdin: Stdin,
+    pub(crate) stdout: Output,
+    pub(crate) stderr: Output,
+    /// Any other file descriptors, `None` means the descriptor is closed
+    pub(crate) fds: Vec<(RawFd, Option<File>)>,
+}
+
+fn redirect_error(file: &str, e: io::Error) -> PosixError {
+    PosixError::Redirect(io::Error::new(e.kind(), format!("{file}: {e}")))
+}
+
+/// Duplicate a file descriptor to one at or above [`MIN_SHELL_FD`]
+fn dup_fd(fd: RawFd) -> io::Result<File> {
+    let new_fd = fcntl(fd, FcntlArg::F_DUPFD_CLOEXEC(MIN_SHELL_FD))?;
+    track_command_fd(new_fd);
+    Ok(unsafe { File::from_raw_fd(new_fd) })
+}
+
+/// Create a pipe, returning the read and write ends
+pub(crate) fn create_pipe() -> io::Result<(File, File)> {
+    let (read, write) = pipe2(OFlag::O_CLOEXEC)?;
+    track_command_fd(read);
+    track_command_fd(write);
+    unsafe { Ok((File::from_raw_fd(read), File::from_raw_fd(write))) }
+}
+
+/// Open the file of a redirection, with `noclobber` writing to an existing regular file fails
+fn open_redirect(path: &str, mode: &ast::RedirectMode, noclobber: bool) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    match mode {
+        ast::RedirectMode::Read => options.read(true),
+        // files that aren't regular files, like `/dev/null`, can still be written to
+        ast::RedirectMode::Write
+            if noclobber && fs::metadata(path).map_or(true, |m| m.is_file()) =>
+        {
+            return match options.write(true).create_new(true).open(path) {
+                Ok(file) => dup_fd(file.as_raw_fd()),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    Err(io::Error::new(e.kind(), "cannot overwrite existing file"))
                 },
+                Err(e) => Err(e),
             };
-            Ok((vec![proc], pgid))
         },
-        ast::Command::Pipeline(a_cmd, b_cmd) => {
-            let (mut a_procs, _a_pgid) =
-                eval_command(job_manager, a_cmd, stdin, Some(Output::CreatePipe))?;
-            let (b_procs, b_pgid) = eval_command(
-                job_manager,
-                b_cmd,
-                a_procs.last_mut().unwrap().stdout(),
-                stdout,
-            )?;
-            a_procs.extend(b_procs);
-            Ok((a_procs, b_pgid))
+        ast::RedirectMode::Write | ast::RedirectMode::Clobber => {
+            options.write(true).create(true).truncate(true)
         },
-        ast::Command::AsyncList(a_cmd, b_cmd) => {
-            // TODO double check stdin and stdout
-            let (procs, pgid) = eval_command(job_manager, a_cmd, None, None)?;
-            run_job(job_manager, procs, pgid, false)?;
-
-            if let Some(b_cmd) = b_cmd {
-                eval_command(job_manager, b_cmd, None, None)
-            } else {
-                Ok((vec![], None))
-            }
+        ast::RedirectMode::WriteAppend => options.append(true).create(true),
+        ast::RedirectMode::ReadWrite => options.read(true).write(true).create(true),
+        ast::RedirectMode::ReadDup
+        | ast::RedirectMode::WriteDup
+        | ast::RedirectMode::HereDoc { .. }
+        | ast::RedirectMode::HereString => {
+            unreachable!("duplications and here-documents do not open files")
         },
-        ast::Command::None => Ok((vec![], None)),
-        _ => todo!(),
+    };
+    dup_fd(options.open(path)?.as_raw_fd())
+}
+
+/// Point the file descriptors of the shell itself at the ones a command is run with, returning
+/// copies of the descriptors that were replaced
+fn redirect_shell(io: CommandIo) -> io::Result<Vec<(RawFd, Option<File>)>> {
+    let stdin = match io.stdin {
+        Stdin::Inherit => None,
+        Stdin::File(file) => Some(file),
+        Stdin::FileDescriptor(fd) => Some(dup_fd(fd)?),
+        Stdin::Child(child) => Some(File::from(OwnedFd::from(child))),
+    };
+    let output = |output: Output| match output {
+        Output::Inherit => Ok(None),
+        Output::File(file) => Ok(Some(file)),
+        Output::FileDescriptor(fd) => dup_fd(fd).map(Some),
+        Output::CreatePipe => unreachable!("pipes are created by the evaluator"),
+    };
+    let mut targets = vec![];
+    targets.extend(stdin.map(|file| (0, Some(file))));
+    targets.extend(output(io.stdout)?.map(|file| (1, Some(file))));
+    targets.extend(output(io.stderr)?.map(|file| (2, Some(file))));
+    targets.extend(io.fds);
+
+    // anything the shell has written so far goes to where it was meant to
+    io::stdout().flush()?;
+    let mut saved = vec![];
+    for (fd, target) in targets {
+        // descriptors that weren't open are closed again afterwards
+        saved.push((fd, dup_fd(fd).ok()));
+        let result = match target {
+            Some(file) => dup2(file.as_raw_fd(), fd).map(|_| ()),
+            None => close(fd).or(Ok(())),
+        };
+        if let Err(e) = result {
+            restore_shell(saved);
+            return Err(e.into());
+        }
+    }
+    Ok(saved)
+}
+
+/// Restore the file descriptors of the shell replaced by [`redirect_shell`]
+fn restore_shell(saved: Vec<(RawFd, Option<File>)>) {
+    let _ = io::stdout().flush();
+    for (fd, file) in saved.into_iter().rev() {
+        let _ = match file {
+            Some(file) => dup2(file.as_raw_fd(), fd).map(|_| ()),
+            None => close(fd),
+        };
+    }
+}
+
+/// Create a file holding the input given to a command by a here-document
+///
+/// The file is removed right away, it stays around only for as long as it is open.
+fn here_document_file(contents: &str) -> io::Result<File> {
+    let (fd, path) = mkstemp(&std::env::temp_dir().join("shrs-heredoc-XXXXXX"))?;
+    let mut file = unsafe { File::from_raw_fd(fd) };
+    fs::remove_file(path)?;
+    file.write_all(contents.as_bytes())?;
+    file.seek(SeekFrom::Start(0))?;
+    dup_fd(file.as_raw_fd())
+}
+
+impl Evaluator<'_> {
+    /// Apply the redirections of a command on top of the stdin and stdout it was given
+    fn apply_redirects(
+        &mut self,
+        redirects: &[ast::Redirect],
+        stdin: Stdin,
+        stdout: Output,
+    ) -> Result<CommandIo, PosixError> {
+        if redirects.is_empty() {
+            return Ok(CommandIo {
+                stdin,
+                stdout,
+                stderr: Output::Inherit,
+                fds: vec![],
+            });
+        }
+
+        // Descriptors that are modified from the ones the shell has open
+        let mut table: BTreeMap<RawFd, Option<File>> = BTreeMap::new();
+        match stdin {
+            Stdin::Inherit => {},
+            Stdin::File(file) => {
+                table.insert(0, Some(file));
+            },
+            Stdin::FileDescriptor(fd) => {
+                table.insert(0, Some(dup_fd(fd).map_err(PosixError::Redirect)?));
+            },
+            Stdin::Child(child) => {
+                table.insert(0, Some(File::from(OwnedFd::from(child))));
+            },
+        }
+        match stdout {
+            Output::Inherit => {},
+            Output::File(file) => {
+                table.insert(1, Some(file));
+            },
+            Output::FileDescriptor(fd) => {
+                table.insert(1, Some(dup_fd(fd).map_err(PosixError::Redirect)?));
+            },
+            Output::CreatePipe => unreachable!("pipes are created by the evaluator"),
+        }
+
+        for redirect in redirects {
+            let fd = match redirect.n {
+                Some(n) => n as RawFd,
+                None => match redirect.mode {
+                    ast::RedirectMode::Read
+                    | ast::RedirectMode::ReadDup
+                    | ast::RedirectMode::ReadWrite
+                    | ast::RedirectMode::HereDoc { .. }
+                    | ast::RedirectMode::HereString => 0,
+                    ast::RedirectMode::Write
+                    | ast::RedirectMode::Clobber
+                    | ast::RedirectMode::WriteAppend
+                    | ast::RedirectMode::WriteDup => 1,
+                },
+            };
+
+            let target = match redirect.mode {
+                ast::RedirectMode::ReadDup | ast::RedirectMode::WriteDup => {
+                    if redirect.file == "-" {
+                        None
+                    } else {
+                        let src = redirect.file.parse::<RawFd>().map_err(|_| {
+                            redirect_error(
+                                &redirect.file,
+                                io::Error::new(io::ErrorKind::InvalidInput, "ambiguous redirect"),
+                            )
+                        })?;
+                        let file = match table.get(&src) {
+                            Some(Some(file)) => {
+                                file.try_clone().and_then(|f| dup_fd(f.as_raw_fd()))
+                            },
+                            Some(None) => Err(io::Error::from_raw_os_error(nix::libc::EBADF)),
+                            None => dup_fd(src),
+                        };
+                        Some(file.map_err(|e| redirect_error(&redirect.file, e))?)
+                    }
+                },
+                ast::RedirectMode::HereDoc { ref body, expand } => {
+                    let contents = match expand {
+                        true => self.expand_here_document(body)?,
+                        false => body.clone(),
+                    };
+                    Some(
+                        here_document_file(&contents)
+                            .map_err(|e| redirect_error(&redirect.file, e))?,
+                    )
+                },
+                ast::RedirectMode::HereString => {
+                    let mut contents = self.expand_word(&redirect.file)?;
+                    contents.push('\n');
+                    Some(
+                        here_document_file(&contents)
+                            .map_err(|e| redirect_error(&redirect.file, e))?,
+                    )
+                },
+                ref mode => {
+                    let mut expanded = self.expand_arg(&redirect.file)?;
+                    if expanded.len() != 1 {
+                        return Err(redirect_error(
+                            &redirect.file,
+                            io::Error::new(io::ErrorKind::InvalidInput, "ambiguous redirect"),
+                        ));
+                    }
+                    let path = expanded.remove(0);
+                    let noclobber = self.ctx.options().noclobber;
+                    Some(
+                        open_redirect(&path, mode, noclobber)
+                            .map_err(|e| redirect_error(&path, e))?,
+                    )
+                },
+            };
+            table.insert(fd, target);
+        }
+
+        let mut fds = vec![];
+        let stdin = match table.remove(&0) {
+            Some(Some(file)) => Stdin::File(file),
+            Some(None) => {
+                fds.push((0, None));
+                Stdin::Inherit
+            },
+            None => Stdin::Inherit,
+        };
+        let mut output = |fd: RawFd| match table.remove(&fd) {
+            Some(Some(file)) => Output::File(file),
+            Some(None) => {
+                fds.push((fd, None));
+                Output::Inherit
+            },
+            None => Output::Inherit,
+        };
+        let stdout = output(1);
+        let stderr = output(2);
+        fds.extend(table);
+
+        Ok(CommandIo {
+            stdin,
+            stdout,
+            stderr,
+            fds,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        fs::{self, File},
+        io::{Read, Seek, SeekFrom, Write},
+        os::fd::{AsRawFd, FromRawFd},
+        process::ExitStatus,
+        sync::{Mutex, PoisonError},
+    };
+
+    use nix::unistd::{close, dup, dup2, mkstemp};
+    use shrs_job::JobManager;
+
+    use super::{eval, exit_code, exit_status, split_assignments};
+    use crate::{ast, Lexer, Parser, ShellContext, ShellOptions, TrapCondition};
+
+    /// State of a shell that only runs external commands
+    #[derive(Default)]
+    struct Context {
+        vars: HashMap<String, String>,
+        arrays: HashMap<String, Vec<String>>,
+        exit_status: i32,
+        last_background_pid: Option<u32>,
+        options: ShellOptions,
+        positional_args: Vec<String>,
+        functions: HashMap<String, Box<ast::Command>>,
+        traps: HashMap<TrapCondition, String>,
+    }
+
+    impl ShellContext for Context {
+        fn get_var(&self, name: &str) -> Option<String> {
+            self.vars
+                .get(name)
+                .cloned()
+                .or_else(|| std::env::var(name).ok())
+        }
+        fn set_var(&mut self, name: &str, value: &str) {
+            self.vars.insert(name.to_string(), value.to_string());
+        }
+        fn unset_var(&mut self, name: &str) {
+            self.vars.remove(name);
+            self.arrays.remove(name);
+        }
+        fn get_array(&self, name: &str) -> Option<Vec<String>> {
+            self.arrays.get(name).cloned()
+        }
+        fn set_array(&mut self, name: &str, values: Vec<String>) {
+            self.arrays.insert(name.to_string(), values);
+        }
+        fn exit_status(&self) -> i32 {
+            self.exit_status
+        }
+        fn set_exit_status(&mut self, status: i32) {
+            self.exit_status = status;
+        }
+        fn shell_name(&self) -> String {
+            String::from("shrs")
+        }
+        fn last_background_pid(&self) -> Option<u32> {
+            self.last_background_pid
+        }
+        fn set_last_background_pid(&mut self, pid: u32) {
+            self.last_background_pid = Some(pid);
+        }
+        fn options(&self) -> ShellOptions {
+            self.options
+        }
+        fn positional_args(&self) -> Vec<String> {
+            self.positional_args.clone()
+        }
+        fn set_positional_args(&mut self, args: Vec<String>) {
+            self.positional_args = args;
+        }
+        fn get_function(&self, name: &str) -> Option<Box<ast::Command>> {
+            self.functions.get(name).cloned()
+        }
+        fn set_function(&mut self, name: &str, body: Box<ast::Command>) {
+            self.functions.insert(name.to_string(), body);
+        }
+        fn get_trap(&self, condition: TrapCondition) -> Option<String> {
+            self.traps.get(&condition).cloned()
+        }
+        fn remove_trap(&mut self, condition: TrapCondition) {
+            self.traps.remove(&condition);
+        }
+        // `exit` is handled by the evaluator itself, once the shell says it's a builtin
+        fn is_builtin(&self, name: &str) -> bool {
+            matches!(name, "exit" | "greet" | "setvar")
+        }
+        fn run_builtin(&mut self, args: &[String]) -> ExitStatus {
+            match args[0].as_str() {
+                "greet" => {
+                    let mut stdout = std::io::stdout().lock();
+                    writeln!(stdout, "hello {}", args[1..].join(" ")).unwrap();
+                    stdout.flush().unwrap();
+                    exit_status(0)
+                },
+                "setvar" => {
+                    self.set_var(&args[1], &args[2]);
+                    exit_status(0)
+                },
+                _ => exit_status(127),
+            }
+        }
+    }
+
+    /// Commands write to the standard streams of the test process, so only one runs at a time
+    static STREAMS: Mutex<()> = Mutex::new(());
+
+    /// Create a file that output can be redirected to, which is removed right away
+    fn output_file() -> File {
+        let (fd, path) = mkstemp(&std::env::temp_dir().join("shrs-test-XXXXXX")).unwrap();
+        fs::remove_file(path).unwrap();
+        unsafe { File::from_raw_fd(fd) }
+    }
+
+    fn contents(mut file: File) -> String {
+        let mut contents = String::new();
+        file.seek(SeekFrom::Start(0)).unwrap();
+        file.read_to_string(&mut contents).unwrap();
+        contents
+    }
+
+    /// Evaluate commands without job control, returning the exit code along with what was
+    /// written to stdout and stderr
+    fn run_with_stderr(input: &str) -> (i32, String, String) {
+        run_in(&mut Context::default(), input)
+    }
+
+    fn run_in(ctx: &mut Context, input: &str) -> (i32, String, String) {
+        let _lock = STREAMS.lock().unwrap_or_else(PoisonError::into_inner);
+        let (stdout, stderr) = (output_file(), output_file());
+        let saved = [(1, &stdout), (2, &stderr)].map(|(fd, file)| {
+            let saved = dup(fd).unwrap();
+            dup2(file.as_raw_fd(), fd).unwrap();
+            (fd, saved)
+        });
+
+        let mut job_manager = JobManager::default();
+        job_manager.set_job_control(false);
+        let status = eval(&mut job_manager, ctx, Parser::default(), Lexer::new(input));
+
+        for (fd, saved) in saved {
+            dup2(saved, fd).unwrap();
+            close(saved).unwrap();
+        }
+        let status = status.unwrap_or_else(|e| panic!("{input}: {e}"));
+        (exit_code(status), contents(stdout), contents(stderr))
+    }
+
+    fn run(input: &str) -> (i32, String) {
+        let (status, stdout, _) = run_with_stderr(input);
+        (status, stdout)
+    }
+
+    fn assert_run(input: &str, status: i32, stdout: &str) {
+        assert_eq!(run(input), (status, stdout.to_string()), "{input}");
+    }
+
+    #[test]
+    fn prefix_assignments() {
+        let words = |words: &[&str]| -> Vec<ast::Word> {
+            words
+                .iter()
+                .map(|word| ast::Word::new(word, 0, 0))
+                .collect()
+        };
+        let args = words(&["A=1", "_b=x=y", "cmd", "C=2"]);
+        let (assigns, rest) = split_assignments(&[], &args);
+        assert_eq!(assigns, vec![("A", "1"), ("_b", "x=y")]);
+        assert_eq!(rest, words(&["cmd", "C=2"]));
+
+        let args = words(&["1A=1"]);
+        let (assigns, rest) = split_assignments(&[], &args);
+        assert!(assigns.is_empty());
+        assert_eq!(rest, args);
+    }
+
+    #[test]
+    fn and_or_lists() {
+        assert_run("true && echo a", 0, "a\n");
+        assert_run("false && echo a", 1, "");
+        assert_run("false || echo b", 0, "b\n");
+        assert_run("true || echo b", 0, "");
+        assert_run("false && echo a || echo b", 0, "b\n");
+        assert_run("true || echo a && echo b", 0, "b\n");
+        assert_run("sh -c 'exit 3' || sh -c 'exit 4'", 4, "");
+    }
+
+    #[test]
+    fn not() {
+        assert_run("! true", 1, "");
+        assert_run("! sh -c 'exit 3'", 0, "");
+        assert_run("! false && echo a", 0, "a\n");
+        assert_run("! echo a | grep -q b", 0, "");
+    }
+
+    #[test]
+    fn pipelines() {
+        assert_run("printf 'b\\na\\n' | sort", 0, "a\nb\n");
+        assert_run("echo abc | tr a x | tr b y", 0, "xyc\n");
+        // the status of a pipeline is the one of its last command
+        assert_run("false | true", 0, "");
+        assert_run("true | false", 1, "");
+        assert_run("echo a | grep -q b || echo none", 0, "none\n");
+    }
+
+    #[test]
+    fn builtins() {
+        assert_run("greet a b", 0, "hello a b\n");
+        assert_run("greet a | tr a-z A-Z", 0, "HELLO A\n");
+        assert_run("echo a | greet b | cat", 0, "hello b\n");
+        assert_run("greet >/dev/null && echo a", 0, "a\n");
+        assert_run("if greet >/dev/null; then echo a; fi", 0, "a\n");
+        assert_run(
+            "for x in a b; do greet $x; done | sort -r",
+            0,
+            "hello b\nhello a\n",
+        );
+        // builtins change the shell they run in, unless they are part of a pipeline
+        assert_run("setvar x 1 && echo $x", 0, "1\n");
+        assert_run("x=0; setvar x 1 | true; echo $x", 0, "0\n");
+        assert_run("{ setvar x 1; greet $x; } | cat", 0, "hello 1\n");
+    }
+
+    #[test]
+    fn sequential_lists() {
+        assert_run("echo a; echo b", 0, "a\nb\n");
+        assert_run("echo a\n\necho b\n", 0, "a\nb\n");
+        assert_run("false; true", 0, "");
+        assert_run("true; false", 1, "");
+        assert_run("sleep 0.1 & echo a", 0, "a\n");
+    }
+
+    #[test]
+    fn brace_groups() {
+        assert_run("{ echo a; echo b; } | tr ab xy", 0, "x\ny\n");
+        assert_run("{ true; false; }", 1, "");
+        // groups run in the shell itself
+        assert_run("x=1; { x=2; }; echo $x", 0, "2\n");
+    }
+
+    #[test]
+    fn subshells() {
+        assert_run("x=1; (x=2; echo $x); echo $x", 0, "2\n1\n");
+        assert_run("(exit 3)", 3, "");
+        assert_run("(exit 3) || echo failed", 0, "failed\n");
+        assert_run("(echo a; echo b) | sort -r", 0, "b\na\n");
+        assert_run("echo a | (cat; echo b)", 0, "a\nb\n");
+        assert_run("(sh -c 'kill -TERM $$')", 143, "");
+    }
+
+    #[test]
+    fn quiet_subshells() {
+        // forked shells don't report anything about the descriptors they close
+        for input in ["(true)", "echo $(echo a) | (cat)", "(ls -d /) | (cat)"] {
+            let (status, _, stderr) = run_with_stderr(input);
+            assert_eq!((status, stderr.as_str()), (0, ""), "{input}");
+        }
+    }
+
+    #[test]
+    fn if_statements() {
+        assert_run("if true; then echo a; fi", 0, "a\n");
+        assert_run("if false; then echo a; fi", 0, "");
+        assert_run("if false; then echo a; else echo b; fi", 0, "b\n");
+        assert_run(
+            "if false; then echo a; elif true; then echo b; else echo c; fi",
+            0,
+            "b\n",
+        );
+        // the status is the one of the branch that ran
+        assert_run("if true; then sh -c 'exit 3'; fi", 3, "");
+        assert_run("if sh -c 'exit 3'; then :; else echo $?; fi", 0, "3\n");
+    }
+
+    #[test]
+    fn loops() {
+        assert_run(
+            "i=0; while test $i -lt 3; do echo $i; i=$((i + 1)); done",
+            0,
+            "0\n1\n2\n",
+        );
+        assert_run(
+            "i=0; until test $i -eq 2; do i=$((i + 1)); echo $i; done",
+            0,
+            "1\n2\n",
+        );
+        assert_run("while false; do echo a; done", 0, "");
+        assert_run(
+            "i=0; while test $i -lt 2; do i=$((i + 1)); false; done",
+            1,
+            "",
+        );
+    }
+
+    #[test]
+    fn loop_control() {
+        assert_run("while true; do echo a; break; echo b; done", 0, "a\n");
+        assert_run(
+            "i=0; while test $i -lt 3; do i=$((i + 1)); test $i -eq 2 && continue; echo $i; done",
+            0,
+            "1\n3\n",
+        );
+        assert_run(
+            "while true; do while true; do break 2; done; echo inner; done; echo out",
+            0,
+            "out\n",
+        );
+        assert_run(
+            "i=0; while test $i -lt 2; do i=$((i + 1)); until false; do continue 2; done; done; echo $i",
+            0,
+            "2\n",
+        );
+        // counts larger than the number of loops exit all of them
+        assert_run("while true; do break 5; done; echo out", 0, "out\n");
+    }
+
+    #[test]
+    fn functions() {
+        assert_run("f() { echo \"$# $1 $2\"; }; f a 'b c'", 0, "2 a b c\n");
+        assert_run("f() { sh -c 'exit 4'; }; f", 4, "");
+        assert_run("f() { echo $1; }; f a | tr a b", 0, "b\n");
+        // positional parameters are restored after the call
+        assert_run("g() { echo $1; }; f() { g b; echo $1; }; f a", 0, "b\na\n");
+        assert_run("f() { x=2; }; x=1; f; echo $x", 0, "2\n");
+        assert_run("f() { echo $x; }; x=1 f; echo \"[$x]\"", 0, "1\n[]\n");
+    }
+
+    #[test]
+    fn return_from_functions() {
+        assert_run("f() { echo a; return 3; echo b; }; f", 3, "a\n");
+        assert_run("f() { false; return; }; f", 1, "");
+        assert_run(
+            "f() { while true; do return 2; done; echo b; }; f; echo $?",
+            0,
+            "2\n",
+        );
+    }
+
+    #[test]
+    fn redirected_functions() {
+        // the function still runs in the shell, so it can change its variables
+        assert_run(
+            "f() { x=2; echo a; echo b >&2; return 3; }; t=$(mktemp); x=1; f > $t 2>&1; echo $? $x; cat $t; rm $t",
+            0,
+            "3 2\na\nb\n",
+        );
+        assert_run(
+            "f() { cat; }; t=$(mktemp); echo a > $t; f < $t; rm $t",
+            0,
+            "a\n",
+        );
+        assert_run(
+            "f() { x=2; }; x=1; f > /nonexistent/file; echo $? $x",
+            0,
+            "1 1\n",
+        );
+    }
+
+    #[test]
+    fn exit_statuses() {
+        // eval returns the status of the foreground job
+        assert_run("sh -c 'exit 7'", 7, "");
+        assert_run("sh -c 'exit 3' | sh -c 'exit 5'", 5, "");
+        assert_run("sh -c 'kill -9 $$'", 137, "");
+        assert_run("shrs-test-missing-command", 127, "");
+        assert_run("t=$(mktemp); $t; echo $?; rm $t", 0, "126\n");
+
+        // and it's the status the shell keeps for `$?`
+        let mut ctx = Context::default();
+        assert_eq!(run_in(&mut ctx, "sh -c 'kill -TERM $$'").0, 143);
+        assert_eq!(ctx.exit_status, 143);
+    }
+
+    #[test]
+    fn special_parameters() {
+        assert_run("false; echo $?", 0, "1\n");
+        assert_run("sh -c 'exit 7'; echo $?; echo $?", 0, "7\n0\n");
+        assert_run("echo $0", 0, "shrs\n");
+        assert_run("echo [$-]", 0, "[]\n");
+
+        // subshells report the process ID of the shell they were forked from
+        let pid = std::process::id();
+        assert_run("echo $$; (echo $$)", 0, &format!("{pid}\n{pid}\n"));
+
+        let (status, stdout) = run("echo [$!]\nsleep 0 & echo $!");
+        let (unset, background) = stdout.split_once('\n').unwrap();
+        assert_eq!((status, unset), (0, "[]"));
+        assert!(background.trim().parse::<u32>().is_ok_and(|bg| bg != pid));
+    }
+
+    #[test]
+    fn option_flags() {
+        let mut ctx = Context::default();
+        ctx.options.errexit = true;
+        ctx.options.noglob = true;
+        assert_eq!(
+            run_in(&mut ctx, "echo $-"),
+            (0, "ef\n".to_string(), String::new())
+        );
     }
 }
@@ -0,0 +1,676 @@
+//! Expansion of the words of a command into the fields passed to it
+
+use std::{
+    env,
+    fs::File,
+    io::{self, Read},
+    os::fd::{AsRawFd, FromRawFd},
+};
+
+use nix::{
+    fcntl::{fcntl, FcntlArg},
+    unistd::User,
+};
+use shrs_job::{Output, Stdin};
+
+use crate::{
+    arith::eval_arith,
+    ast,
+    brace::expand_braces,
+    eval::{create_pipe, CommandIo, Evaluator, MIN_SHELL_FD},
+    glob::{escape, expand_pathname, Pattern},
+    word::{parse_assignment, parse_here_document, parse_word, WordPart},
+    Lexer, Parser, PosixError,
+};
+
+/// Characters fields are split at when `IFS` is not set
+const DEFAULT_IFS: &str = " \t\n";
+
+/// Field produced by expanding a word
+#[derive(Default)]
+struct Field {
+    /// Text of the field, after quote removal
+    text: String,
+    /// The field as a pattern, with the quoted characters escaped
+    pattern: String,
+    /// Whether the field contained quotes, which keeps it even if it is empty
+    quoted: bool,
+}
+
+/// Fields that the expansions of a word are collected into
+struct Fields {
+    fields: Vec<Field>,
+    current: Field,
+    /// Characters unquoted expansions are split at, or [None] if they aren't split
+    ifs: Option<String>,
+    /// Whether the last field was ended by whitespace in `IFS`
+    after_space: bool,
+}
+
+impl Fields {
+    fn new(ifs: Option<String>) -> Self {
+        Fields {
+            fields: vec![],
+            current: Field::default(),
+            ifs,
+            after_space: false,
+        }
+    }
+
+    /// Add text to the current field
+    fn push(&mut self, s: &str, quoted: bool) {
+        if s.is_empty() {
+            return;
+        }
+        self.after_space = false;
+        self.current.text.push_str(s);
+        if quoted {
+            self.current.pattern.push_str(&escape(s));
+        } else {
+            self.current.pattern.push_str(s);
+        }
+    }
+
+    /// Keep the current field even if nothing is added to it
+    fn mark_quoted(&mut self) {
+        self.current.quoted = true;
+    }
+
+    fn is_open(&self) -> bool {
+        !self.current.text.is_empty() || self.current.quoted
+    }
+
+    fn end_field(&mut self) {
+        self.fields.push(std::mem::take(&mut self.current));
+    }
+
+    /// Add the result of an expansion, splitting it into fields at the characters in `IFS` if it
+    /// is unquoted
+    fn push_expansion(&mut self, s: &str, quoted: bool) {
+        let ifs = match &self.ifs {
+            Some(ifs) if !quoted => ifs.clone(),
+            _ => return self.push(s, quoted),
+        };
+        let mut rest = s;
+        while let Some(i) = rest.find(|c| ifs.contains(c)) {
+            self.push(&rest[..i], false);
+            let delimiter = rest[i..].chars().next().unwrap();
+            rest = &rest[i + delimiter.len_utf8()..];
+
+            // whitespace only separates fields, other delimiters also end empty fields
+            if DEFAULT_IFS.contains(delimiter) {
+                if self.is_open() {
+                    self.end_field();
+                    self.after_space = true;
+                }
+            } else {
+                if self.is_open() || !self.after_space {
+                    self.end_field();
+                }
+                self.after_space = false;
+            }
+        }
+        self.push(rest, false);
+    }
+
+    fn finish(mut self) -> Vec<Field> {
+        if self.is_open() {
+            self.end_field();
+        }
+        self.fields
+    }
+}
+
+impl Evaluator<'_> {
+    /// Expand each of the words of a command, in order
+    pub(crate) fn expand_args(&mut self, args: &[ast::Word]) -> Result<Vec<String>, PosixError> {
+        let mut expanded = vec![];
+        for arg in args {
+            expanded.extend(self.expand_arg(arg.as_str())?);
+        }
+        Ok(expanded)
+    }
+
+    /// Expand a word into the arguments it stands for
+    pub(crate) fn expand_arg(&mut self, arg: &str) -> Result<Vec<String>, PosixError> {
+        let ifs = self
+            .ctx
+            .get_var("IFS")
+            .unwrap_or_else(|| DEFAULT_IFS.to_string());
+        // brace expansion happens before all the other expansions, words that look like
+        // assignments get tilde expansion after `=` and `:` like the values of assignments do
+        let mut fields = vec![];
+        for word in expand_braces(arg) {
+            let parts = match word.split_once('=') {
+                Some((name, _)) if is_name(name) => parse_assignment(&word)?,
+                _ => parse_word(&word)?,
+            };
+            fields.extend(self.expand_fields(&parts, Some(ifs.clone()))?);
+        }
+
+        let options = self.ctx.options();
+        let mut expanded = vec![];
+        for field in fields {
+            if options.noglob {
+                expanded.push(field.text);
+                continue;
+            }
+            match expand_pathname(&field.pattern, &options) {
+                Some(files) if !files.is_empty() => expanded.extend(files),
+                // patterns matching nothing are left as is, unless `failglob` or `nullglob` is on
+                Some(_) if options.failglob => {
+                    return Err(PosixError::Expansion(format!("no match: {}", field.text)))
+                },
+                Some(_) if options.nullglob => {},
+                _ => expanded.push(field.text),
+            }
+        }
+        Ok(expanded)
+    }
+
+    /// Expand a word without splitting it or matching it against files
+    pub(crate) fn expand_word(&mut self, word: &str) -> Result<String, PosixError> {
+        let fields = self.expand_fields(&parse_word(word)?, None)?;
+        Ok(fields
+            .into_iter()
+            .map(|field| field.text)
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+
+    /// Expand a word into a pattern, in which only the unquoted pattern characters are special
+    pub(crate) fn expand_pattern(&mut self, word: &str) -> Result<String, PosixError> {
+        let fields = self.expand_fields(&parse_word(word)?, None)?;
+        Ok(fields
+            .into_iter()
+            .map(|field| field.pattern)
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+
+    /// Expand a word into a regular expression, in which only the unquoted characters are special
+    pub(crate) fn expand_regex(&mut self, word: &str) -> Result<String, PosixError> {
+        let mut regex = String::new();
+        for part in parse_word(word)? {
+            let quoted = matches!(part, WordPart::Quoted(_) | WordPart::DoubleQuoted(_));
+            let text = self
+                .expand_fields(std::slice::from_ref(&part), None)?
+                .into_iter()
+                .map(|field| field.text)
+                .collect::<Vec<_>>()
+                .join(" ");
+            match quoted {
+                true => regex.push_str(&regex::escape(&text)),
+                false => regex.push_str(&text),
+            }
+        }
+        Ok(regex)
+    }
+
+    /// Expand the value of a variable assignment, which is not split or matched against files
+    pub(crate) fn expand_assignment(&mut self, value: &str) -> Result<String, PosixError> {
+        let fields = self.expand_fields(&parse_assignment(value)?, None)?;
+        Ok(fields
+            .into_iter()
+            .map(|field| field.text)
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+
+    /// Expand the body of a here-document, which is never split or matched against files
+    pub(crate) fn expand_here_document(&mut self, body: &str) -> Result<String, PosixError> {
+        let parts = parse_here_document(body)?;
+        let mut fields = Fields::new(None);
+        self.expand_parts(&parts, true, &mut fields)?;
+        Ok(fields
+            .finish()
+            .into_iter()
+            .map(|field| field.text)
+            .collect::<Vec<_>>()
+            .join(" "))
+    }
+
+    /// Perform tilde expansion, parameter expansion, command substitution and arithmetic
+    /// expansion on the parts of a word, followed by quote removal
+    ///
+    /// If `ifs` is set, the results of unquoted expansions are split into fields at its
+    /// characters.
+    fn expand_fields(
+        &mut self,
+        parts: &[WordPart],
+        ifs: Option<String>,
+    ) -> Result<Vec<Field>, PosixError> {
+        let mut fields = Fields::new(ifs);
+        self.expand_parts(parts, false, &mut fields)?;
+        Ok(fields.finish())
+    }
+
+    fn expand_parts(
+        &mut self,
+        parts: &[WordPart],
+        quoted: bool,
+        fields: &mut Fields,
+    ) -> Result<(), PosixError> {
+        for part in parts {
+            match part {
+                WordPart::Literal(s) => fields.push(s, quoted),
+                WordPart::Quoted(s) => {
+                    fields.mark_quoted();
+                    fields.push(s, true);
+                },
+                WordPart::DoubleQuoted(inner) => {
+                    // "$@" without any positional parameters expands to no fields at all, as does
+                    // "${name[@]}" of an empty array
+                    let only_args = match inner.as_slice() {
+                        [WordPart::Param(name)] => name == "@",
+                        [WordPart::BracedParam(expr)] => {
+                            expr == "@" || matches!(array_subscript(expr), Some((_, "@")))
+                        },
+                        _ => false,
+                    };
+                    if !only_args {
+                        fields.mark_quoted();
+                    }
+                    self.expand_parts(inner, true, fields)?;
+                },
+                WordPart::Tilde(user) => {
+                    let home = match user.as_str() {
+                        "" => self.ctx.get_var("HOME").or_else(|| {
+                            dirs::home_dir().map(|home| home.to_string_lossy().to_string())
+                        }),
+                        // `~+` is the current directory and `~-` the previous one
+                        "+" => self.ctx.get_var("PWD").or_else(|| {
+                            env::current_dir()
+                                .ok()
+                                .map(|dir| dir.to_string_lossy().to_string())
+                        }),
+                        "-" => self.ctx.get_var("OLDPWD"),
+                        user => User::from_name(user)
+                            .ok()
+                            .flatten()
+                            .map(|user| user.dir.to_string_lossy().to_string()),
+                    };
+                    match home {
+                        Some(home) => fields.push(&home, true),
+                        None => fields.push(&format!("~{user}"), false),
+                    }
+                },
+                WordPart::Param(name) | WordPart::BracedParam(name)
+                    if name == "@" || name == "*" =>
+                {
+                    let args = self.ctx.positional_args();
+                    self.expand_list(&args, name == "*", quoted, fields)
+                },
+                WordPart::Param(name) => {
+                    let value = self.expand_set_param(name)?;
+                    fields.push_expansion(&value, quoted);
+                },
+                WordPart::BracedParam(expr) => match array_subscript(expr) {
+                    Some((name, subscript @ ("@" | "*"))) => {
+                        let values = self.ctx.get_array(name).unwrap_or_default();
+                        self.expand_list(&values, subscript == "*", quoted, fields)
+                    },
+                    _ => {
+                        let value = self.expand_braced_param(expr)?;
+                        fields.push_expansion(&value, quoted);
+                    },
+                },
+                WordPart::CommandSubst(cmd) => {
+                    let output = self.command_substitution(cmd)?;
+                    fields.push_expansion(&output, quoted);
+                },
+                WordPart::Arith(expr) => {
+                    let value = self.arithmetic_expansion(expr)?;
+                    fields.push_expansion(&value, quoted);
+                },
+                WordPart::ProcessSubst(cmd, output) => {
+                    let path = self.process_substitution(cmd, *output)?;
+                    fields.push(&path, true);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Expand `$@` or `$*`, or all the values of an array with `${name[@]}` or `${name[*]}`
+    ///
+    /// Each value is a separate field, except for `"$*"` and `"${name[*]}"` which join them with
+    /// the first character of `IFS`.
+    fn expand_list(&mut self, args: &[String], joined: bool, quoted: bool, fields: &mut Fields) {
+        if quoted && joined {
+            let separator = match self.ctx.get_var("IFS") {
+                Some(ifs) => ifs.chars().next().map(String::from).unwrap_or_default(),
+                None => String::from(" "),
+            };
+            fields.push(&args.join(&separator), true);
+            return;
+        }
+        for (i, arg) in args.iter().enumerate() {
+            if quoted {
+                if i > 0 {
+                    fields.end_field();
+                }
+                fields.mark_quoted();
+                fields.push(arg, true);
+            } else {
+                if i > 0 && fields.is_open() {
+                    fields.end_field();
+                }
+                fields.push_expansion(arg, false);
+            }
+        }
+    }
+
+    /// Run a command in a subshell, returning what it writes to stdout with trailing newlines
+    /// removed
+    fn command_substitution(&mut self, cmd: &str) -> Result<String, PosixError> {
+        let parsed = Parser::default()
+            .parse(Lexer::new(cmd))
+            .map_err(PosixError::Parse)?;
+
+        let (mut read, write): (File, File) =
+            create_pipe().map_err(|e| PosixError::Eval(e.into()))?;
+        let io = CommandIo {
+            stdin: Stdin::Inherit,
+            stdout: Output::File(write),
+            stderr: Output::Inherit,
+            fds: vec![],
+        };
+        let (procs, pgid) = self.fork(cmd, io, true, |evaluator| evaluator.run_command(&parsed))?;
+
+        // read everything before waiting, the subshell would block once the pipe is full
+        let mut output = vec![];
+        let read_result = read.read_to_end(&mut output);
+        let status = self.run_job(procs, pgid, true)?;
+        read_result.map_err(|e: io::Error| PosixError::Eval(e.into()))?;
+        self.last_substitution = Some(status);
+
+        let output = String::from_utf8_lossy(&output);
+        Ok(output.trim_end_matches('\n').to_string())
+    }
+
+    /// Run a command in the background connected to a pipe, returning the `/dev/fd` path of the
+    /// other end of the pipe
+    ///
+    /// With `output` set the command reads what is written to the path, otherwise what it writes
+    /// can be read from the path. The end of the pipe stays open in the shell until the command
+    /// using it completes, see [Evaluator::finish_process_substitutions].
+    fn process_substitution(&mut self, cmd: &str, output: bool) -> Result<String, PosixError> {
+        let parsed = Parser::default()
+            .parse(Lexer::new(cmd))
+            .map_err(PosixError::Parse)?;
+
+        let (read, write) = create_pipe().map_err(|e| PosixError::Eval(e.into()))?;
+        let (stdin, stdout, shell_end) = match output {
+            true => (Stdin::File(read), Output::Inherit, write),
+            false => (Stdin::Inherit, Output::File(write), read),
+        };
+        let io = CommandIo {
+            stdin,
+            stdout,
+            stderr: Output::Inherit,
+            fds: vec![],
+        };
+        let (mut procs, _) =
+            self.fork(cmd, io, false, |evaluator| evaluator.run_command(&parsed))?;
+
+        // the descriptor is inherited by the commands the shell starts, unlike its other ones
+        let fd = fcntl(shell_end.as_raw_fd(), FcntlArg::F_DUPFD(MIN_SHELL_FD))
+            .map_err(|e| PosixError::Eval(e.into()))?;
+        let shell_end = unsafe { File::from_raw_fd(fd) };
+        if let Some(proc) = procs.pop() {
+            self.process_substitutions.push((proc, shell_end));
+        }
+        Ok(format!("/dev/fd/{fd}"))
+    }
+
+    /// Close the pipes of the process substitutions started after the first `start` of them and
+    /// wait for their commands to exit, once the command using them has completed
+    pub(crate) fn finish_process_substitutions(&mut self, start: usize) {
+        if start >= self.process_substitutions.len() {
+            return;
+        }
+        for (mut proc, shell_end) in self.process_substitutions.split_off(start) {
+            drop(shell_end);
+            let _ = proc.wait();
+        }
+    }
+
+    /// Evaluate the expression of an arithmetic expansion, after expanding parameters and command
+    /// substitutions in it
+    fn arithmetic_expansion(&mut self, expr: &str) -> Result<String, PosixError> {
+        let expanded = self.expand_word(expr)?;
+        eval_arith(&mut *self.ctx, &expanded)
+            .map(|n| n.to_string())
+            .map_err(|e| PosixError::Expansion(format!("{}: {e}", expanded.trim())))
+    }
+
+    /// Expand the contents of a `${...}` parameter expansion
+    fn expand_braced_param(&mut self, expr: &str) -> Result<String, PosixError> {
+        let bad_substitution = || PosixError::Expansion(format!("${{{expr}}}: bad substitution"));
+
+        // ${#VAR} is the length of the value, and ${#name[@]} the number of values in an array
+        if let Some(name) = expr.strip_prefix('#').filter(|name| !name.is_empty()) {
+            let value = match array_subscript(name) {
+                Some((name, "@" | "*")) => {
+                    let len = self.ctx.get_array(name).map_or(0, |values| values.len());
+                    return Ok(len.to_string());
+                },
+                Some((name, subscript)) => self.array_element(name, subscript)?,
+                None => Some(self.expand_set_param(name)?),
+            };
+            return Ok(value.unwrap_or_default().chars().count().to_string());
+        }
+
+        if let Some((name, subscript)) = array_subscript(expr) {
+            return match self.array_element(name, subscript)? {
+                Some(value) => Ok(value),
+                None if self.ctx.options().nounset => {
+                    Err(PosixError::Expansion(format!("{expr}: unbound variable")))
+                },
+                None => Ok(String::new()),
+            };
+        }
+
+        let name_len = match expr.chars().next() {
+            Some(c) if c.is_ascii_digit() => expr
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(expr.len()),
+            Some(c) if c.is_ascii_alphabetic() || c == '_' => expr
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(expr.len()),
+            Some('#' | '@' | '*' | '?' | '$' | '!' | '-') => 1,
+            _ => return Err(bad_substitution()),
+        };
+        let (name, op) = expr.split_at(name_len);
+        let value = self.expand_param(name);
+
+        // with a colon, the operators treat variables set to the empty string as unset
+        let (check_null, op) = match op.strip_prefix(':') {
+            Some(op) => (true, op),
+            None => (false, op),
+        };
+        let unset = match &value {
+            Some(value) => check_null && value.is_empty(),
+            None => true,
+        };
+        let Some(op_char) = op.chars().next() else {
+            return match check_null {
+                true => Err(bad_substitution()),
+                false => self.expand_set_param(name),
+            };
+        };
+        let word = &op[1..];
+
+        match op_char {
+            '-' if unset => self.expand_word(word),
+            '=' if unset => {
+                if !is_name(name) {
+                    return Err(PosixError::Expansion(format!(
+                        "${name}: cannot assign in this way"
+                    )));
+                }
+                let value = self.expand_word(word)?;
+                self.ctx.set_var(name, &value);
+                Ok(value)
+            },
+            '?' if unset => {
+                let message = match self.expand_word(word)? {
+                    message if message.is_empty() => String::from("parameter null or not set"),
+                    message => message,
+                };
+                Err(PosixError::Expansion(format!("{name}: {message}")))
+            },
+            '+' if unset => Ok(String::new()),
+            '+' => self.expand_word(word),
+            '-' | '=' | '?' => Ok(value.unwrap_or_default()),
+            '%' | '#' if !check_null => {
+                let (longest, pattern) = match word.strip_prefix(op_char) {
+                    Some(pattern) => (true, pattern),
+                    None => (false, word),
+                };
+                let value = self.expand_set_param(name)?;
+                let pattern =
+                    Pattern::new(&self.expand_pattern(pattern)?, self.ctx.options().extglob);
+                Ok(remove_pattern(&value, &pattern, op_char == '%', longest))
+            },
+            _ => Err(bad_substitution()),
+        }
+    }
+
+    /// Element of an array at an index that is an arithmetic expression, negative indices count
+    /// from the end of the array
+    ///
+    /// Variables that aren't arrays are treated as arrays with their value as the only element.
+    fn array_element(&mut self, name: &str, subscript: &str) -> Result<Option<String>, PosixError> {
+        let index = self
+            .arithmetic_expansion(subscript)?
+            .parse::<i64>()
+            .unwrap_or_default();
+        let values = match self.ctx.get_array(name) {
+            Some(values) => values,
+            None => self.ctx.get_var(name).into_iter().collect(),
+        };
+        let index = match index < 0 {
+            true => values.len() as i64 + index,
+            false => index,
+        };
+        Ok(usize::try_from(index)
+            .ok()
+            .and_then(|index| values.get(index).cloned()))
+    }
+
+    /// Value of a parameter that is being substituted, which is an error if it is not set and
+    /// `set -u` is on
+    fn expand_set_param(&self, name: &str) -> Result<String, PosixError> {
+        match self.expand_param(name) {
+            Some(value) => Ok(value),
+            None if self.ctx.options().nounset => {
+                Err(PosixError::Expansion(format!("{name}: unbound variable")))
+            },
+            None => Ok(String::new()),
+        }
+    }
+
+    /// Value of a parameter, which is either a variable, one of the positional parameters or a
+    /// special parameter
+    fn expand_param(&self, name: &str) -> Option<String> {
+        match name {
+            "#" => Some(self.ctx.positional_args().len().to_string()),
+            "@" | "*" => Some(self.ctx.positional_args().join(" ")),
+            "?" => Some(self.ctx.exit_status().to_string()),
+            "$" => Some(self.shell_pid.to_string()),
+            "!" => self.ctx.last_background_pid().map(|pid| pid.to_string()),
+            "-" => Some(self.option_flags()),
+            name if name.chars().all(|c| c.is_ascii_digit()) => match name.parse::<usize>() {
+                Ok(0) => Some(self.ctx.shell_name()),
+                Ok(n) => self.ctx.positional_args().get(n - 1).cloned(),
+                Err(_) => None,
+            },
+            // an array expands to its first element
+            name => self
+                .ctx
+                .get_var(name)
+                .or_else(|| self.ctx.get_array(name)?.into_iter().next()),
+        }
+    }
+}
+
+/// Name and subscript of an array element like `name[1]` or `name[@]`
+fn array_subscript(expr: &str) -> Option<(&str, &str)> {
+    let (name, rest) = expr.split_once('[')?;
+    let subscript = rest.strip_suffix(']')?;
+    is_name(name).then_some((name, subscript))
+}
+
+/// Check if a string can be used as the name of a variable
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Remove the shortest or longest prefix or suffix of a value that matches a pattern
+fn remove_pattern(value: &str, pattern: &Pattern, suffix: bool, longest: bool) -> String {
+    let mut bounds = value
+        .char_indices()
+        .map(|(i, _)| i)
+        .chain(std::iter::once(value.len()))
+        .collect::<Vec<_>>();
+    // order the places to split at so that the first match is the one to remove
+    if suffix != longest {
+        bounds.reverse();
+    }
+    for i in bounds {
+        let (kept, removed) = match suffix {
+            true => value.split_at(i),
+            false => {
+                let (removed, kept) = value.split_at(i);
+                (kept, removed)
+            },
+        };
+        if pattern.matches(removed) {
+            return kept.to_string();
+        }
+    }
+    value.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{remove_pattern, Fields};
+    use crate::glob::Pattern;
+
+    #[test]
+    fn pattern_removal() {
+        let path = "path/to/file.tar.gz";
+        let remove = |pattern: &str, suffix: bool, longest: bool| {
+            remove_pattern(path, &Pattern::new(pattern, false), suffix, longest)
+        };
+        assert_eq!(remove(".*", true, false), "path/to/file.tar");
+        assert_eq!(remove(".*", true, true), "path/to/file");
+        assert_eq!(remove("*/", false, false), "to/file.tar.gz");
+        assert_eq!(remove("*/", false, true), "file.tar.gz");
+        assert_eq!(remove("x*", false, true), path);
+    }
+
+    #[test]
+    fn field_splitting() {
+        let split = |ifs: &str, prefix: &str, value: &str| {
+            let mut fields = Fields::new(Some(ifs.to_string()));
+            fields.push(prefix, true);
+            fields.push_expansion(value, false);
+            fields
+                .finish()
+                .into_iter()
+                .map(|field| field.text)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(split(" \t\n", "", "  a  b\tc\n"), vec!["a", "b", "c"]);
+        assert_eq!(split(" \t\n", "x", " a"), vec!["x", "a"]);
+        assert_eq!(split(" :", "", "a : b::c:"), vec!["a", "b", "", "c"]);
+        assert_eq!(split(":", "", ":a"), vec!["", "a"]);
+        assert_eq!(split("", "", "a b"), vec!["a b"]);
+        assert!(split(" ", "", "  ").is_empty());
+    }
+}
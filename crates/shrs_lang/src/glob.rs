@@ -0,0 +1,496 @@
+//! Shell patterns, used for pathname expansion, `case` statements and removing prefixes and
+//! suffixes of parameters
+//!
+//! Besides `*`, `?` and bracket expressions, patterns support the extended `?(...)`, `*(...)`,
+//! `+(...)`, `@(...)` and `!(...)` forms when the `extglob` option is on, and `**` matches any
+//! number of directories in pathname expansion when the `globstar` option is on.
+
+use std::{cmp::Ordering, ffi::CString, fs, path::Path, sync::Once};
+
+use nix::libc::{setlocale, strcoll, LC_COLLATE};
+
+use crate::ShellOptions;
+
+/// Characters that are special in patterns, and need to be escaped to be matched literally
+const SPECIAL_CHARS: &str = "\\*?[]()|+@!";
+
+/// Compiled shell pattern
+#[derive(Debug, Clone)]
+pub(crate) struct Pattern {
+    tokens: Vec<Token>,
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    /// Character that is matched literally
+    Char(char),
+    /// `?`, which matches any single character
+    Any,
+    /// `*`, which matches any string
+    Star,
+    /// Bracket expression like `[a-z]` or `[![:digit:]]`
+    Class(Class),
+    /// Extended pattern like `@(a|b)`, holding the operator before the parenthesis and the
+    /// alternatives inside of it
+    Group(char, Vec<Vec<Token>>),
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Char(char),
+    Range(char, char),
+    /// Character class like `[:alpha:]`
+    Named(fn(char) -> bool),
+}
+
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        let found = self.items.iter().any(|item| match *item {
+            ClassItem::Char(item) => item == c,
+            ClassItem::Range(first, last) => (first..=last).contains(&c),
+            ClassItem::Named(class) => class(c),
+        });
+        found != self.negated
+    }
+}
+
+impl Pattern {
+    /// Compile a pattern, in which backslashes escape the character after them
+    ///
+    /// Pattern characters that don't form a valid pattern, like a `[` without a closing `]`, are
+    /// matched literally.
+    pub(crate) fn new(pattern: &str, extglob: bool) -> Self {
+        let chars = pattern.chars().collect::<Vec<_>>();
+        let mut parser = PatternParser {
+            chars: &chars,
+            pos: 0,
+            extglob,
+        };
+        let mut alternatives = parser.alternatives(false).unwrap_or_default();
+        Pattern {
+            tokens: alternatives.pop().unwrap_or_default(),
+        }
+    }
+
+    /// Check if a string matches the whole pattern
+    pub(crate) fn matches(&self, s: &str) -> bool {
+        matches_tokens(&self.tokens, &s.chars().collect::<Vec<_>>())
+    }
+
+    /// Check if a file name matches the pattern, leading dots have to be matched explicitly
+    /// unless `dotglob` is on, and `.` and `..` always have to be
+    fn matches_file_name(&self, name: &str, dotglob: bool) -> bool {
+        if name.starts_with('.') && !matches!(self.tokens.first(), Some(Token::Char('.'))) {
+            let special = name == "." || name == "..";
+            if special || !dotglob {
+                return false;
+            }
+        }
+        self.matches(name)
+    }
+
+    /// The string the pattern matches if it contains no pattern characters
+    fn literal(&self) -> Option<String> {
+        self.tokens
+            .iter()
+            .map(|token| match token {
+                Token::Char(c) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+struct PatternParser<'a> {
+    chars: &'a [char],
+    pos: usize,
+    extglob: bool,
+}
+
+impl PatternParser<'_> {
+    /// Parse the `|` separated alternatives of a pattern, or of the group of an extended pattern
+    /// up to its closing parenthesis
+    ///
+    /// Returns [None] if the group is not closed.
+    fn alternatives(&mut self, in_group: bool) -> Option<Vec<Vec<Token>>> {
+        let mut alternatives = vec![];
+        let mut tokens = vec![];
+        while let Some(&c) = self.chars.get(self.pos) {
+            self.pos += 1;
+            let token = match c {
+                '\\' => Token::Char(self.next_char().unwrap_or('\\')),
+                '?' | '*' | '+' | '@' | '!'
+                    if self.extglob && self.chars.get(self.pos) == Some(&'(') =>
+                {
+                    let start = self.pos;
+                    self.pos += 1;
+                    match self.alternatives(true) {
+                        Some(group) => Token::Group(c, group),
+                        None => {
+                            self.pos = start;
+                            plain_token(c)
+                        },
+                    }
+                },
+                '[' => match self.class() {
+                    Some(class) => Token::Class(class),
+                    None => Token::Char('['),
+                },
+                '|' if in_group => {
+                    alternatives.push(std::mem::take(&mut tokens));
+                    continue;
+                },
+                ')' if in_group => {
+                    alternatives.push(tokens);
+                    return Some(alternatives);
+                },
+                c => plain_token(c),
+            };
+            // consecutive stars match the same strings as a single one
+            if matches!((&token, tokens.last()), (Token::Star, Some(Token::Star))) {
+                continue;
+            }
+            tokens.push(token);
+        }
+        if in_group {
+            return None;
+        }
+        alternatives.push(tokens);
+        Some(alternatives)
+    }
+
+    /// Parse a bracket expression after its opening `[`, leaving the position unchanged if it is
+    /// not terminated
+    fn class(&mut self) -> Option<Class> {
+        let start = self.pos;
+        let class = self.class_items();
+        if class.is_none() {
+            self.pos = start;
+        }
+        class
+    }
+
+    fn class_items(&mut self) -> Option<Class> {
+        let negated = matches!(self.chars.get(self.pos), Some('!' | '^'));
+        if negated {
+            self.pos += 1;
+        }
+        let mut items = vec![];
+        // a `]` right after the opening bracket is part of the expression
+        let mut first = true;
+        loop {
+            let c = self.next_char()?;
+            let c = match c {
+                ']' if !first => return Some(Class { negated, items }),
+                '[' if self.chars.get(self.pos) == Some(&':') => {
+                    let rest = &self.chars[self.pos + 1..];
+                    let len = rest.windows(2).position(|w| w == [':', ']'])?;
+                    let name = rest[..len].iter().collect::<String>();
+                    items.push(ClassItem::Named(named_class(&name)?));
+                    self.pos += len + 3;
+                    first = false;
+                    continue;
+                },
+                '\\' => self.next_char()?,
+                c => c,
+            };
+            first = false;
+
+            // a `-` makes a range unless it is the last character of the expression
+            if self.chars.get(self.pos) == Some(&'-')
+                && !matches!(self.chars.get(self.pos + 1), Some(']') | None)
+            {
+                self.pos += 1;
+                let last = match self.next_char()? {
+                    '\\' => self.next_char()?,
+                    last => last,
+                };
+                items.push(ClassItem::Range(c, last));
+            } else {
+                items.push(ClassItem::Char(c));
+            }
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.chars.get(self.pos).copied();
+        if c.is_some() {
+            self.pos += 1;
+        }
+        c
+    }
+}
+
+fn plain_token(c: char) -> Token {
+    match c {
+        '?' => Token::Any,
+        '*' => Token::Star,
+        c => Token::Char(c),
+    }
+}
+
+/// Predicate for a character class like `[:alpha:]`, by its name
+fn named_class(name: &str) -> Option<fn(char) -> bool> {
+    let class: fn(char) -> bool = match name {
+        "alnum" => |c| c.is_alphanumeric(),
+        "alpha" => |c| c.is_alphabetic(),
+        "blank" => |c| c == ' ' || c == '\t',
+        "cntrl" => |c| c.is_control(),
+        "digit" => |c| c.is_ascii_digit(),
+        "graph" => |c| !c.is_control() && !c.is_whitespace(),
+        "lower" => |c| c.is_lowercase(),
+        "print" => |c| !c.is_control(),
+        "punct" => |c| c.is_ascii_punctuation(),
+        "space" => |c| c.is_whitespace(),
+        "upper" => |c| c.is_uppercase(),
+        "xdigit" => |c| c.is_ascii_hexdigit(),
+        _ => return None,
+    };
+    Some(class)
+}
+
+fn matches_tokens(tokens: &[Token], s: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return s.is_empty();
+    };
+    match token {
+        Token::Char(c) => s.first() == Some(c) && matches_tokens(rest, &s[1..]),
+        Token::Any => !s.is_empty() && matches_tokens(rest, &s[1..]),
+        Token::Class(class) => {
+            s.first().is_some_and(|c| class.matches(*c)) && matches_tokens(rest, &s[1..])
+        },
+        Token::Star => (0..=s.len()).any(|i| matches_tokens(rest, &s[i..])),
+        Token::Group(op, alternatives) => (0..=s.len()).any(|i| {
+            let (group, after) = s.split_at(i);
+            let group_matches = match op {
+                '?' => group.is_empty() || matches_any(alternatives, group),
+                '*' => matches_repeated(alternatives, group),
+                '+' => (0..=group.len()).any(|j| {
+                    matches_any(alternatives, &group[..j])
+                        && matches_repeated(alternatives, &group[j..])
+                }),
+                '!' => !matches_any(alternatives, group),
+                _ => matches_any(alternatives, group),
+            };
+            group_matches && matches_tokens(rest, after)
+        }),
+    }
+}
+
+fn matches_any(alternatives: &[Vec<Token>], s: &[char]) -> bool {
+    alternatives
+        .iter()
+        .any(|alternative| matches_tokens(alternative, s))
+}
+
+/// Check if a string is made up of any number of strings that match one of the alternatives
+fn matches_repeated(alternatives: &[Vec<Token>], s: &[char]) -> bool {
+    s.is_empty()
+        || (1..=s.len())
+            .any(|i| matches_any(alternatives, &s[..i]) && matches_repeated(alternatives, &s[i..]))
+}
+
+/// Escape the pattern characters in a string so that it is matched literally
+pub(crate) fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        if SPECIAL_CHARS.contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Expand a pattern into the paths of the files it matches, sorted in the collation order of
+/// the locale
+///
+/// Returns [None] if the pattern contains no pattern characters, in which case it should not be
+/// matched against files at all.
+pub(crate) fn expand_pathname(pattern: &str, options: &ShellOptions) -> Option<Vec<String>> {
+    let components = pattern
+        .split('/')
+        .map(|component| (component, Pattern::new(component, options.extglob)))
+        .collect::<Vec<_>>();
+    if components
+        .iter()
+        .all(|(_, component)| component.literal().is_some())
+    {
+        return None;
+    }
+
+    // an absolute pattern starts with an empty component before the first slash
+    let (mut paths, components) = match pattern.starts_with('/') {
+        true => (vec![String::from("/")], &components[1..]),
+        false => (vec![String::new()], &components[..]),
+    };
+    for (i, (text, component)) in components.iter().enumerate() {
+        let last = i == components.len() - 1;
+        let mut matched = vec![];
+        for path in paths {
+            match component.literal() {
+                // an empty component comes from a trailing or repeated slash, which only matches
+                // directories
+                Some(name) if name.is_empty() => {
+                    if is_dir(&path) {
+                        matched.push(join(&path, ""));
+                    }
+                },
+                Some(name) => {
+                    let path = join(&path, &name);
+                    if !last || fs::symlink_metadata(&path).is_ok() {
+                        matched.push(path);
+                    }
+                },
+                None if options.globstar && *text == "**" => {
+                    // `**` matches any number of directories, or any files when it comes last
+                    if !last {
+                        matched.push(path.clone());
+                    }
+                    walk(&path, last, options.dotglob, &mut matched);
+                },
+                None => {
+                    for name in read_dir_names(&path) {
+                        if component.matches_file_name(&name, options.dotglob) {
+                            matched.push(join(&path, &name));
+                        }
+                    }
+                },
+            }
+        }
+        paths = matched;
+    }
+
+    paths.sort_by(|a, b| collate(a, b));
+    Some(paths)
+}
+
+/// Collect the paths of the directories below `dir`, along with the other files if `files` is set
+fn walk(dir: &str, files: bool, dotglob: bool, paths: &mut Vec<String>) {
+    for name in read_dir_names(dir) {
+        if name.starts_with('.') && !dotglob {
+            continue;
+        }
+        let path = join(dir, &name);
+        // symbolic links to directories are not followed, which could otherwise loop forever
+        let is_dir = fs::symlink_metadata(&path).is_ok_and(|metadata| metadata.is_dir());
+        if is_dir || files {
+            paths.push(path.clone());
+        }
+        if is_dir {
+            walk(&path, files, dotglob, paths);
+        }
+    }
+}
+
+/// Names of the files in a directory, with the empty path standing for the working directory
+fn read_dir_names(dir: &str) -> Vec<String> {
+    let dir = if dir.is_empty() { "." } else { dir };
+    match fs::read_dir(dir) {
+        Ok(entries) => entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+fn is_dir(path: &str) -> bool {
+    Path::new(if path.is_empty() { "." } else { path }).is_dir()
+}
+
+fn join(dir: &str, name: &str) -> String {
+    match dir.is_empty() || dir.ends_with('/') {
+        true => format!("{dir}{name}"),
+        false => format!("{dir}/{name}"),
+    }
+}
+
+/// Compare two strings in the collation order of the locale, as set by `LC_COLLATE` or `LANG`
+fn collate(a: &str, b: &str) -> Ordering {
+    static INIT_LOCALE: Once = Once::new();
+    INIT_LOCALE.call_once(|| unsafe {
+        setlocale(LC_COLLATE, c"".as_ptr());
+    });
+    match (CString::new(a), CString::new(b)) {
+        (Ok(a_c), Ok(b_c)) => unsafe { strcoll(a_c.as_ptr(), b_c.as_ptr()) }
+            .cmp(&0)
+            .then_with(|| a.cmp(b)),
+        _ => a.cmp(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{escape, Pattern};
+
+    fn matches(pattern: &str, s: &str) -> bool {
+        Pattern::new(pattern, true).matches(s)
+    }
+
+    #[test]
+    fn basic_patterns() {
+        assert!(matches("*.rs", "main.rs"));
+        assert!(matches("*", ""));
+        assert!(matches("?b", "ab"));
+        assert!(!matches("?b", "b"));
+        assert!(matches("a\\*", "a*"));
+        assert!(!matches("a\\*", "ab"));
+        assert!(matches("[", "["));
+        assert!(matches("a[b", "a[b"));
+        assert!(matches(&escape("*?[x]"), "*?[x]"));
+        assert!(!matches(&escape("*"), "main.rs"));
+    }
+
+    #[test]
+    fn bracket_expressions() {
+        assert!(matches("[a-c]", "b"));
+        assert!(!matches("[!a-c]", "b"));
+        assert!(matches("[^a-c]", "d"));
+        assert!(matches("[*]", "*"));
+        assert!(!matches("[*]", "main.rs"));
+        assert!(matches("[]]", "]"));
+        assert!(matches("[a-]", "-"));
+        assert!(matches("[[:alpha:]][[:digit:]]", "x1"));
+        assert!(!matches("[[:alpha:]]", "1"));
+        assert!(matches("[![:space:]]", "x"));
+        assert!(!matches("[[:nosuchclass:]]", "a"));
+    }
+
+    #[test]
+    fn extended_patterns() {
+        assert!(matches("@(a|b).rs", "b.rs"));
+        assert!(!matches("@(a|b).rs", "ab.rs"));
+        assert!(matches("?(x)y", "y"));
+        assert!(matches("?(x)y", "xy"));
+        assert!(!matches("?(x)y", "xxy"));
+        assert!(matches("*(ab)", ""));
+        assert!(matches("*(ab|c)", "abcab"));
+        assert!(!matches("+(ab)", ""));
+        assert!(matches("+(ab)", "abab"));
+        assert!(matches("!(*.rs)", "main.c"));
+        assert!(!matches("!(*.rs)", "main.rs"));
+        assert!(matches("*.@(c|h)", "x.h"));
+        assert!(matches("@(a|@(b|c))", "c"));
+        assert!(matches("@(a", "@(a"));
+
+        assert!(!Pattern::new("@(a|b)", false).matches("a"));
+        assert!(Pattern::new("@(a|b)", false).matches("@(a|b)"));
+    }
+
+    #[test]
+    fn file_names() {
+        let pattern = Pattern::new("*", true);
+        assert!(pattern.matches_file_name("file", false));
+        assert!(!pattern.matches_file_name(".hidden", false));
+        assert!(pattern.matches_file_name(".hidden", true));
+        assert!(!pattern.matches_file_name("..", true));
+        assert!(Pattern::new(".*", true).matches_file_name(".hidden", false));
+    }
+}
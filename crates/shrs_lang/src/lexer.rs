@@ -2,20 +2,25 @@
 
 // heavily inspired by https://github.com/nixpulvis/oursh/blob/develop/src/program/posix/lex.rs
 
-use std::str::CharIndices;
+use std::{collections::VecDeque, fmt, ops::Range, str::CharIndices};
 
 use lazy_static::lazy_static;
 use thiserror::Error;
 
+use crate::expand::is_name;
+
 lazy_static! {
     pub static ref RESERVED_WORDS: Vec<&'static str> = vec![
-        "!", "{", "}", "case", "do", "done", "elif", "else", "esac", "fi", "for", "if", "in",
-        "then", "until", "while"
+        "!", "{", "}", "[[", "]]", "case", "do", "done", "elif", "else", "esac", "fi", "for", "if",
+        "in", "then", "until", "while"
     ];
 }
 
 pub type Spanned<Token, Loc, Error> = Result<(Loc, Token, Loc), Error>;
 
+/// Looks up the value of an alias by its name
+type AliasLookup<'input> = &'input dyn Fn(&str) -> Option<&'input str>;
+
 #[allow(non_camel_case_types)]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Token<'input> {
@@ -36,6 +41,8 @@ pub enum Token<'input> {
     LBRACE,
     RBRACE,
     BANG,
+    DLBRACKET,
+    DRBRACKET,
 
     AND_IF,
     OR_IF,
@@ -47,6 +54,7 @@ pub enum Token<'input> {
     GREATAND,
     LESSGREAT,
     DLESSDASH,
+    TLESS,
     CLOBBER,
 
     IF,
@@ -69,12 +77,93 @@ pub enum Token<'input> {
     FNAME(&'input str),
     NAME(&'input str),
     IO_NUMBER(&'input str),
+    /// Body of a here-document, which directly follows its delimiter
+    HEREDOC(&'input str),
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Token::NEWLINE => "newline",
+            Token::SEMI => ";",
+            Token::AMP => "&",
+            Token::PIPE => "|",
+            Token::BACKTICK => "`",
+            Token::EQUAL => "=",
+            Token::BACKSLASH => "\\",
+            Token::SINGLEQUOTE => "'",
+            Token::DOUBLEQUOTE => "\"",
+            Token::LESS => "<",
+            Token::GREAT => ">",
+            Token::LPAREN => "(",
+            Token::RPAREN => ")",
+            Token::LBRACE => "{",
+            Token::RBRACE => "}",
+            Token::BANG => "!",
+            Token::DLBRACKET => "[[",
+            Token::DRBRACKET => "]]",
+            Token::AND_IF => "&&",
+            Token::OR_IF => "||",
+            Token::DSEMI => ";;",
+            Token::DLESS => "<<",
+            Token::DGREAT => ">>",
+            Token::LESSAND => "<&",
+            Token::GREATAND => ">&",
+            Token::LESSGREAT => "<>",
+            Token::DLESSDASH => "<<-",
+            Token::TLESS => "<<<",
+            Token::CLOBBER => ">|",
+            Token::IF => "if",
+            Token::THEN => "then",
+            Token::ELSE => "else",
+            Token::ELIF => "elif",
+            Token::FI => "fi",
+            Token::DO => "do",
+            Token::DONE => "done",
+            Token::CASE => "case",
+            Token::ESAC => "esac",
+            Token::WHILE => "while",
+            Token::UNTIL => "until",
+            Token::FOR => "for",
+            Token::IN => "in",
+            Token::HEREDOC(_) => "here-document",
+            Token::WORD(text)
+            | Token::ASSIGNMENT_WORD(text)
+            | Token::FNAME(text)
+            | Token::NAME(text)
+            | Token::IO_NUMBER(text) => text,
+        };
+        f.write_str(text)
+    }
 }
 
 #[derive(Debug, PartialEq, Eq, Error)]
 pub enum Error {
-    #[error("unrecognized character {1} in range {0}:{2}")]
+    #[error("unrecognized character `{1}`")]
     UnrecognizedChar(usize, char, usize),
+    #[error("unterminated quote or substitution in range {0}:{1}")]
+    Unterminated(usize, usize),
+    #[error("here-document starting at {0} is missing its delimiter")]
+    MissingDelimiter(usize),
+}
+
+impl Error {
+    /// Byte range of the input the error refers to
+    pub fn span(&self) -> Range<usize> {
+        match *self {
+            Error::UnrecognizedChar(start, _, end) | Error::Unterminated(start, end) => start..end,
+            Error::MissingDelimiter(start) => start..start,
+        }
+    }
+
+    /// Move the error to another span, for errors in the value of an alias
+    fn at(self, start: usize, end: usize) -> Self {
+        match self {
+            Error::UnrecognizedChar(_, ch, _) => Error::UnrecognizedChar(start, ch, end),
+            Error::Unterminated(..) => Error::Unterminated(start, end),
+            Error::MissingDelimiter(_) => Error::MissingDelimiter(start),
+        }
+    }
 }
 
 // TODO could technically make EOF a token so we don't need to do Result<Option> shinengans
@@ -83,6 +172,49 @@ pub struct Lexer<'input> {
     input: &'input str,
     chars: CharIndices<'input>,
     lookahead: Option<(usize, char, usize)>,
+    /// Tokens that were read ahead of the one being returned
+    pending: VecDeque<(usize, Token<'input>, usize)>,
+    /// Where the line continues after the here-documents that were started on it
+    heredoc_end: Option<usize>,
+    /// Aliases are only substituted if this is set
+    aliases: Option<AliasLookup<'input>>,
+    /// Aliases whose values are being read from, innermost last
+    expanding: Vec<AliasExpansion<'input>>,
+    /// Whether the next word is the name of a command
+    command_position: bool,
+    /// Whether the next word follows an alias whose value ends in a blank, which makes it an
+    /// alias candidate even outside of command position
+    after_blank_alias: bool,
+    /// Whether the next word is the target of a redirection
+    after_redirect: bool,
+    /// Whether the lexer is inside of a `[[ ... ]]` conditional command
+    conditional: bool,
+    /// Whether the next word is the regular expression on the right of `=~`
+    regex_next: bool,
+    /// Parts of the `case` commands the lexer is inside of, innermost last
+    cases: Vec<CasePart>,
+}
+
+/// Part of a `case` command, which decides whether words in it can be the names of commands
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum CasePart {
+    /// The word being matched, before `in`
+    Subject,
+    /// The patterns of an item, up to the `)` that ends them
+    Patterns,
+    /// The commands of an item
+    Body,
+}
+
+/// Alias substituted for a word in command position
+#[derive(Clone)]
+struct AliasExpansion<'input> {
+    name: &'input str,
+    /// Lexer reading the value of the alias
+    lexer: Lexer<'input>,
+    /// Span of the word the alias replaced, which all of the tokens of the value are given
+    start: usize,
+    end: usize,
 }
 
 impl<'input> Lexer<'input> {
@@ -94,9 +226,30 @@ impl<'input> Lexer<'input> {
             input,
             chars,
             lookahead,
+            pending: VecDeque::new(),
+            heredoc_end: None,
+            aliases: None,
+            expanding: vec![],
+            command_position: true,
+            after_blank_alias: false,
+            after_redirect: false,
+            conditional: false,
+            regex_next: false,
+            cases: vec![],
         }
     }
 
+    /// Substitute aliases for the words in command position, looking their values up with
+    /// `aliases`
+    ///
+    /// The tokens of an alias value can contain more aliases, except for the ones already being
+    /// substituted. The word after an alias whose value ends in a blank is also substituted if
+    /// it is an alias.
+    pub fn with_aliases(mut self, aliases: AliasLookup<'input>) -> Self {
+        self.aliases = Some(aliases);
+        self
+    }
+
     pub fn input(&self) -> &'input str {
         self.input
     }
@@ -118,7 +271,26 @@ impl<'input> Lexer<'input> {
         start: usize,
         end: usize,
     ) -> Result<(usize, Token<'input>, usize), Error> {
-        let (word, end) = self.take_until(start, end, |ch| !is_word_continue(ch));
+        // the word is kept as it is written, quote removal happens when it is expanded
+        let word_end =
+            word_end(self.input, start).ok_or(Error::Unterminated(start, self.input.len()))?;
+        let mut end = end;
+        while end < word_end {
+            match self.advance() {
+                Some((_, _, e)) => end = e,
+                None => break,
+            }
+        }
+        let word = &self.input[start..end];
+
+        // a word made up of only digits that is immediately followed by a redirection operator is
+        // the file descriptor the redirection applies to
+        if word.chars().all(|ch| ch.is_ascii_digit()) {
+            if let Some((_, '<' | '>', _)) = self.lookahead {
+                return Ok((start, Token::IO_NUMBER(word), end));
+            }
+        }
+
         let token = match word {
             "if" => Token::IF,
             "then" => Token::THEN,
@@ -133,51 +305,208 @@ impl<'input> Lexer<'input> {
             "until" => Token::UNTIL,
             "for" => Token::FOR,
             "in" => Token::IN,
-            word => Token::WORD(word),
+            // braces are only reserved words when they stand alone, otherwise they are left in
+            // the word for brace expansion
+            "{" => Token::LBRACE,
+            "}" => Token::RBRACE,
+            "[[" => {
+                self.conditional = true;
+                Token::DLBRACKET
+            },
+            "]]" => {
+                self.conditional = false;
+                Token::DRBRACKET
+            },
+            word => {
+                self.regex_next = self.conditional && word == "=~";
+                Token::WORD(word)
+            },
         };
         Ok((start, token, end))
     }
 
-    // TODO escape characters
-    fn single_quote(
-        &mut self,
-        start: usize,
-        end: usize,
-    ) -> Result<(usize, Token<'input>, usize), Error> {
-        let (_, end) = self.take_until(start + 1, end, |ch| ch == '\'');
-        self.advance();
-        Ok((start, Token::WORD(&self.input[start + 1..end]), end))
+    /// Read the regular expression on the right of `=~`, in which parentheses and `|` are part of
+    /// the word instead of operators
+    fn regex(&mut self, start: usize) -> Result<(usize, Token<'input>, usize), Error> {
+        let mut depth = 0;
+        let mut i = start;
+        while let Some(ch) = self.input[i..].chars().next() {
+            match ch {
+                '(' => depth += 1,
+                ')' if depth == 0 => break,
+                ')' => depth -= 1,
+                ch if ch.is_whitespace() && depth == 0 => break,
+                _ => {},
+            }
+            i = skip(self.input, i, ch).ok_or(Error::Unterminated(start, self.input.len()))?;
+        }
+        while matches!(self.lookahead, Some((s, _, _)) if s < i) {
+            self.advance();
+        }
+        Ok((start, Token::WORD(&self.input[start..i]), i))
     }
 
-    fn double_quote(
-        &mut self,
-        start: usize,
-        end: usize,
-    ) -> Result<(usize, Token<'input>, usize), Error> {
-        let (_, end) = self.take_until(start + 1, end, |ch| ch == '"');
-        self.advance();
-        Ok((start, Token::WORD(&self.input[start + 1..end]), end))
-    }
+    /// Read the delimiter of a here-document along with its body, after `<<` or `<<-`
+    ///
+    /// The body starts on the line after the operator, or after the body of the previous
+    /// here-document on the same line. It is returned as a [Token::HEREDOC] right after the
+    /// delimiter and skipped over once the lexer reaches the end of the line.
+    fn here_document(&mut self, strip_tabs: bool) -> Result<(), Error> {
+        while let Some((_, ' ' | '\t', _)) = self.lookahead {
+            self.advance();
+        }
+        let start = match self.lookahead {
+            Some((start, ch, _)) if is_word_start(ch) || matches!(ch, '\'' | '"' | '`') => start,
+            // the missing delimiter is a syntax error left to the parser
+            _ => return Ok(()),
+        };
+        let end =
+            word_end(self.input, start).ok_or(Error::Unterminated(start, self.input.len()))?;
+        while matches!(self.lookahead, Some((s, _, _)) if s < end) {
+            self.advance();
+        }
+        let word = &self.input[start..end];
+        let delimiter = remove_quotes(word);
 
-    // utils for reading until condition is met
-    fn take_until<F>(
-        &mut self,
-        start: usize,
-        mut end: usize,
-        mut terminate: F,
-    ) -> (&'input str, usize)
-    where
-        F: FnMut(char) -> bool,
-    {
-        while let Some((_, ch, _)) = self.lookahead {
-            if terminate(ch) {
-                return (&self.input[start..end], end);
-            } else if let Some((_, _, e)) = self.advance() {
-                end = e;
+        let body_start = match self.heredoc_end {
+            Some(body_start) => body_start,
+            None => line_end(self.input, end).ok_or(Error::MissingDelimiter(start))? + 1,
+        };
+        let mut line_start = body_start;
+        let (body_end, after) = loop {
+            if line_start >= self.input.len() {
+                return Err(Error::MissingDelimiter(start));
+            }
+            let line_end = self.input[line_start..]
+                .find('\n')
+                .map_or(self.input.len(), |i| line_start + i);
+            let mut line = &self.input[line_start..line_end];
+            if strip_tabs {
+                line = line.trim_start_matches('\t');
             }
+            if line == delimiter {
+                break (line_start, (line_end + 1).min(self.input.len()));
+            }
+            line_start = line_end + 1;
+        };
+        self.heredoc_end = Some(after);
+
+        self.pending.push_back((start, Token::WORD(word), end));
+        self.pending.push_back((
+            body_start,
+            Token::HEREDOC(&self.input[body_start..body_end]),
+            body_end,
+        ));
+        Ok(())
+    }
+
+    /// Value of the alias to substitute for a word, if it is one
+    ///
+    /// Words with quotes or escapes in them are never aliases.
+    fn alias_value(&self, word: &str) -> Option<&'input str> {
+        let aliases = self.aliases?;
+        let eligible = (self.command_position || self.after_blank_alias)
+            && !word.contains(['\'', '"', '\\', '$', '`'])
+            && !self.before_paren()
+            && !self
+                .expanding
+                .iter()
+                .any(|expansion| expansion.name == word);
+        if !eligible {
+            return None;
+        }
+        aliases(word)
+    }
+
+    /// Whether the word just read is followed by `(`, which makes it the name of a function being
+    /// defined rather than a command
+    fn before_paren(&self) -> bool {
+        let lexer = self
+            .expanding
+            .last()
+            .map_or(self, |expansion| &expansion.lexer);
+        lexer.lookahead.is_some_and(|(start, _, _)| {
+            lexer.input[start..]
+                .trim_start_matches([' ', '\t'])
+                .starts_with('(')
+        })
+    }
+
+    fn set_case_part(&mut self, part: CasePart) {
+        if let Some(last) = self.cases.last_mut() {
+            *last = part;
         }
-        (&self.input[start..end], end)
     }
+
+    /// Keep track of whether the word after a token is in command position
+    fn update_position(&mut self, token: &Token) {
+        // words in the patterns of a case item are never in command position
+        let part = self.cases.last().copied();
+        match (token, part) {
+            (Token::CASE, _) => self.cases.push(CasePart::Subject),
+            (Token::IN, Some(CasePart::Subject)) | (Token::DSEMI, Some(CasePart::Body)) => {
+                self.set_case_part(CasePart::Patterns)
+            },
+            // patterns can be on their own line and start with `(`
+            (Token::NEWLINE | Token::LPAREN | Token::PIPE, Some(CasePart::Patterns)) => return,
+            (Token::RPAREN, Some(CasePart::Patterns)) => self.set_case_part(CasePart::Body),
+            (Token::ESAC, _) => {
+                self.cases.pop();
+            },
+            _ => {},
+        }
+
+        match token {
+            Token::WORD(word) => {
+                if self.after_redirect {
+                    self.after_redirect = false;
+                } else {
+                    // assignments are followed by the name of the command
+                    let assignment = word.split_once('=').is_some_and(|(name, _)| is_name(name));
+                    self.command_position &= assignment;
+                }
+                self.after_blank_alias = false;
+            },
+            // `!` is either reserved at the start of a pipeline or an argument
+            Token::IO_NUMBER(_) | Token::HEREDOC(_) | Token::BANG => {},
+            Token::LESS
+            | Token::GREAT
+            | Token::DGREAT
+            | Token::LESSAND
+            | Token::GREATAND
+            | Token::LESSGREAT
+            | Token::CLOBBER
+            | Token::DLESS
+            | Token::DLESSDASH
+            | Token::TLESS => self.after_redirect = true,
+            Token::NEWLINE
+            | Token::SEMI
+            | Token::AMP
+            | Token::PIPE
+            | Token::AND_IF
+            | Token::OR_IF
+            | Token::LPAREN
+            | Token::RPAREN
+            | Token::LBRACE
+            | Token::RBRACE
+            | Token::IF
+            | Token::THEN
+            | Token::ELSE
+            | Token::ELIF
+            | Token::DO
+            | Token::WHILE
+            | Token::UNTIL => {
+                self.command_position = true;
+                self.after_blank_alias = false;
+            },
+            _ => {
+                self.command_position = false;
+                self.after_blank_alias = false;
+            },
+        }
+    }
+
+    // utils for reading until condition is met
     fn take_until_inclusive<F>(
         &mut self,
         start: usize,
@@ -198,15 +527,28 @@ impl<'input> Lexer<'input> {
     }
 }
 
-impl<'input> Iterator for Lexer<'input> {
-    type Item = Spanned<Token<'input>, usize, Error>;
-
+impl<'input> Lexer<'input> {
     // TODO create proc macro to generate all this?
-    fn next(&mut self) -> Option<Self::Item> {
+    fn next_token(&mut self) -> Option<Spanned<Token<'input>, usize, Error>> {
+        if let Some(token) = self.pending.pop_front() {
+            return Some(Ok(token));
+        }
         while let Some((start, ch, end)) = self.advance() {
+            if self.regex_next && !ch.is_whitespace() {
+                self.regex_next = false;
+                return Some(self.regex(start));
+            }
             // TODO see if this could be generated with macro
             let token = match ch {
-                '\n' => Some(Ok((start, Token::NEWLINE, end))),
+                '\n' => {
+                    // the bodies of here-documents follow the line they were started on
+                    if let Some(after) = self.heredoc_end.take() {
+                        while matches!(self.lookahead, Some((s, _, _)) if s < after) {
+                            self.advance();
+                        }
+                    }
+                    Some(Ok((start, Token::NEWLINE, end)))
+                },
                 ';' => match self.lookahead {
                     Some((_, ';', new_end)) => {
                         self.advance();
@@ -228,14 +570,33 @@ impl<'input> Iterator for Lexer<'input> {
                     },
                     _ => Some(Ok((start, Token::PIPE, end))),
                 },
-                '`' => Some(Ok((start, Token::BACKTICK, end))),
-                '=' => Some(Ok((start, Token::EQUAL, end))),
-                '\\' => Some(Ok((start, Token::BACKSLASH, end))),
+                // a backslash before a newline continues the line
+                '\\' if matches!(self.lookahead, Some((_, '\n', _))) => {
+                    self.advance();
+                    continue;
+                },
+                // `<(` and `>(` start process substitutions, which are words
+                '<' | '>' if matches!(self.lookahead, Some((_, '(', _))) => {
+                    Some(self.keyword(start, end))
+                },
                 '<' => match self.lookahead {
-                    // TODO current doesn't support <<-
                     Some((_, '<', new_end)) => {
                         self.advance();
-                        Some(Ok((start, Token::DLESS, new_end)))
+                        let (token, new_end) = match self.lookahead {
+                            Some((_, '-', new_end)) => {
+                                self.advance();
+                                (Token::DLESSDASH, new_end)
+                            },
+                            Some((_, '<', new_end)) => {
+                                self.advance();
+                                return Some(Ok((start, Token::TLESS, new_end)));
+                            },
+                            _ => (Token::DLESS, new_end),
+                        };
+                        if let Err(e) = self.here_document(token == Token::DLESSDASH) {
+                            return Some(Err(e));
+                        }
+                        Some(Ok((start, token, new_end)))
                     },
                     Some((_, '&', new_end)) => {
                         self.advance();
@@ -265,11 +626,21 @@ impl<'input> Iterator for Lexer<'input> {
 
                 '(' => Some(Ok((start, Token::LPAREN, end))),
                 ')' => Some(Ok((start, Token::RPAREN, end))),
-                '{' => Some(Ok((start, Token::LBRACE, end))),
-                '}' => Some(Ok((start, Token::RBRACE, end))),
+                // `!` is only reserved when it stands alone, otherwise it is part of a word like
+                // `!=` or starts an extended pattern like `!(*.rs)`
+                '!' if matches!(self.lookahead, Some((_, ch, _)) if ch == '(' || is_word_continue(ch)) => {
+                    Some(self.keyword(start, end))
+                },
                 '!' => Some(Ok((start, Token::BANG, end))),
-                '\'' => Some(self.single_quote(start, end)),
-                '"' => Some(self.double_quote(start, end)),
+                // comments run until the end of the line
+                '#' => {
+                    while matches!(self.lookahead, Some((_, ch, _)) if ch != '\n') {
+                        self.advance();
+                    }
+                    continue;
+                },
+                // quotes, escapes and command substitutions are part of words
+                '\'' | '"' | '\\' | '`' => Some(self.keyword(start, end)),
                 ch if is_word_start(ch) => Some(self.keyword(start, end)),
                 ch if ch.is_whitespace() => continue,
                 ch => return Some(Err(Error::UnrecognizedChar(start, ch, end))),
@@ -280,6 +651,133 @@ impl<'input> Iterator for Lexer<'input> {
     }
 }
 
+impl<'input> Iterator for Lexer<'input> {
+    type Item = Spanned<Token<'input>, usize, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.aliases.is_none() {
+            return self.next_token();
+        }
+        loop {
+            let (start, token, end) = match self.expanding.last_mut() {
+                Some(expansion) => match expansion.lexer.next_token() {
+                    Some(Ok((_, token, _))) => (expansion.start, token, expansion.end),
+                    Some(Err(e)) => return Some(Err(e.at(expansion.start, expansion.end))),
+                    None => {
+                        let expansion = self.expanding.pop().unwrap();
+                        self.after_blank_alias = expansion.lexer.input.ends_with([' ', '\t']);
+                        continue;
+                    },
+                },
+                None => match self.next_token()? {
+                    Ok(token) => token,
+                    Err(e) => return Some(Err(e)),
+                },
+            };
+
+            if let Token::WORD(word) = token {
+                if let Some(value) = self.alias_value(word) {
+                    // the first word of the value can be an alias too
+                    self.expanding.push(AliasExpansion {
+                        name: word,
+                        lexer: Lexer::new(value),
+                        start,
+                        end,
+                    });
+                    continue;
+                }
+            }
+            self.update_position(&token);
+            return Some(Ok((start, token, end)));
+        }
+    }
+}
+
+/// Find where the word starting at `start` ends
+///
+/// Quoted and escaped characters, as well as everything inside of command substitutions and
+/// parameter expansions, are part of the word. Returns [None] if a quote or substitution is not
+/// terminated.
+fn word_end(input: &str, start: usize) -> Option<usize> {
+    let mut i = start;
+    while let Some(ch) = input[i..].chars().next() {
+        let before_paren = matches!(ch, '<' | '>') && input[i + 1..].starts_with('(');
+        if !is_word_continue(ch) && !matches!(ch, '\'' | '"' | '`') && !before_paren {
+            break;
+        }
+        i = skip(input, i, ch)?;
+    }
+    Some(i)
+}
+
+/// Position after the quote, escape, substitution or character at `i`
+fn skip(input: &str, i: usize, ch: char) -> Option<usize> {
+    let rest = &input[i + ch.len_utf8()..];
+    let end = match ch {
+        '\\' => i + 1 + rest.chars().next().map_or(0, char::len_utf8),
+        '\'' => i + 1 + rest.find('\'')? + 1,
+        '"' => scan_until(input, i + 1, '"')?,
+        '`' => scan_until(input, i + 1, '`')?,
+        '$' if rest.starts_with('(') => scan_until(input, i + 2, ')')?,
+        '$' if rest.starts_with('{') => scan_until(input, i + 2, '}')?,
+        // extended patterns like `@(a|b)` and process substitutions are part of words
+        '?' | '*' | '+' | '@' | '!' | '<' | '>' if rest.starts_with('(') => {
+            scan_until(input, i + 2, ')')?
+        },
+        ch => i + ch.len_utf8(),
+    };
+    Some(end)
+}
+
+/// Position after the character closing a quote or substitution, starting the search at `i`
+fn scan_until(input: &str, mut i: usize, close: char) -> Option<usize> {
+    while let Some(ch) = input[i..].chars().next() {
+        i = match ch {
+            ch if ch == close => return Some(i + ch.len_utf8()),
+            // single quotes and nested parentheses are only special outside of double quotes
+            '\'' | '(' if close == '"' || close == '`' => i + 1,
+            '(' => scan_until(input, i + 1, ')')?,
+            '"' | '`' if close == '`' => i + 1,
+            ch => skip(input, i, ch)?,
+        };
+    }
+    None
+}
+
+/// Position of the newline ending the line that `i` is on, not counting newlines that are quoted
+/// or inside of substitutions
+fn line_end(input: &str, mut i: usize) -> Option<usize> {
+    while let Some(ch) = input[i..].chars().next() {
+        if ch == '\n' {
+            return Some(i);
+        }
+        i = skip(input, i, ch)?;
+    }
+    None
+}
+
+/// Remove the quotes and backslashes from a word without expanding it, as is done for the
+/// delimiters of here-documents
+fn remove_quotes(word: &str) -> String {
+    let mut unquoted = String::new();
+    let mut quote = None;
+    let mut chars = word.chars().peekable();
+    while let Some(ch) = chars.next() {
+        match (quote, ch) {
+            (None, '\'' | '"') => quote = Some(ch),
+            (Some(q), ch) if q == ch => quote = None,
+            (None, '\\') => unquoted.extend(chars.next()),
+            // inside of double quotes, backslashes only escape the characters special there
+            (Some('"'), '\\') => match chars.next_if(|c| matches!(c, '$' | '`' | '"' | '\\')) {
+                Some(c) => unquoted.push(c),
+                None => unquoted.push('\\'),
+            },
+            (_, ch) => unquoted.push(ch),
+        }
+    }
+    unquoted
+}
+
 /// predicate that detects when a word starts (non whitespace, non control character)
 fn is_word_start(ch: char) -> bool {
     match ch {
@@ -291,14 +789,14 @@ fn is_word_start(ch: char) -> bool {
 /// predicate for when to keep reading word token
 fn is_word_continue(ch: char) -> bool {
     match ch {
-        ';' | ')' | '(' | '`' | '!' | '\'' | '"' | '>' | '<' | '&' | '|' | '{' | '}' => false,
+        ';' | ')' | '(' | '`' | '\'' | '"' | '>' | '<' | '&' | '|' => false,
         _ => !ch.is_whitespace(),
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{Lexer, Token};
+    use super::{Error, Lexer, Token};
 
     #[test]
     fn single_quote() {
@@ -309,6 +807,202 @@ mod tests {
         );
     }
 
+    #[test]
+    fn io_number() {
+        let mut lexer = Lexer::new("2>&1");
+        assert_eq!(lexer.next(), Some(Ok((0, Token::IO_NUMBER("2"), 1))));
+        assert_eq!(lexer.next(), Some(Ok((1, Token::GREATAND, 3))));
+        assert_eq!(lexer.next(), Some(Ok((3, Token::WORD("1"), 4))));
+
+        let mut lexer = Lexer::new("2 >");
+        assert_eq!(lexer.next(), Some(Ok((0, Token::WORD("2"), 1))));
+    }
+
+    #[test]
+    fn parameter_expansion() {
+        let mut lexer = Lexer::new("${VAR:-${X}y}z }");
+        assert_eq!(
+            lexer.next(),
+            Some(Ok((0, Token::WORD("${VAR:-${X}y}z"), 14)))
+        );
+        assert_eq!(lexer.next(), Some(Ok((15, Token::RBRACE, 16))));
+
+        let mut lexer = Lexer::new("$!x !");
+        assert_eq!(lexer.next(), Some(Ok((0, Token::WORD("$!x"), 3))));
+        assert_eq!(lexer.next(), Some(Ok((4, Token::BANG, 5))));
+    }
+
+    #[test]
+    fn braces() {
+        let mut lexer = Lexer::new("{ cp a{,.bak} {1..3}; }");
+        assert_eq!(lexer.next(), Some(Ok((0, Token::LBRACE, 1))));
+        assert_eq!(lexer.next(), Some(Ok((2, Token::WORD("cp"), 4))));
+        assert_eq!(lexer.next(), Some(Ok((5, Token::WORD("a{,.bak}"), 13))));
+        assert_eq!(lexer.next(), Some(Ok((14, Token::WORD("{1..3}"), 20))));
+        assert_eq!(lexer.next(), Some(Ok((20, Token::SEMI, 21))));
+        assert_eq!(lexer.next(), Some(Ok((22, Token::RBRACE, 23))));
+    }
+
+    #[test]
+    fn extended_patterns() {
+        let mut lexer = Lexer::new("ls !(*.o) src/@(a|b).rs ! x");
+        assert_eq!(lexer.next(), Some(Ok((0, Token::WORD("ls"), 2))));
+        assert_eq!(lexer.next(), Some(Ok((3, Token::WORD("!(*.o)"), 9))));
+        assert_eq!(
+            lexer.next(),
+            Some(Ok((10, Token::WORD("src/@(a|b).rs"), 23)))
+        );
+        assert_eq!(lexer.next(), Some(Ok((24, Token::BANG, 25))));
+    }
+
+    #[test]
+    fn process_substitution() {
+        let mut lexer = Lexer::new("diff <(sort a) <(sort b) >(x)> y");
+        assert_eq!(lexer.next(), Some(Ok((0, Token::WORD("diff"), 4))));
+        assert_eq!(lexer.next(), Some(Ok((5, Token::WORD("<(sort a)"), 14))));
+        assert_eq!(lexer.next(), Some(Ok((15, Token::WORD("<(sort b)"), 24))));
+        assert_eq!(lexer.next(), Some(Ok((25, Token::WORD(">(x)"), 29))));
+        assert_eq!(lexer.next(), Some(Ok((29, Token::GREAT, 30))));
+    }
+
+    #[test]
+    fn aliases() {
+        let lookup = |name: &str| match name {
+            "ll" => Some("ls -l"),
+            "ls" => Some("ls --color"),
+            "sudo" => Some("sudo "),
+            "x" => Some("y"),
+            _ => None,
+        };
+        let words = |input| {
+            Lexer::new(input)
+                .with_aliases(&lookup)
+                .map(|token| match token.unwrap() {
+                    (_, Token::WORD(word), _) => word.to_string(),
+                    (_, token, _) => token.to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        assert_eq!(words("ll x"), "ls --color -l x");
+        assert_eq!(
+            words("x; if x | x && A=1 x; then x; fi"),
+            "y ; if y | y && A=1 y ; then y ; fi"
+        );
+        assert_eq!(words("sudo ll"), "sudo ls --color -l");
+        assert_eq!(words("'ll' \\x x > x"), "'ll' \\x x > x");
+        assert_eq!(words("for x in x; do x; done"), "for x in x ; do y ; done");
+        assert_eq!(
+            words("case x in x) x;; (x | y) x\nesac; x"),
+            "case x in x ) y ;; ( x | y ) y newline esac ; y"
+        );
+        assert_eq!(
+            words("case x in\nx)\nx;;\nx) esac"),
+            "case x in newline x ) newline y ;; newline x ) esac"
+        );
+        assert_eq!(words("x() { x; }; x ()"), "x ( ) { y ; } ; x ( )");
+
+        let mut lexer = Lexer::new("a; ll").with_aliases(&lookup);
+        lexer.nth(1);
+        assert_eq!(lexer.next(), Some(Ok((3, Token::WORD("ls"), 5))));
+        assert_eq!(lexer.next(), Some(Ok((3, Token::WORD("--color"), 5))));
+        assert_eq!(lexer.next(), Some(Ok((3, Token::WORD("-l"), 5))));
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn conditional_command() {
+        let mut lexer = Lexer::new("[[ a != b && ! $x =~ (a|b)c ]] ]]");
+        assert_eq!(lexer.next(), Some(Ok((0, Token::DLBRACKET, 2))));
+        assert_eq!(lexer.next(), Some(Ok((3, Token::WORD("a"), 4))));
+        assert_eq!(lexer.next(), Some(Ok((5, Token::WORD("!="), 7))));
+        assert_eq!(lexer.next(), Some(Ok((8, Token::WORD("b"), 9))));
+        assert_eq!(lexer.next(), Some(Ok((10, Token::AND_IF, 12))));
+        assert_eq!(lexer.next(), Some(Ok((13, Token::BANG, 14))));
+        assert_eq!(lexer.next(), Some(Ok((15, Token::WORD("$x"), 17))));
+        assert_eq!(lexer.next(), Some(Ok((18, Token::WORD("=~"), 20))));
+        assert_eq!(lexer.next(), Some(Ok((21, Token::WORD("(a|b)c"), 27))));
+        assert_eq!(lexer.next(), Some(Ok((28, Token::DRBRACKET, 30))));
+
+        // parentheses are only part of the regular expression inside of `[[ ... ]]`
+        let mut lexer = Lexer::new("echo =~ (a)");
+        assert_eq!(lexer.nth(2), Some(Ok((8, Token::LPAREN, 9))));
+    }
+
+    #[test]
+    fn command_substitution() {
+        let mut lexer = Lexer::new("a$(b (c) `d`)e `f \\` g` ;");
+        assert_eq!(
+            lexer.next(),
+            Some(Ok((0, Token::WORD("a$(b (c) `d`)e"), 14)))
+        );
+        assert_eq!(lexer.next(), Some(Ok((15, Token::WORD("`f \\` g`"), 23))));
+        assert_eq!(lexer.next(), Some(Ok((24, Token::SEMI, 25))));
+    }
+
+    #[test]
+    fn quoted_words() {
+        let mut lexer = Lexer::new(r#"a"b; c"'d e'\ f "$(echo ")")" 'x"#);
+        assert_eq!(
+            lexer.next(),
+            Some(Ok((0, Token::WORD(r#"a"b; c"'d e'\ f"#), 15)))
+        );
+        assert_eq!(
+            lexer.next(),
+            Some(Ok((16, Token::WORD(r#""$(echo ")")""#), 29)))
+        );
+        assert_eq!(lexer.next(), Some(Err(Error::Unterminated(30, 32))));
+
+        let mut lexer = Lexer::new("\"if\" \\\n fi");
+        assert_eq!(lexer.next(), Some(Ok((0, Token::WORD("\"if\""), 4))));
+        assert_eq!(lexer.next(), Some(Ok((8, Token::FI, 10))));
+    }
+
+    #[test]
+    fn comments() {
+        let mut lexer = Lexer::new("#!/bin/sh\necho a#b # c 'd\n");
+        assert_eq!(lexer.next(), Some(Ok((9, Token::NEWLINE, 10))));
+        assert_eq!(lexer.next(), Some(Ok((10, Token::WORD("echo"), 14))));
+        assert_eq!(lexer.next(), Some(Ok((15, Token::WORD("a#b"), 18))));
+        assert_eq!(lexer.next(), Some(Ok((25, Token::NEWLINE, 26))));
+        assert_eq!(lexer.next(), None);
+    }
+
+    #[test]
+    fn here_documents() {
+        let input = "cat <<A <<-'B'; echo\nhello\nA\n\tworld\n\tB\nls";
+        let tokens = Lexer::new(input)
+            .map(|token| token.map(|(_, token, _)| token))
+            .collect::<Result<Vec<_>, _>>();
+        assert_eq!(
+            tokens,
+            Ok(vec![
+                Token::WORD("cat"),
+                Token::DLESS,
+                Token::WORD("A"),
+                Token::HEREDOC("hello\n"),
+                Token::DLESSDASH,
+                Token::WORD("'B'"),
+                Token::HEREDOC("\tworld\n"),
+                Token::SEMI,
+                Token::WORD("echo"),
+                Token::NEWLINE,
+                Token::WORD("ls"),
+            ])
+        );
+
+        let mut lexer = Lexer::new("cat <<EOF\nhello\nEOF");
+        assert_eq!(lexer.nth(3), Some(Ok((10, Token::HEREDOC("hello\n"), 16))));
+        assert_eq!(lexer.next(), Some(Ok((9, Token::NEWLINE, 10))));
+        assert_eq!(lexer.next(), None);
+
+        let mut lexer = Lexer::new("cat <<EOF\nhello\n");
+        assert_eq!(lexer.nth(1), Some(Err(Error::MissingDelimiter(6))));
+
+        let mut lexer = Lexer::new("cat <<< hi");
+        assert_eq!(lexer.nth(1), Some(Ok((4, Token::TLESS, 7))));
+    }
+
     #[test]
     fn keywords() {
         let mut lexer = Lexer::new("case");
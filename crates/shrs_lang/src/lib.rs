@@ -11,13 +11,36 @@ mod parser;
 pub use parser::{Parser, ParserError};
 
 mod lexer;
-pub use lexer::{Lexer, Token, RESERVED_WORDS};
+pub use lexer::{Error as LexerError, Lexer, Token, RESERVED_WORDS};
 
 pub mod ast;
 
 mod eval;
-pub use eval::eval;
+pub use eval::{eval, eval_command, exit_code};
 
+mod expand;
+pub use expand::is_name;
+
+mod brace;
+
+mod glob;
+
+mod word;
+pub use word::{quote_word, split_words};
+
+mod arith;
+
+mod cond;
+pub use cond::eval_test;
+
+mod context;
+pub use context::ShellContext;
+
+mod options;
+pub use options::ShellOptions;
+
+mod trap;
+pub use trap::{catch_signal, reset_signal, TrapCondition};
 
 mod error;
 pub use error::PosixError;
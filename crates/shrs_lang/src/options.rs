@@ -0,0 +1,165 @@
+//! Options that change how commands are evaluated, as set by the `set` builtin
+
+/// Shell options, each of them can be set with `set -o name` and unset with `set +o name`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ShellOptions {
+    /// Exit as soon as a command fails, `-e`
+    pub errexit: bool,
+    /// Treat expanding a parameter that is not set as an error, `-u`
+    pub nounset: bool,
+    /// Print each command to stderr before running it, `-x`
+    pub xtrace: bool,
+    /// Disable pathname expansion, `-f`
+    pub noglob: bool,
+    /// Remove patterns that match no files instead of leaving them as they are
+    pub nullglob: bool,
+    /// Treat patterns that match no files as an error
+    pub failglob: bool,
+    /// Let patterns match file names starting with a dot without matching the dot explicitly
+    pub dotglob: bool,
+    /// Let `**` match any number of directories in pathname expansion
+    pub globstar: bool,
+    /// Recognize the `?(...)`, `*(...)`, `+(...)`, `@(...)` and `!(...)` extended patterns
+    pub extglob: bool,
+    /// Keep `>` from overwriting existing files, `-C`
+    pub noclobber: bool,
+    /// Exit status of a pipeline is the one of the last command in it that failed
+    pub pipefail: bool,
+    /// Use vi style line editing, otherwise emacs style is used
+    pub vi: bool,
+    /// Substitute aliases for the words in command position, which is off when running scripts
+    pub expand_aliases: bool,
+}
+
+impl Default for ShellOptions {
+    fn default() -> Self {
+        Self {
+            errexit: false,
+            nounset: false,
+            xtrace: false,
+            noglob: false,
+            nullglob: false,
+            failglob: false,
+            dotglob: false,
+            globstar: false,
+            extglob: false,
+            noclobber: false,
+            pipefail: false,
+            vi: true,
+            expand_aliases: true,
+        }
+    }
+}
+
+/// Names of all the options, along with the flag each of them can be set with
+const OPTIONS: [(&str, Option<char>); 14] = [
+    ("dotglob", None),
+    ("emacs", None),
+    ("errexit", Some('e')),
+    ("expand_aliases", None),
+    ("extglob", None),
+    ("failglob", None),
+    ("globstar", None),
+    ("noclobber", Some('C')),
+    ("noglob", Some('f')),
+    ("nounset", Some('u')),
+    ("nullglob", None),
+    ("pipefail", None),
+    ("vi", None),
+    ("xtrace", Some('x')),
+];
+
+impl ShellOptions {
+    /// Names of all the options, in alphabetical order
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        OPTIONS.iter().map(|(name, _)| *name)
+    }
+
+    /// Name of the option that a single letter flag like `-e` sets
+    pub fn flag_name(flag: char) -> Option<&'static str> {
+        OPTIONS
+            .iter()
+            .find(|(_, f)| *f == Some(flag))
+            .map(|(name, _)| *name)
+    }
+
+    /// Check if an option is enabled, returns `None` if there is no option with the given name
+    pub fn get(&self, name: &str) -> Option<bool> {
+        match name {
+            "dotglob" => Some(self.dotglob),
+            "emacs" => Some(!self.vi),
+            "errexit" => Some(self.errexit),
+            "expand_aliases" => Some(self.expand_aliases),
+            "extglob" => Some(self.extglob),
+            "failglob" => Some(self.failglob),
+            "globstar" => Some(self.globstar),
+            "noclobber" => Some(self.noclobber),
+            "noglob" => Some(self.noglob),
+            "nounset" => Some(self.nounset),
+            "nullglob" => Some(self.nullglob),
+            "pipefail" => Some(self.pipefail),
+            "vi" => Some(self.vi),
+            "xtrace" => Some(self.xtrace),
+            _ => None,
+        }
+    }
+
+    /// Enable or disable an option, returns false if there is no option with the given name
+    ///
+    /// The `vi` and `emacs` options are mutually exclusive, changing one changes the other.
+    pub fn set(&mut self, name: &str, value: bool) -> bool {
+        let option = match name {
+            "emacs" => {
+                self.vi = !value;
+                return true;
+            },
+            "dotglob" => &mut self.dotglob,
+            "errexit" => &mut self.errexit,
+            "expand_aliases" => &mut self.expand_aliases,
+            "extglob" => &mut self.extglob,
+            "failglob" => &mut self.failglob,
+            "globstar" => &mut self.globstar,
+            "noclobber" => &mut self.noclobber,
+            "noglob" => &mut self.noglob,
+            "nounset" => &mut self.nounset,
+            "nullglob" => &mut self.nullglob,
+            "pipefail" => &mut self.pipefail,
+            "vi" => &mut self.vi,
+            "xtrace" => &mut self.xtrace,
+            _ => return false,
+        };
+        *option = value;
+        true
+    }
+
+    /// Flags of the options that are enabled, as expanded by `$-`
+    pub fn flags(&self) -> String {
+        OPTIONS
+            .iter()
+            .filter_map(|(name, flag)| flag.filter(|_| self.get(name) == Some(true)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ShellOptions;
+
+    #[test]
+    fn options() {
+        let mut options = ShellOptions::default();
+        assert_eq!(options.flags(), "");
+        assert!(options.set(ShellOptions::flag_name('e').unwrap(), true));
+        assert!(options.set("xtrace", true));
+        assert!(options.set("pipefail", true));
+        assert!(!options.set("nosuchoption", true));
+        assert_eq!(options.flags(), "ex");
+        assert_eq!(options.get("errexit"), Some(true));
+        assert_eq!(options.get("nosuchoption"), None);
+
+        assert_eq!(options.get("vi"), Some(true));
+        options.set("emacs", true);
+        assert_eq!(options.get("vi"), Some(false));
+        assert_eq!(options.get("emacs"), Some(true));
+    }
+}
@@ -1,14 +1,151 @@
 //! Generated parser
 
+use std::ops::Range;
+
+use lalrpop_util::ParseError;
 use thiserror::Error;
 
-use crate::{ast, grammar, lexer::Lexer};
+use crate::{
+    ast, grammar,
+    lexer::{self, Lexer, Token},
+};
 
-// TODO better errors for unsuccessful parses
-#[derive(Error, Debug)]
+/// Error encountered while parsing a command
+///
+/// Locations are byte offsets into the parsed input
+#[derive(Error, Debug, PartialEq, Eq)]
 pub enum ParserError {
-    #[error("unsuccessful parse")]
-    UnsuccessfulParse,
+    /// Token that does not fit the grammar at its position
+    #[error(
+        "syntax error near unexpected token `{token}`{}",
+        expected_list(expected)
+    )]
+    UnexpectedToken {
+        token: String,
+        span: Range<usize>,
+        expected: Vec<String>,
+    },
+    /// Input ended before the command was complete
+    #[error("syntax error: unexpected end of input{}", expected_list(expected))]
+    UnexpectedEof {
+        location: usize,
+        expected: Vec<String>,
+    },
+    /// Token found after what should have been the end of the input
+    #[error("syntax error near unexpected token `{token}`")]
+    ExtraToken { token: String, span: Range<usize> },
+    /// Token the parser could not recognize
+    #[error("syntax error: invalid token")]
+    InvalidToken { location: usize },
+    /// Input that could not be split into tokens
+    #[error("syntax error: {0}")]
+    Lexer(#[from] lexer::Error),
+}
+
+impl ParserError {
+    /// Byte range of the input the error refers to, which is empty for errors at the end of the
+    /// input
+    pub fn span(&self) -> Range<usize> {
+        match self {
+            ParserError::UnexpectedToken { span, .. } | ParserError::ExtraToken { span, .. } => {
+                span.clone()
+            },
+            ParserError::UnexpectedEof { location, .. }
+            | ParserError::InvalidToken { location } => *location..*location,
+            ParserError::Lexer(e) => e.span(),
+        }
+    }
+
+    /// Whether more input could turn this into a valid command, such as an open quote or an `if`
+    /// without its `fi`
+    pub fn is_incomplete(&self) -> bool {
+        matches!(
+            self,
+            ParserError::UnexpectedEof { .. }
+                | ParserError::Lexer(
+                    lexer::Error::Unterminated(..) | lexer::Error::MissingDelimiter(..)
+                )
+        )
+    }
+
+    /// Render the error along with the line of the input it occurred on and a caret under the
+    /// offending region
+    pub fn diagnostic(&self, input: &str) -> String {
+        let span = self.span();
+        let start = span.start.min(input.len());
+        let line_start = input[..start].rfind('\n').map_or(0, |i| i + 1);
+        let line_end = input[start..].find('\n').map_or(input.len(), |i| start + i);
+        let line = &input[line_start..line_end];
+        let line_number = input[..line_start].matches('\n').count() + 1;
+
+        // keep tabs in the padding so the caret lines up with the source line
+        let padding: String = input[line_start..start]
+            .chars()
+            .map(|ch| if ch == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = input[start..span.end.clamp(start, line_end)]
+            .chars()
+            .count()
+            .max(1);
+
+        let gutter = " ".repeat(line_number.to_string().len());
+        format!(
+            "{self}\n{gutter} |\n{line_number} | {line}\n{gutter} | {padding}{}",
+            "^".repeat(width)
+        )
+    }
+}
+
+impl<'input> From<ParseError<usize, Token<'input>, lexer::Error>> for ParserError {
+    fn from(e: ParseError<usize, Token<'input>, lexer::Error>) -> Self {
+        match e {
+            ParseError::InvalidToken { location } => ParserError::InvalidToken { location },
+            ParseError::UnrecognizedEOF { location, expected } => ParserError::UnexpectedEof {
+                location,
+                expected: expected.iter().map(|t| terminal_name(t)).collect(),
+            },
+            ParseError::UnrecognizedToken {
+                token: (start, token, end),
+                expected,
+            } => ParserError::UnexpectedToken {
+                token: token.to_string(),
+                span: start..end,
+                expected: expected.iter().map(|t| terminal_name(t)).collect(),
+            },
+            ParseError::ExtraToken {
+                token: (start, token, end),
+            } => ParserError::ExtraToken {
+                token: token.to_string(),
+                span: start..end,
+            },
+            ParseError::User { error } => ParserError::Lexer(error),
+        }
+    }
+}
+
+/// Most expected tokens listed in an error message
+const MAX_EXPECTED: usize = 6;
+
+/// Readable name for a terminal of the grammar, which lalrpop reports as it is written in the
+/// grammar, so `";"` or `WORD`
+fn terminal_name(terminal: &str) -> String {
+    match terminal.trim_matches('"') {
+        "\\n" => "newline".to_string(),
+        name if name.chars().all(|ch| ch.is_ascii_uppercase() || ch == '_') => {
+            name.to_lowercase().replace('_', " ")
+        },
+        name => format!("`{}`", name.replace("\\\"", "\"")),
+    }
+}
+
+fn expected_list(expected: &[String]) -> String {
+    match expected {
+        [] => String::new(),
+        // long lists name most of the grammar and don't help find the mistake
+        terminals if terminals.len() > MAX_EXPECTED => String::new(),
+        [terminal] => format!(", expected {terminal}"),
+        terminals => format!(", expected one of {}", terminals.join(", ")),
+    }
 }
 
 #[derive(Default)]
@@ -18,26 +155,124 @@ impl Parser {
     pub fn parse(&self, lexer: Lexer) -> Result<ast::Command, ParserError> {
         grammar::ProgramParser::new()
             .parse(lexer.input(), lexer)
-            .map_err(|_e| ParserError::UnsuccessfulParse)
+            .map_err(ParserError::from)
     }
 }
 
-/*
 #[cfg(test)]
 mod tests {
+    use super::{Parser, ParserError};
+    use crate::{
+        ast::{CommandKind, CondExprKind},
+        Lexer, LexerError,
+    };
+
+    fn parse(input: &str) -> Result<crate::ast::Command, ParserError> {
+        Parser::default().parse(Lexer::new(input))
+    }
+
+    #[test]
+    fn parse_ok() {
+        assert!(parse("ls home | grep downloads").is_ok());
+        assert!(parse("ls home || grep downloads").is_ok());
+    }
+
+    #[test]
+    fn spans() {
+        let input = "A=1 cat <src | wc\nif true; then ls; fi";
+        let cmd = parse(input).unwrap();
+        assert_eq!(cmd.span.text(input), Some(input));
+
+        let CommandKind::SeqList(pipeline, Some(if_cmd)) = cmd.kind else {
+            panic!("expected a sequential list");
+        };
+        assert_eq!(pipeline.span.text(input), Some("A=1 cat <src | wc"));
+        let CommandKind::Pipeline(cat, _) = pipeline.kind else {
+            panic!("expected a pipeline");
+        };
+        let CommandKind::Simple {
+            redirects, args, ..
+        } = cat.kind
+        else {
+            panic!("expected a simple command");
+        };
+        assert_eq!(cat.span.text(input), Some("A=1 cat <src"));
+        assert_eq!(redirects[0].span.text(input), Some("<src"));
+        // assignments before the name of the command are words too, until they are evaluated
+        assert_eq!(args[0].span.text(input), Some("A=1"));
+        assert_eq!(args[1].span.text(input), Some("cat"));
 
-    use super::grammar;
+        let CommandKind::If { conds, .. } = if_cmd.kind else {
+            panic!("expected an if statement");
+        };
+        assert_eq!(if_cmd.span.text(input), Some("if true; then ls; fi"));
+        assert_eq!(conds[0].span.text(input), Some("if true; then ls;"));
+        assert_eq!(conds[0].body.span.text(input), Some("ls;"));
+
+        // spans outside of the input have no text
+        assert_eq!(cmd.span.text("ls"), None);
+    }
+
+    #[test]
+    fn conditional_command() {
+        let input = "[[ ! -f $x && a < b || $y =~ ^(a|b)$ ]]";
+        let cmd = parse(input).unwrap();
+        let CommandKind::Conditional(expr) = cmd.kind else {
+            panic!("expected a conditional command");
+        };
+        let CondExprKind::Or(and, regex) = expr.kind else {
+            panic!("expected an or expression");
+        };
+        assert!(
+            matches!(and.kind, CondExprKind::And(ref not, _) if matches!(not.kind, CondExprKind::Not(_)))
+        );
+        assert!(
+            matches!(regex.kind, CondExprKind::Binary(ref a, ref op, ref b) if a == "$y" && op == "=~" && b == "^(a|b)$")
+        );
+        assert_eq!(
+            expr.span.text(input),
+            Some("! -f $x && a < b || $y =~ ^(a|b)$")
+        );
+        assert_eq!(and.span.text(input), Some("! -f $x && a < b"));
+        assert_eq!(regex.span.text(input), Some("$y =~ ^(a|b)$"));
+
+        assert!(parse("test ! -e x").is_ok());
+        assert!(parse("[[ a b c d ]]").is_err());
+    }
+
+    #[test]
+    fn unexpected_token() {
+        let e = parse("if true; fi").unwrap_err();
+        assert_eq!(
+            e,
+            ParserError::UnexpectedToken {
+                token: "fi".into(),
+                span: 9..11,
+                expected: vec!["`then`".into()],
+            }
+        );
+        assert_eq!(
+            e.diagnostic("if true; fi"),
+            "syntax error near unexpected token `fi`, expected `then`\n  |\n1 | if true; fi\n  |          ^^"
+        );
+        assert!(!e.is_incomplete());
+    }
 
     #[test]
-    fn parse() {
-        let res = grammar::ProgramParser::new().parse("ls home | grep downloads");
-        println!("{:?}", res);
+    fn unexpected_eof() {
+        let e = parse("case x in").unwrap_err();
+        assert_eq!(e.span(), 9..9);
+        assert!(e.is_incomplete());
     }
 
     #[test]
-    fn and_or() {
-        let res = grammar::ProgramParser::new().parse("ls home || grep downloads");
-        println!("{:?}", res);
+    fn lexer_errors() {
+        let input = "echo a\n\techo \u{1}b";
+        let e = parse(input).unwrap_err();
+        assert_eq!(
+            e,
+            ParserError::Lexer(LexerError::UnrecognizedChar(13, '\u{1}', 14))
+        );
+        assert_eq!(e.diagnostic(input).lines().nth(3), Some("  | \t     ^"));
     }
 }
-*/
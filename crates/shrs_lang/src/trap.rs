@@ -0,0 +1,173 @@
+//! Conditions that the `trap` builtin can set commands to run on
+//!
+//! Signals with a trap are caught by recording that they were received, the evaluator then runs
+//! their traps in between commands.
+
+use std::{
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use nix::{
+    libc::c_int,
+    sys::signal::{sigaction, SaFlags, SigAction, SigHandler, SigSet, Signal},
+};
+
+/// Signals that traps can be set for
+const TRAP_SIGNALS: [Signal; 7] = [
+    Signal::SIGHUP,
+    Signal::SIGINT,
+    Signal::SIGTERM,
+    Signal::SIGUSR1,
+    Signal::SIGUSR2,
+    Signal::SIGWINCH,
+    Signal::SIGCHLD,
+];
+
+/// Condition that a trap runs its command on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TrapCondition {
+    /// The shell exits
+    Exit,
+    /// A command fails, in the same places that `set -e` would exit the shell
+    Error,
+    /// The shell receives a signal
+    Signal(Signal),
+}
+
+impl TrapCondition {
+    /// All the conditions that traps can be set for
+    pub fn all() -> impl Iterator<Item = TrapCondition> {
+        [TrapCondition::Exit, TrapCondition::Error]
+            .into_iter()
+            .chain(TRAP_SIGNALS.into_iter().map(TrapCondition::Signal))
+    }
+}
+
+impl fmt::Display for TrapCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TrapCondition::Exit => write!(f, "EXIT"),
+            TrapCondition::Error => write!(f, "ERR"),
+            TrapCondition::Signal(signal) => write!(f, "{}", &signal.as_str()[3..]),
+        }
+    }
+}
+
+impl FromStr for TrapCondition {
+    type Err = String;
+
+    /// Parse a condition by name or signal number, signal names may start with `SIG`
+    fn from_str(s: &str) -> Result<Self, String> {
+        let upper = s.to_ascii_uppercase();
+        let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+        match name {
+            "EXIT" | "0" => Ok(TrapCondition::Exit),
+            "ERR" => Ok(TrapCondition::Error),
+            _ => TRAP_SIGNALS
+                .into_iter()
+                .find(|signal| {
+                    &signal.as_str()[3..] == name || (*signal as i32).to_string() == name
+                })
+                .map(TrapCondition::Signal)
+                .ok_or_else(|| format!("{s}: invalid signal specification")),
+        }
+    }
+}
+
+#[allow(clippy::declare_interior_mutable_const)]
+const NOT_PENDING: AtomicBool = AtomicBool::new(false);
+
+/// Signals that were received but whose traps haven't run yet, indexed by signal number
+static PENDING: [AtomicBool; 32] = [NOT_PENDING; 32];
+
+/// Dispositions that signals had before they were caught
+static SAVED_ACTIONS: Mutex<Vec<(Signal, SigAction)>> = Mutex::new(Vec::new());
+
+extern "C" fn record_signal(signal: c_int) {
+    if let Some(pending) = PENDING.get(signal as usize) {
+        pending.store(true, Ordering::SeqCst);
+    }
+}
+
+/// Catch a signal so that its trap is run by the evaluator, or ignore it if the command of the
+/// trap is empty
+pub fn catch_signal(signal: Signal, ignore: bool) -> nix::Result<()> {
+    // ignoring SIGCHLD would keep the shell from waiting on its children, so it's caught instead
+    let handler = match ignore && signal != Signal::SIGCHLD {
+        true => SigHandler::SigIgn,
+        false => SigHandler::Handler(record_signal),
+    };
+    let action = SigAction::new(handler, SaFlags::SA_RESTART, SigSet::empty());
+    let old_action = unsafe { sigaction(signal, &action) }?;
+
+    let mut saved = SAVED_ACTIONS.lock().unwrap();
+    if !saved
+        .iter()
+        .any(|(saved_signal, _)| *saved_signal == signal)
+    {
+        saved.push((signal, old_action));
+    }
+    Ok(())
+}
+
+/// Restore the disposition a signal had before it was caught with [`catch_signal`]
+pub fn reset_signal(signal: Signal) -> nix::Result<()> {
+    let mut saved = SAVED_ACTIONS.lock().unwrap();
+    if let Some(i) = saved
+        .iter()
+        .position(|(saved_signal, _)| *saved_signal == signal)
+    {
+        let (_, action) = saved.remove(i);
+        unsafe { sigaction(signal, &action) }?;
+    }
+    PENDING[signal as usize].store(false, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Give a signal its default handling, forgetting the disposition it had before it was caught
+pub(crate) fn default_signal(signal: Signal) -> nix::Result<()> {
+    SAVED_ACTIONS
+        .lock()
+        .unwrap()
+        .retain(|(saved_signal, _)| *saved_signal != signal);
+    PENDING[signal as usize].store(false, Ordering::SeqCst);
+    let action = SigAction::new(SigHandler::SigDfl, SaFlags::empty(), SigSet::empty());
+    unsafe { sigaction(signal, &action) }?;
+    Ok(())
+}
+
+/// Signals that were received since the last time this was called
+pub(crate) fn take_pending_signals() -> Vec<Signal> {
+    TRAP_SIGNALS
+        .into_iter()
+        .filter(|signal| PENDING[*signal as usize].swap(false, Ordering::SeqCst))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use nix::sys::signal::Signal;
+
+    use super::TrapCondition;
+
+    #[test]
+    fn condition_names() {
+        for name in ["EXIT", "0", "exit"] {
+            assert_eq!(name.parse(), Ok(TrapCondition::Exit));
+        }
+        for name in ["INT", "SIGINT", "2", "int"] {
+            assert_eq!(name.parse(), Ok(TrapCondition::Signal(Signal::SIGINT)));
+        }
+        assert!("KILL".parse::<TrapCondition>().is_err());
+        assert!("nope".parse::<TrapCondition>().is_err());
+
+        for condition in TrapCondition::all() {
+            assert_eq!(condition.to_string().parse(), Ok(condition));
+        }
+    }
+}
@@ -0,0 +1,523 @@
+//! Words of a command, split into the parts that are expanded differently
+//!
+//! The lexer keeps words as they are written, with quotes and backslashes. Before a word is
+//! expanded it is parsed into [WordPart]s that remember which characters were quoted, so that
+//! quote removal can happen after the expansions.
+
+use std::iter::Peekable;
+
+use crate::PosixError;
+
+/// Part of a word as it is written
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum WordPart {
+    /// Unquoted characters, or characters inside double quotes when part of [WordPart::DoubleQuoted]
+    Literal(String),
+    /// Characters quoted with single quotes or a backslash, which are taken literally
+    Quoted(String),
+    /// Parts of the word inside of double quotes
+    DoubleQuoted(Vec<WordPart>),
+    /// `~`, `~user`, `~+` or `~-` at the start of a word, holding what follows the tilde
+    Tilde(String),
+    /// Parameter like `$VAR`, `$1` or `$@`, holding its name
+    Param(String),
+    /// Contents of a `${...}` parameter expansion
+    BracedParam(String),
+    /// Command of a `$(...)` or backquoted command substitution
+    CommandSubst(String),
+    /// Expression of a `$((...))` arithmetic expansion
+    Arith(String),
+    /// Command of a `<(...)` or `>(...)` process substitution, along with whether it is the
+    /// `>(...)` form whose command reads what is written to the substituted path
+    ProcessSubst(String, bool),
+}
+
+/// Parse a word into its parts
+pub(crate) fn parse_word(word: &str) -> Result<Vec<WordPart>, PosixError> {
+    let mut parts = vec![];
+
+    // the characters up to the first slash are a tilde prefix if none of them are quoted
+    let mut rest = word;
+    if let Some(prefix) = word.strip_prefix('~') {
+        let user = &prefix[..prefix.find('/').unwrap_or(prefix.len())];
+        if user
+            .chars()
+            .all(|c| c.is_alphanumeric() || "._-+".contains(c))
+        {
+            parts.push(WordPart::Tilde(user.to_string()));
+            rest = &prefix[user.len()..];
+        }
+    }
+
+    let mut chars = rest.chars().peekable();
+    let mut literal = String::new();
+    while let Some(c) = chars.next() {
+        let part = match c {
+            '\\' => match chars.next() {
+                // a backslash before a newline continues the line
+                Some('\n') => continue,
+                Some(c) => WordPart::Quoted(c.to_string()),
+                None => {
+                    literal.push('\\');
+                    continue;
+                },
+            },
+            '\'' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => quoted.push(c),
+                        None => return Err(unterminated_quote(word)),
+                    }
+                }
+                WordPart::Quoted(quoted)
+            },
+            '"' => WordPart::DoubleQuoted(parse_double_quoted(&mut chars, word, Some('"'))?),
+            '$' | '`' => match parse_expansion(c, &mut chars, word)? {
+                Some(part) => part,
+                None => {
+                    literal.push(c);
+                    continue;
+                },
+            },
+            '<' | '>' if chars.next_if_eq(&'(').is_some() => {
+                let cmd =
+                    read_until_closing(&mut chars, '(', ')').ok_or_else(|| unterminated(word))?;
+                WordPart::ProcessSubst(cmd, c == '>')
+            },
+            c => {
+                literal.push(c);
+                continue;
+            },
+        };
+        if !literal.is_empty() {
+            parts.push(WordPart::Literal(std::mem::take(&mut literal)));
+        }
+        parts.push(part);
+    }
+    if !literal.is_empty() {
+        parts.push(WordPart::Literal(literal));
+    }
+    Ok(parts)
+}
+
+/// Parse the value of a variable assignment, in which tilde prefixes can also follow an unquoted
+/// `:` or `=`, as in `PATH=~/bin:~/.local/bin`
+pub(crate) fn parse_assignment(value: &str) -> Result<Vec<WordPart>, PosixError> {
+    let mut parts = vec![];
+    let mut start = 0;
+    for (sep, c) in unquoted_chars(value) {
+        if c == ':' || c == '=' {
+            parts.extend(parse_word(&value[start..sep])?);
+            parts.push(WordPart::Literal(c.to_string()));
+            start = sep + 1;
+        }
+    }
+    parts.extend(parse_word(&value[start..])?);
+    Ok(parts)
+}
+
+/// Characters of a word that are neither quoted nor part of an expansion, along with their byte
+/// offsets
+pub(crate) fn unquoted_chars(word: &str) -> Vec<(usize, char)> {
+    let mut unquoted = vec![];
+    let mut chars = word.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let mut rest = chars.by_ref().map(|(_, c)| c);
+        match c {
+            '\\' => {
+                rest.next();
+            },
+            '\'' => {
+                rest.find(|c| *c == '\'');
+            },
+            '"' => {
+                while let Some(c) = rest.next() {
+                    match c {
+                        '\\' => {
+                            rest.next();
+                        },
+                        '"' => break,
+                        _ => {},
+                    }
+                }
+            },
+            '`' => {
+                read_backquoted(&mut rest);
+            },
+            '$' => match chars.next_if(|(_, c)| *c == '(' || *c == '{') {
+                Some((_, '(')) => {
+                    read_until_closing(&mut chars.by_ref().map(|(_, c)| c), '(', ')');
+                },
+                Some(_) => {
+                    read_until_closing(&mut chars.by_ref().map(|(_, c)| c), '{', '}');
+                },
+                None => unquoted.push((i, c)),
+            },
+            '<' | '>' if chars.next_if(|(_, c)| *c == '(').is_some() => {
+                read_until_closing(&mut chars.by_ref().map(|(_, c)| c), '(', ')');
+            },
+            c => unquoted.push((i, c)),
+        }
+    }
+    unquoted
+}
+
+/// Parse the body of a here-document, which is expanded like the inside of double quotes except
+/// that double quotes themselves are not special
+pub(crate) fn parse_here_document(body: &str) -> Result<Vec<WordPart>, PosixError> {
+    parse_double_quoted(&mut body.chars().peekable(), body, None)
+}
+
+/// Parse the inside of double quotes, after the opening quote, up to the closing quote `close`
+///
+/// Without a closing quote everything up to the end of the input is parsed.
+fn parse_double_quoted(
+    chars: &mut Peekable<impl Iterator<Item = char>>,
+    word: &str,
+    close: Option<char>,
+) -> Result<Vec<WordPart>, PosixError> {
+    let mut parts = vec![];
+    let mut literal = String::new();
+    let escapable = |c: &char| matches!(c, '$' | '`' | '\\' | '\n') || Some(*c) == close;
+    loop {
+        let c = match chars.next() {
+            Some(c) if Some(c) == close => break,
+            Some(c) => c,
+            None if close.is_none() => break,
+            None => return Err(unterminated_quote(word)),
+        };
+        let part = match c {
+            // backslashes only escape the characters that are special inside of double quotes
+            '\\' => match chars.next_if(escapable) {
+                Some('\n') => continue,
+                Some(c) => {
+                    literal.push(c);
+                    continue;
+                },
+                None => {
+                    literal.push('\\');
+                    continue;
+                },
+            },
+            '$' | '`' => match parse_expansion(c, chars, word)? {
+                Some(part) => part,
+                None => {
+                    literal.push(c);
+                    continue;
+                },
+            },
+            c => {
+                literal.push(c);
+                continue;
+            },
+        };
+        if !literal.is_empty() {
+            parts.push(WordPart::Literal(std::mem::take(&mut literal)));
+        }
+        parts.push(part);
+    }
+    if !literal.is_empty() {
+        parts.push(WordPart::Literal(literal));
+    }
+    Ok(parts)
+}
+
+/// Parse an expansion starting with `$` or a backquote
+///
+/// Returns [None] if a `$` doesn't start an expansion, in which case it is taken literally.
+fn parse_expansion(
+    c: char,
+    chars: &mut Peekable<impl Iterator<Item = char>>,
+    word: &str,
+) -> Result<Option<WordPart>, PosixError> {
+    let part = match c {
+        '`' => {
+            let cmd = read_backquoted(chars).ok_or_else(|| unterminated(word))?;
+            WordPart::CommandSubst(cmd)
+        },
+        _ if chars.next_if_eq(&'(').is_some() => {
+            let cmd = read_until_closing(chars, '(', ')').ok_or_else(|| unterminated(word))?;
+            match arithmetic_expression(&cmd) {
+                Some(expr) => WordPart::Arith(expr.to_string()),
+                None => WordPart::CommandSubst(cmd),
+            }
+        },
+        _ if chars.next_if_eq(&'{').is_some() => {
+            let expr = read_until_closing(chars, '{', '}')
+                .ok_or_else(|| PosixError::Expansion(format!("{word}: bad substitution")))?;
+            WordPart::BracedParam(expr)
+        },
+        // positional parameters after $9 need to be written in braces
+        _ => match chars.next_if(|c| c.is_ascii_digit() || "#@*?$!-".contains(*c)) {
+            Some(c) => WordPart::Param(c.to_string()),
+            None => {
+                let mut name = String::new();
+                while let Some(c) = chars.next_if(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                    name.push(c);
+                }
+                if name.is_empty() {
+                    return Ok(None);
+                }
+                WordPart::Param(name)
+            },
+        },
+    };
+    Ok(Some(part))
+}
+
+fn unterminated(word: &str) -> PosixError {
+    PosixError::Expansion(format!("{word}: unterminated command substitution"))
+}
+
+fn unterminated_quote(word: &str) -> PosixError {
+    PosixError::Expansion(format!("{word}: unterminated quote"))
+}
+
+/// Read up to the bracket closing one that was just read, returning everything in between
+///
+/// Brackets that are quoted or escaped are not counted. Returns [None] if the closing bracket is
+/// missing.
+fn read_until_closing(
+    chars: &mut impl Iterator<Item = char>,
+    open: char,
+    close: char,
+) -> Option<String> {
+    let mut contents = String::new();
+    let mut depth = 0;
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some('"'), '\\') | (None, '\\') => {
+                contents.push(c);
+                contents.push(chars.next()?);
+                continue;
+            },
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {},
+            (None, '\'' | '"') => quote = Some(c),
+            (None, c) if c == close && depth == 0 => return Some(contents),
+            (None, c) if c == close => depth -= 1,
+            (None, c) if c == open => depth += 1,
+            _ => {},
+        }
+        contents.push(c);
+    }
+    None
+}
+
+/// Read the command in a backquoted command substitution, after the opening backquote
+///
+/// A backslash followed by `$`, `` ` `` or `\` stands for that character, which allows
+/// backquoted command substitutions to be nested.
+fn read_backquoted(chars: &mut impl Iterator<Item = char>) -> Option<String> {
+    let mut contents = String::new();
+    while let Some(c) = chars.next() {
+        match c {
+            '`' => return Some(contents),
+            '\\' => match chars.next()? {
+                c @ ('$' | '`' | '\\') => contents.push(c),
+                c => {
+                    contents.push('\\');
+                    contents.push(c);
+                },
+            },
+            c => contents.push(c),
+        }
+    }
+    None
+}
+
+/// Expression of an arithmetic expansion, given what is in between the outer parentheses of
+/// `$(...)`
+///
+/// This is an arithmetic expansion if the contents are enclosed in another pair of parentheses,
+/// otherwise it is a command substitution starting with a subshell such as `$((a) | b)`.
+fn arithmetic_expression(contents: &str) -> Option<&str> {
+    let expr = contents.strip_prefix('(')?;
+    let mut chars = expr.chars();
+    read_until_closing(&mut chars, '(', ')')?;
+    chars.as_str().is_empty().then(|| &expr[..expr.len() - 1])
+}
+
+/// Quote a word so that the shell reads it back unchanged, words without any special characters
+/// are left as they are
+pub fn quote_word(word: &str) -> String {
+    let plain = !word.is_empty()
+        && word
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c));
+    match plain {
+        true => word.to_string(),
+        false => format!("'{}'", word.replace('\'', r"'\''")),
+    }
+}
+
+/// Split a line into words at unquoted whitespace and remove the quotes from them, without
+/// expanding anything
+///
+/// The last word is the one at the end of the line, which is empty if the line is empty or ends
+/// with whitespace. This makes it the word being typed when completing.
+pub fn split_words(line: &str) -> Vec<String> {
+    let mut words = vec![];
+    let mut word = String::new();
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (None | Some('"'), '\\') => match chars.next() {
+                Some('\n') => {},
+                Some(c) => word.push(c),
+                None => word.push('\\'),
+            },
+            (None, c) if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            },
+            (_, c) => word.push(c),
+        }
+    }
+    words.push(word);
+    words
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        arithmetic_expression, parse_assignment, parse_here_document, parse_word, quote_word,
+        read_backquoted, read_until_closing, split_words, unquoted_chars, WordPart,
+    };
+
+    #[test]
+    fn quoting() {
+        assert_eq!(
+            parse_word(r#"a"b $x"'$y'\ c"#).unwrap(),
+            vec![
+                WordPart::Literal(String::from("a")),
+                WordPart::DoubleQuoted(vec![
+                    WordPart::Literal(String::from("b ")),
+                    WordPart::Param(String::from("x")),
+                ]),
+                WordPart::Quoted(String::from("$y")),
+                WordPart::Quoted(String::from(" ")),
+                WordPart::Literal(String::from("c")),
+            ]
+        );
+        assert_eq!(
+            parse_word(r#""\$\a""#).unwrap(),
+            vec![WordPart::DoubleQuoted(vec![WordPart::Literal(
+                String::from(r"$\a")
+            )])]
+        );
+        assert_eq!(
+            parse_word("~/$((1+2))$").unwrap(),
+            vec![
+                WordPart::Tilde(String::new()),
+                WordPart::Literal(String::from("/")),
+                WordPart::Arith(String::from("1+2")),
+                WordPart::Literal(String::from("$")),
+            ]
+        );
+        assert_eq!(
+            parse_word("$?$$-").unwrap(),
+            vec![
+                WordPart::Param(String::from("?")),
+                WordPart::Param(String::from("$")),
+                WordPart::Literal(String::from("-")),
+            ]
+        );
+        assert_eq!(
+            parse_word("<(ls -a)>(wc)").unwrap(),
+            vec![
+                WordPart::ProcessSubst(String::from("ls -a"), false),
+                WordPart::ProcessSubst(String::from("wc"), true),
+            ]
+        );
+        assert!(parse_word("'a").is_err());
+        assert!(parse_word("\"a").is_err());
+    }
+
+    #[test]
+    fn assignment_tildes() {
+        assert_eq!(
+            parse_assignment("~/bin:~:'~'x=~root").unwrap(),
+            vec![
+                WordPart::Tilde(String::new()),
+                WordPart::Literal(String::from("/bin")),
+                WordPart::Literal(String::from(":")),
+                WordPart::Tilde(String::new()),
+                WordPart::Literal(String::from(":")),
+                WordPart::Quoted(String::from("~")),
+                WordPart::Literal(String::from("x")),
+                WordPart::Literal(String::from("=")),
+                WordPart::Tilde(String::from("root")),
+            ]
+        );
+        assert_eq!(
+            unquoted_chars(r#"a'b'\c"d"$(e)${f}`g`h"#),
+            vec![(0, 'a'), (20, 'h')]
+        );
+    }
+
+    #[test]
+    fn here_document_body() {
+        assert_eq!(
+            parse_here_document("\"$x\" \\$y 'z'\n").unwrap(),
+            vec![
+                WordPart::Literal(String::from("\"")),
+                WordPart::Param(String::from("x")),
+                WordPart::Literal(String::from("\" $y 'z'\n")),
+            ]
+        );
+    }
+
+    #[test]
+    fn substitution_bounds() {
+        let mut chars = "echo $(echo ')') x) rest".chars();
+        assert_eq!(
+            read_until_closing(&mut chars, '(', ')').as_deref(),
+            Some("echo $(echo ')') x")
+        );
+        assert_eq!(chars.as_str(), " rest");
+
+        assert_eq!(read_until_closing(&mut "echo (".chars(), '(', ')'), None);
+        assert_eq!(read_until_closing(&mut r"\)".chars(), '(', ')'), None);
+
+        let mut chars = r"echo \`date\` \$HOME` rest".chars();
+        assert_eq!(
+            read_backquoted(&mut chars).as_deref(),
+            Some("echo `date` $HOME")
+        );
+        assert_eq!(chars.as_str(), " rest");
+    }
+
+    #[test]
+    fn arithmetic_bounds() {
+        assert_eq!(arithmetic_expression("(1 + (2))"), Some("1 + (2)"));
+        assert_eq!(arithmetic_expression("(a) | (b)"), None);
+        assert_eq!(arithmetic_expression("echo"), None);
+    }
+
+    #[test]
+    fn line_words() {
+        assert_eq!(
+            split_words(r#"git commit -m "fix the bug" a\ b"#),
+            vec!["git", "commit", "-m", "fix the bug", "a b"]
+        );
+        assert_eq!(split_words("ls 'a b' "), vec!["ls", "a b", ""]);
+        assert_eq!(split_words(""), vec![""]);
+    }
+
+    #[test]
+    fn word_quoting() {
+        assert_eq!(quote_word("ls"), "ls");
+        assert_eq!(quote_word("a=/tmp/x.txt"), "a=/tmp/x.txt");
+        assert_eq!(quote_word("a b"), "'a b'");
+        assert_eq!(quote_word(""), "''");
+        assert_eq!(quote_word("it's"), r"'it'\''s'");
+    }
+}
@@ -28,7 +28,10 @@ impl BashLang {
 }
 
 impl Lang for BashLang {
-    fn eval(&self, _sh: &Shell, states: &States, cmd: String) -> shrs::anyhow::Result<CmdOutput> {
+    fn eval(&self, sh: &Shell, states: &States, cmd: String) -> shrs::anyhow::Result<CmdOutput> {
+        if let Some(output) = sh.builtins.run_line(sh, states, &cmd) {
+            return Ok(output);
+        }
         let Ok(rt) = states.try_get::<Runtime>() else {
             return Ok(CmdOutput::error());
         };
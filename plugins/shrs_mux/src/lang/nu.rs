@@ -11,7 +11,10 @@ impl NuLang {
 }
 
 impl Lang for NuLang {
-    fn eval(&self, _sh: &Shell, _ctx: &States, cmd: String) -> shrs::anyhow::Result<CmdOutput> {
+    fn eval(&self, sh: &Shell, states: &States, cmd: String) -> shrs::anyhow::Result<CmdOutput> {
+        if let Some(output) = sh.builtins.run_line(sh, states, &cmd) {
+            return Ok(output);
+        }
         let handle = Command::new("nu")
             .args(vec!["-c", &cmd])
             .stdout(Stdio::piped())
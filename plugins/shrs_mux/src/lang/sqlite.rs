@@ -91,7 +91,10 @@ impl SqliteLang {
 }
 
 impl Lang for SqliteLang {
-    fn eval(&self, _sh: &Shell, _states: &States, cmd: String) -> shrs::anyhow::Result<CmdOutput> {
+    fn eval(&self, sh: &Shell, states: &States, cmd: String) -> shrs::anyhow::Result<CmdOutput> {
+        if let Some(output) = sh.builtins.run_line(sh, states, &cmd) {
+            return Ok(output);
+        }
         let lang_ctx = self
             .lang_ctx
             .get_or_init(|| SqliteLangCtx::init(&self.runtime, &self.db_file));
@@ -102,7 +102,10 @@ impl SshLang {
 }
 
 impl Lang for SshLang {
-    fn eval(&self, _sh: &Shell, _states: &States, cmd: String) -> shrs::anyhow::Result<CmdOutput> {
+    fn eval(&self, sh: &Shell, states: &States, cmd: String) -> shrs::anyhow::Result<CmdOutput> {
+        if let Some(output) = sh.builtins.run_line(sh, states, &cmd) {
+            return Ok(output);
+        }
         let lang_ctx = self
             .lang_ctx
             .get_or_init(|| SshLangCtx::init(&self.runtime, &self.remote));
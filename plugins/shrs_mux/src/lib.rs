@@ -145,8 +145,11 @@ impl Lang for MuxLang {
         let Ok(state) = states.try_get::<MuxState>() else {
             return Ok(CmdOutput::error());
         };
+        // builtins like `mux` need to be able to borrow the state
+        let lang = state.current_lang();
+        drop(state);
 
-        state.current_lang().eval(sh, states, cmd)
+        lang.eval(sh, states, cmd)
     }
 
     fn name(&self) -> String {
@@ -160,4 +163,12 @@ impl Lang for MuxLang {
         let lang = mux_state.current_lang();
         lang.needs_line_check(shell, ctx)
     }
+
+    fn input_status(&self, shell: &Shell, ctx: &States, input: &str) -> InputStatus {
+        let Ok(mux_state) = ctx.try_get::<MuxState>() else {
+            return InputStatus::Complete;
+        };
+        let lang = mux_state.current_lang();
+        lang.input_status(shell, ctx, input)
+    }
 }